@@ -4,13 +4,16 @@ use eframe::CreationContext;
 use egui::{Color32, Frame, RichText, TopBottomPanel};
 
 use crate::{
-    view::{setting_view::Settings, BitcoinView},
+    model::Market,
+    view::{setting_view::Settings, BitcoinView, HoldingsView, LedgerView},
     StockTrackerView,
 };
 
 pub struct WealthTracker {
     stock_tracker: StockTrackerView,
     btc_tracker: BitcoinView,
+    holdings: HoldingsView,
+    ledger: LedgerView,
     settings: Settings,
 }
 
@@ -22,10 +25,15 @@ impl WealthTracker {
     pub fn new(cc: &CreationContext) -> Self {
         load_font(&cc.egui_ctx);
 
+        let settings = Settings::load(cc.storage);
+        cc.egui_ctx.set_theme(settings.theme);
+
         Self {
             stock_tracker: StockTrackerView::new(cc),
             btc_tracker: BitcoinView::new(),
-            settings: Settings::default(),
+            holdings: HoldingsView::default(),
+            ledger: LedgerView::new(cc),
+            settings,
         }
     }
 
@@ -60,6 +68,7 @@ impl WealthTracker {
                     );
                     egui::widgets::global_theme_preference_switch(ui);
                     ui.toggle_value(&mut self.settings.open, "💻 Setting");
+                    ui.toggle_value(&mut self.settings.ledger, "🧾 Ledger");
                 });
             });
     }
@@ -67,6 +76,8 @@ impl WealthTracker {
 
 impl eframe::App for WealthTracker {
     fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
+        // the theme switches write straight into the egui options
+        self.settings.theme = ctx.options(|o| o.theme_preference);
         self.render_top_panel(ctx);
         self.setting_pannel(ctx, frame);
         egui::CentralPanel::default()
@@ -74,9 +85,29 @@ impl eframe::App for WealthTracker {
             .show(ctx, |_ui| {
                 // let mut is_open = true;
                 self.stock_tracker.show(ctx, &mut self.settings.zh_share);
+                self.stock_tracker
+                    .show_market(ctx, Market::HkShare, &mut self.settings.hk_share);
+                self.stock_tracker
+                    .show_market(ctx, Market::UsShare, &mut self.settings.us_share);
                 self.btc_tracker.show(ctx, &mut self.settings.btc);
+                let quotes = self.stock_tracker.stocks();
+                if let Some(code) = self.ledger.show(ctx, &mut self.settings.ledger, quotes) {
+                    self.stock_tracker.watch(&code);
+                }
+                self.holdings.show(
+                    ctx,
+                    &mut self.settings.holdings,
+                    self.ledger.portfolio(),
+                    self.stock_tracker.stocks(),
+                );
             });
     }
+
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        self.settings.save(storage);
+        self.stock_tracker.save(storage);
+        self.ledger.save(storage);
+    }
 }
 
 fn load_font(ctx: &egui::Context) {
@@ -0,0 +1,311 @@
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Local};
+use crossbeam::channel::{tick, Receiver, Sender};
+use crossbeam::select;
+
+use crate::{
+    error::TrackerError,
+    model::{crypto::BinanceProvider, stock::KLineScale, CryptoProvider},
+};
+
+use super::{
+    ws::{WsClient, WsMessage},
+    CryptoCommand, Endpoint, FetchError, FetchStatus, TxCryptoData,
+};
+
+const HEARTBEAT: Duration = Duration::from_secs(20);
+const RECONNECT_MIN: Duration = Duration::from_millis(500);
+const RECONNECT_MAX: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone)]
+pub struct CryptoTask {
+    symbols: Vec<String>,
+    kline_scale_map: HashMap<String, KLineScale>,
+    provider: Arc<dyn CryptoProvider>,
+    last_success: HashMap<Endpoint, DateTime<Local>>,
+    rx: Receiver<CryptoCommand>,
+    tx: Sender<TxCryptoData>,
+}
+
+impl CryptoTask {
+    pub fn new(rx: Receiver<CryptoCommand>, tx: Sender<TxCryptoData>, symbols: String) -> Self {
+        Self::with_provider(rx, tx, symbols, Arc::new(BinanceProvider::default()))
+    }
+
+    pub fn with_provider(
+        rx: Receiver<CryptoCommand>,
+        tx: Sender<TxCryptoData>,
+        symbols: String,
+        provider: Arc<dyn CryptoProvider>,
+    ) -> Self {
+        let symbols = symbols
+            .split(",")
+            .map(|x| x.trim().to_uppercase())
+            .filter(|x| !x.is_empty())
+            .collect::<Vec<String>>();
+
+        Self {
+            symbols,
+            rx,
+            tx,
+            provider,
+            kline_scale_map: HashMap::new(),
+            last_success: HashMap::new(),
+        }
+    }
+
+    pub fn run(&mut self) {
+        self.refresh_tickers();
+        self.refresh_klines();
+        // the exchange weights requests, no need to poll as fast as stocks
+        let mut interval = Duration::from_secs(2);
+        if let Some(url) = self.provider.stream_url() {
+            let mut stream = TickerStream::new(
+                url,
+                self.symbols.clone(),
+                self.provider.clone(),
+                self.tx.clone(),
+            );
+            thread::spawn(move || stream.run());
+            // tickers are pushed, polling only backs up the stream
+            interval = Duration::from_secs(60);
+        }
+        let mut ticker = tick(interval);
+        let kline_ticker = tick(Duration::from_secs(60));
+        loop {
+            select! {
+                recv(self.rx) -> msg => {
+                    match msg {
+                        Ok(CryptoCommand::Refresh) => self.refresh_tickers(),
+                        Ok(CryptoCommand::SetInterval(interval)) => {
+                            ticker = tick(Duration::from_millis(interval.into()));
+                        }
+                        Ok(CryptoCommand::CoinKLine(symbol, scale)) => {
+                            self.kline_scale_map.insert(symbol.clone(), scale);
+                            self.refresh_kline(&symbol, scale);
+                        }
+                        // the view is gone
+                        Err(_) => break,
+                    }
+                },
+                recv(ticker) -> _msg => {
+                    self.refresh_tickers();
+                },
+                recv(kline_ticker) -> _msg => {
+                    self.refresh_klines();
+                }
+            }
+        }
+    }
+
+    fn refresh_tickers(&mut self) {
+        if self.symbols.is_empty() {
+            return;
+        }
+        let start = Instant::now();
+        let result = self.provider.fetch_tickers(&self.symbols);
+        let ok = result.is_ok();
+        match result {
+            Ok(coins) => {
+                self.tx.send(TxCryptoData::CoinList(coins)).ok();
+            }
+            Err(e) => self.send_error(Endpoint::Quote, None, e),
+        }
+        self.send_status(Endpoint::Quote, ok, start.elapsed());
+    }
+
+    fn refresh_klines(&mut self) {
+        for symbol in self.symbols.clone() {
+            let scale = self
+                .kline_scale_map
+                .get(&symbol)
+                .copied()
+                .unwrap_or(KLineScale::Munute15);
+            self.refresh_kline(&symbol, scale);
+        }
+    }
+
+    fn refresh_kline(&mut self, symbol: &str, scale: KLineScale) {
+        let start = Instant::now();
+        let result = self.provider.fetch_klines(symbol, scale, 100);
+        let ok = result.is_ok();
+        match result {
+            Ok(mut klines) => {
+                // rows the exchange sent broken, the other candles still show
+                for message in std::mem::take(&mut klines.skipped) {
+                    tracing::warn!("Kline {symbol} skipped row: {message}");
+                    let error = FetchError {
+                        endpoint: Endpoint::Kline,
+                        code: Some(symbol.to_string()),
+                        message,
+                    };
+                    self.tx.send(TxCryptoData::Error(error)).ok();
+                }
+                let data = TxCryptoData::Kline((symbol.to_string(), klines));
+                self.tx.send(data).ok();
+            }
+            Err(e) => self.send_error(Endpoint::Kline, Some(symbol.to_string()), e),
+        }
+        self.send_status(Endpoint::Kline, ok, start.elapsed());
+    }
+
+    fn send_error(&self, endpoint: Endpoint, code: Option<String>, e: TrackerError) {
+        tracing::warn!("crypto {endpoint:?} {code:?} error: {e}");
+        let error = FetchError {
+            endpoint,
+            code,
+            message: e.to_string(),
+        };
+        self.tx.send(TxCryptoData::Error(error)).ok();
+    }
+
+    fn send_status(&mut self, endpoint: Endpoint, ok: bool, latency: Duration) {
+        if ok {
+            self.last_success.insert(endpoint, Local::now());
+        }
+        let status = FetchStatus {
+            endpoint,
+            ok,
+            latency,
+            last_success: self.last_success.get(&endpoint).copied(),
+        };
+        self.tx.send(TxCryptoData::Status(status)).ok();
+    }
+}
+
+/// Tickers pushed over a websocket. Reconnects with backoff and subscribes
+/// again after every connect, stops once the view is gone.
+pub struct TickerStream {
+    url: String,
+    symbols: Vec<String>,
+    provider: Arc<dyn CryptoProvider>,
+    tx: Sender<TxCryptoData>,
+    heartbeat: Duration,
+    last_success: Option<DateTime<Local>>,
+}
+
+impl TickerStream {
+    pub fn new(
+        url: String,
+        symbols: Vec<String>,
+        provider: Arc<dyn CryptoProvider>,
+        tx: Sender<TxCryptoData>,
+    ) -> Self {
+        Self {
+            url,
+            symbols,
+            provider,
+            tx,
+            heartbeat: HEARTBEAT,
+            last_success: None,
+        }
+    }
+
+    /// Ping after this long without a message, reconnect after twice as long.
+    pub fn with_heartbeat(mut self, heartbeat: Duration) -> Self {
+        self.heartbeat = heartbeat;
+        self
+    }
+
+    pub fn run(&mut self) {
+        let mut backoff = RECONNECT_MIN;
+        loop {
+            let start = Instant::now();
+            let mut connected = false;
+            let e = match self.session(&mut connected) {
+                Ok(()) => break,
+                Err(e) => e,
+            };
+            tracing::warn!("crypto stream error: {e}");
+            let error = FetchError {
+                endpoint: Endpoint::Stream,
+                code: None,
+                message: e.to_string(),
+            };
+            if self.tx.send(TxCryptoData::Error(error)).is_err()
+                || !self.send_status(false, start.elapsed())
+            {
+                break;
+            }
+
+            if connected {
+                backoff = RECONNECT_MIN;
+            }
+            thread::sleep(backoff);
+            backoff = (backoff * 2).min(RECONNECT_MAX);
+        }
+    }
+
+    /// One connection, `Ok` when the view is gone.
+    fn session(&mut self, connected: &mut bool) -> Result<(), TrackerError> {
+        let start = Instant::now();
+        let read_timeout = self.heartbeat.min(Duration::from_secs(1)) / 2;
+        let mut ws = WsClient::connect(&self.url, read_timeout)?;
+        ws.send_text(&self.provider.subscribe_message(&self.symbols))?;
+        *connected = true;
+        if !self.send_status(true, start.elapsed()) {
+            ws.close();
+            return Ok(());
+        }
+
+        let mut last_recv = Instant::now();
+        let mut ping_sent: Option<Instant> = None;
+        loop {
+            match ws.read_message()? {
+                Some(WsMessage::Text(text)) => {
+                    last_recv = Instant::now();
+                    match self.provider.decode_stream_message(&text) {
+                        Ok(Some(coin)) => {
+                            if self.tx.send(TxCryptoData::CoinList(vec![coin])).is_err() {
+                                ws.close();
+                                return Ok(());
+                            }
+                        }
+                        Ok(None) => {}
+                        Err(e) => tracing::warn!("crypto stream message: {e}"),
+                    }
+                }
+                Some(WsMessage::Pong) => {
+                    last_recv = Instant::now();
+                    if let Some(sent) = ping_sent.take() {
+                        if !self.send_status(true, sent.elapsed()) {
+                            ws.close();
+                            return Ok(());
+                        }
+                    }
+                }
+                None => {
+                    let idle = last_recv.elapsed();
+                    if idle > self.heartbeat * 2 {
+                        ws.close();
+                        return Err(TrackerError::WebSocket("heartbeat timeout".into()));
+                    }
+                    if idle > self.heartbeat && ping_sent.is_none() {
+                        ws.send_ping()?;
+                        ping_sent = Some(Instant::now());
+                    }
+                }
+            }
+        }
+    }
+
+    /// `false` once the view is gone.
+    fn send_status(&mut self, ok: bool, latency: Duration) -> bool {
+        if ok {
+            self.last_success = Some(Local::now());
+        }
+        let status = FetchStatus {
+            endpoint: Endpoint::Stream,
+            ok,
+            latency,
+            last_success: self.last_success,
+        };
+        self.tx.send(TxCryptoData::Status(status)).is_ok()
+    }
+}
@@ -1,6 +1,14 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
 use crate::model::{
+    crypto::Coin,
+    kline_store::CacheEntry,
     stock::{KLineScale, Klines},
-    Stock,
+    timeshare::TimeShare,
+    Stock, Symbol,
 };
 
 #[derive(Debug)]
@@ -9,14 +17,97 @@ pub enum StockCammnd {
     SetInterval(u32),
     StockAdd(String),
     StockDel(String),
+    /// Move a watched code to an index of the list.
+    StockMove(String, usize),
     StockKLine(String, KLineScale),
+    /// Older candles than the given number already held.
+    KLineHistory(String, KLineScale, usize),
+    /// Today's minute line of a code.
+    TimeShare(String),
+    /// Ask the source for symbols matching a keyword.
+    Search(String),
+    /// List the klines cached on disk.
+    CacheInspect,
+    /// Drop the cached klines of a code, or all of them.
+    CacheClear(Option<String>),
 }
 
 // send data to view
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub enum TxStockData {
-    Stock(Stock),
+    Stock(Box<Stock>),
     StockList(Vec<Stock>),
     Kline((String, Klines)),
+    /// A longer history, to merge before the held candles.
+    KlineHistory((String, Klines)),
+    TimeShare((String, TimeShare)),
+    Error(FetchError),
+    Status(FetchStatus),
+    Watch(WatchAck),
+    /// Search results for the keyword.
+    Symbols(String, Vec<Symbol>),
+    /// Klines on disk, after an inspect or a clear.
+    Cache(Vec<CacheEntry>),
+}
+
+/// Reply to a watchlist command.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum WatchAck {
+    Added(String),
+    Rejected {
+        code: String,
+        reason: String,
+    },
+    Removed(String),
+    /// Watchlist after a move.
+    Order(Vec<String>),
+}
+
+#[derive(Debug)]
+pub enum CryptoCommand {
+    Refresh,
+    SetInterval(u32),
+    CoinKLine(String, KLineScale),
+}
+
+#[derive(Debug)]
+pub enum TxCryptoData {
+    CoinList(Vec<Coin>),
+    Kline((String, Klines)),
+    Error(FetchError),
+    Status(FetchStatus),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Endpoint {
+    Quote,
+    Kline,
+    /// A-share minute lines, only asked for the time-share chart.
+    Minutes,
+    /// Pushed crypto tickers.
+    Stream,
+    /// Symbol suggestions, only asked while typing.
+    Search,
+    /// Klines cached on disk.
+    Cache,
+    /// A recorded session played back.
+    Replay,
+}
+
+/// A failed request, or a single bad row when `code` is set.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FetchError {
+    pub endpoint: Endpoint,
+    pub code: Option<String>,
+    pub message: String,
+}
+
+/// Health of an endpoint after each request.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FetchStatus {
+    pub endpoint: Endpoint,
+    pub ok: bool,
+    pub latency: Duration,
+    pub last_success: Option<DateTime<Local>>,
 }
@@ -1,45 +1,116 @@
 // excute task
 
+pub mod crypto;
 pub mod message;
-use std::{collections::HashMap, time::Duration};
+pub mod recorder;
+pub mod ws;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
 
-use crossbeam::channel::{tick, Receiver, Sender};
+use chrono::{DateTime, Local, Utc};
+use crossbeam::channel::{tick, unbounded, Receiver, Sender};
 use crossbeam::select;
+pub use crypto::{CryptoTask, TickerStream};
 pub use message::*;
 
-use crate::model::{
-    stock::{self, KLineScale, Klines},
-    Stock,
+use crate::{
+    error::TrackerError,
+    model::{
+        calendar::Calendar,
+        kline_store::KlineStore,
+        stock::{KLineScale, Klines, SinaProvider, KLINE_PAGE, MAX_KLINES},
+        valid_code, Market, QuoteProvider, Stock, Symbol,
+    },
 };
 
+// answers of requests run beside the loop, so they don't hold up the refresh
+#[derive(Debug)]
+enum Done {
+    Probed(String, Option<Result<Box<Stock>, TrackerError>>),
+    Searched(String, Result<Vec<Symbol>, TrackerError>),
+}
+
 #[derive(Debug, Clone)]
 pub struct StockTask {
     stock_codes: Vec<String>,
     kline_scale_map: HashMap<String, KLineScale>,
+    provider: Arc<dyn QuoteProvider>,
+    // klines served from disk, only the tail is fetched
+    store: Option<KlineStore>,
+    last_success: HashMap<Endpoint, DateTime<Local>>,
+    calendar: Calendar,
+    now: fn() -> DateTime<Utc>,
+    last_refresh: Option<Instant>,
+    // codes being probed before they are watched
+    probing: HashSet<String>,
+    done: (Sender<Done>, Receiver<Done>),
     rx: Receiver<StockCammnd>,
     tx: Sender<TxStockData>,
 }
 
+// polling while every watched market is closed
+pub const IDLE_INTERVAL: Duration = Duration::from_secs(60);
+
 impl StockTask {
     pub fn new(rx: Receiver<StockCammnd>, tx: Sender<TxStockData>, codes: String) -> Self {
+        Self::with_provider(rx, tx, codes, Arc::new(SinaProvider::default()))
+    }
+
+    pub fn with_provider(
+        rx: Receiver<StockCammnd>,
+        tx: Sender<TxStockData>,
+        codes: String,
+        provider: Arc<dyn QuoteProvider>,
+    ) -> Self {
         let stock_codes = codes
             .split(",")
             .map(|x| x.to_string())
             .collect::<Vec<String>>();
 
         Self {
-            stock_codes: stock_codes,
-            rx: rx,
-            tx: tx,
+            stock_codes,
+            rx,
+            tx,
+            provider,
+            store: None,
             kline_scale_map: HashMap::new(),
+            last_success: HashMap::new(),
+            calendar: Calendar::default(),
+            now: Utc::now,
+            last_refresh: None,
+            probing: HashSet::new(),
+            done: unbounded(),
         }
     }
 
+    /// Clock deciding whether markets are open, for tests.
+    pub fn with_clock(mut self, now: fn() -> DateTime<Utc>) -> Self {
+        self.now = now;
+        self
+    }
+
+    /// Sessions deciding how often to poll, the bundled holidays otherwise.
+    pub fn with_calendar(mut self, calendar: Calendar) -> Self {
+        self.calendar = calendar;
+        self
+    }
+
+    /// Keeps klines on disk between runs.
+    pub fn with_store(mut self, store: KlineStore) -> Self {
+        self.store = Some(store);
+        self
+    }
+
     pub fn run(&mut self) {
         self.refresh_data();
         self.refresh_klines();
         let mut ticker = tick(Duration::from_millis(200));
         let kline_ticker = tick(Duration::from_secs(60));
+        let done = self.done.1.clone();
         loop {
             select! {
                 recv(self.rx) -> msg => {
@@ -50,89 +121,325 @@ impl StockTask {
                                     self.refresh_data();
                                 },
                                 StockCammnd::SetInterval(interval) => {
-                                    ticker = tick(Duration::from_secs(interval.into()));
+                                    ticker = tick(Duration::from_millis(interval.into()));
                                 },
                                 StockCammnd::StockAdd(code) => {
                                     self.add_code(code);
                                 },
                                 StockCammnd::StockDel(code) => {
                                     self.remove_code(code.as_str());
-                                    //
+                                },
+                                StockCammnd::StockMove(code, index) => {
+                                    self.move_code(&code, index);
                                 },
                                 StockCammnd::StockKLine(code, scale) => {
-                                    let scale_int = scale.to_usize();
                                     self.kline_scale_map.insert(code.clone(), scale);
-                                    // get kline
-                                    let _ = match Klines::get_klines(&code, scale_int, 100) {
-                                        Ok(klines) => {
-                                            self.tx.send(TxStockData::Kline((code,klines))).ok();
-                                        } ,
-                                        Err(e) => {
-                                            println!("kline error {}",e);
-                                        },
-                                    };
-
+                                    self.refresh_kline(&code, scale);
+                                },
+                                StockCammnd::KLineHistory(code, scale, held) => {
+                                    self.fetch_history(&code, scale, held);
+                                },
+                                StockCammnd::TimeShare(code) => {
+                                    self.fetch_minutes(&code);
+                                },
+                                StockCammnd::Search(keyword) => {
+                                    self.search(keyword);
+                                },
+                                StockCammnd::CacheInspect => {
+                                    self.inspect_cache();
+                                },
+                                StockCammnd::CacheClear(code) => {
+                                    self.clear_cache(code.as_deref());
                                 },
                             }
                         },
-                        Err(e) => {
-
-                        },
+                        // the view is gone
+                        Err(_) => break,
                     }
                 }  ,
+                recv(done) -> msg => {
+                    match msg {
+                        Ok(Done::Probed(code, row)) => self.probed(code, row),
+                        Ok(Done::Searched(keyword, result)) => self.searched(keyword, result),
+                        Err(_) => {}
+                    }
+                },
                 recv(ticker)->_msg =>{
-                    self.refresh_data();
+                    // nights, weekends and holidays only poll now and then
+                    let idle = self.last_refresh.is_some_and(|t| t.elapsed() < IDLE_INTERVAL);
+                    if self.is_live() || !idle {
+                        self.refresh_data();
+                    }
                 },
                 recv(kline_ticker) -> _msg => {
-                    self.refresh_klines();
+                    if self.is_live() {
+                        self.refresh_klines();
+                    }
                 }
             }
         }
     }
 
-    fn refresh_data(&self) {
+    /// Any watched market is trading.
+    fn is_live(&self) -> bool {
+        let now = (self.now)();
+        [Market::ZhShare, Market::HkShare, Market::UsShare]
+            .into_iter()
+            .filter(|m| self.stock_codes.iter().any(|x| Market::from_code(x) == *m))
+            .any(|m| self.calendar.session(m, now).is_live())
+    }
+
+    fn refresh_data(&mut self) {
+        self.last_refresh = Some(Instant::now());
         if !self.stock_codes.is_empty() {
-            match stock::fetch_data_list(self.stock_codes.clone()) {
-                Ok(v) => {
-                    // refresh data
-                    self.tx.send(TxStockData::StockList(v)).ok();
+            let start = Instant::now();
+            let result = self.provider.fetch_quotes(&self.stock_codes);
+            let ok = result.is_ok();
+            match result {
+                Ok(rows) => {
+                    // skip bad rows, the rest still refresh
+                    let mut stocks = vec![];
+                    for row in rows {
+                        match row {
+                            Ok(stock) => stocks.push(stock),
+                            Err(e) => {
+                                let code = e.code().map(str::to_string);
+                                self.send_error(Endpoint::Quote, code, e);
+                            }
+                        }
+                    }
+                    self.tx.send(TxStockData::StockList(stocks)).ok();
                 }
-                Err(_) => {}
+                Err(e) => self.send_error(Endpoint::Quote, None, e),
             }
+            self.send_status(Endpoint::Quote, ok, start.elapsed());
         }
     }
 
-    fn refresh_klines(&self) {
-        if !self.stock_codes.is_empty() {
-            self.stock_codes.iter().for_each(|code| {
-                // fetch kline data
-                let mut s = Stock::default();
-                s.code = code.clone();
-                let scale = self
-                    .kline_scale_map
-                    .get(code)
-                    .unwrap_or(&KLineScale::Munute15);
-                match Klines::get_klines(code, scale.to_usize(), 100) {
-                    Ok(klines) => {
-                        let data = TxStockData::Kline((code.clone(), klines));
-                        self.tx.send(data).ok()
+    fn refresh_klines(&mut self) {
+        for code in self.stock_codes.clone() {
+            let scale = self
+                .kline_scale_map
+                .get(&code)
+                .copied()
+                .unwrap_or_else(|| KLineScale::default_for(Market::from_code(&code)));
+            self.refresh_kline(&code, scale);
+        }
+    }
+
+    fn refresh_kline(&mut self, code: &str, scale: KLineScale) {
+        let start = Instant::now();
+        let result = match &mut self.store {
+            Some(store) => store.sync(self.provider.as_ref(), code, scale),
+            None => self.provider.fetch_klines(code, scale, KLINE_PAGE),
+        };
+        let ok = result.is_ok();
+        match result {
+            Ok(mut klines) => {
+                self.report_skipped(code, &mut klines);
+                let data = TxStockData::Kline((code.to_string(), klines));
+                self.tx.send(data).ok();
+            }
+            Err(e) => {
+                self.send_error(Endpoint::Kline, Some(code.to_string()), e);
+                // the history on disk still shows while the source is down
+                if let Some(store) = &mut self.store {
+                    let cached = store.get(code, scale);
+                    if !cached.klines.is_empty() {
+                        let data = TxStockData::Kline((code.to_string(), cached.clone()));
+                        self.tx.send(data).ok();
                     }
-                    Err(_) => todo!(),
-                };
-            });
-            //x
+                }
+            }
         }
+        self.send_status(Endpoint::Kline, ok, start.elapsed());
     }
 
-    fn add_code(&mut self, code: String) {
-        // check code illege
+    // the source only serves the latest `datalen` candles, there is no end date
+    // or offset to page by, so every page downloads the held candles again.
+    // Asking for at least twice as many reaches MAX_KLINES in a few requests.
+    fn fetch_history(&mut self, code: &str, scale: KLineScale, held: usize) {
+        let datalen = (held * 2).max(held + KLINE_PAGE as usize).min(MAX_KLINES) as u32;
+        let start = Instant::now();
+        let result = self.provider.fetch_klines(code, scale, datalen);
+        let ok = result.is_ok();
+        match result {
+            Ok(mut klines) => {
+                self.report_skipped(code, &mut klines);
+                if let Some(store) = &mut self.store {
+                    if let Err(e) = store.prepend(code, klines.clone()) {
+                        tracing::warn!("kline cache {code} {scale:?}: {e}");
+                    }
+                }
+                let data = TxStockData::KlineHistory((code.to_string(), klines));
+                self.tx.send(data).ok();
+            }
+            Err(e) => self.send_error(Endpoint::Kline, Some(code.to_string()), e),
+        }
+        self.send_status(Endpoint::Kline, ok, start.elapsed());
+    }
 
+    fn fetch_minutes(&mut self, code: &str) {
+        let start = Instant::now();
+        let result = self.provider.fetch_minutes(code);
+        let ok = result.is_ok();
+        match result {
+            Ok(minutes) => {
+                let data = TxStockData::TimeShare((code.to_string(), minutes));
+                self.tx.send(data).ok();
+            }
+            Err(e) => self.send_error(Endpoint::Minutes, Some(code.to_string()), e),
+        }
+        self.send_status(Endpoint::Minutes, ok, start.elapsed());
+    }
+
+    fn search(&self, keyword: String) {
+        let provider = self.provider.clone();
+        let done = self.done.0.clone();
+        thread::spawn(move || {
+            let result = provider.search_symbol(&keyword);
+            done.send(Done::Searched(keyword, result)).ok();
+        });
+    }
+
+    fn searched(&self, keyword: String, result: Result<Vec<Symbol>, TrackerError>) {
+        match result {
+            Ok(symbols) => {
+                self.tx.send(TxStockData::Symbols(keyword, symbols)).ok();
+            }
+            Err(e) => self.send_error(Endpoint::Search, None, e),
+        }
+    }
+
+    fn inspect_cache(&self) {
+        let entries = match &self.store {
+            Some(store) => store.entries(),
+            None => Ok(vec![]),
+        };
+        match entries {
+            Ok(entries) => {
+                self.tx.send(TxStockData::Cache(entries)).ok();
+            }
+            Err(e) => self.send_error(Endpoint::Cache, None, e),
+        }
+    }
+
+    fn clear_cache(&mut self, code: Option<&str>) {
+        if let Some(store) = &mut self.store {
+            match store.clear(code) {
+                Ok(removed) => tracing::info!("cleared {removed} kline cache files"),
+                Err(e) => self.send_error(Endpoint::Cache, None, e),
+            }
+        }
+        self.inspect_cache();
+    }
+
+    fn send_error(&self, endpoint: Endpoint, code: Option<String>, e: TrackerError) {
+        tracing::warn!("{endpoint:?} {code:?} error: {e}");
+        let error = FetchError {
+            endpoint,
+            code,
+            message: e.to_string(),
+        };
+        self.tx.send(TxStockData::Error(error)).ok();
+    }
+
+    // rows the source sent broken, the other candles still show
+    fn report_skipped(&self, code: &str, klines: &mut Klines) {
+        for message in std::mem::take(&mut klines.skipped) {
+            tracing::warn!("Kline {code} skipped row: {message}");
+            let error = FetchError {
+                endpoint: Endpoint::Kline,
+                code: Some(code.to_string()),
+                message,
+            };
+            self.tx.send(TxStockData::Error(error)).ok();
+        }
+    }
+
+    fn send_status(&mut self, endpoint: Endpoint, ok: bool, latency: Duration) {
+        if ok {
+            self.last_success.insert(endpoint, Local::now());
+        }
+        let status = FetchStatus {
+            endpoint,
+            ok,
+            latency,
+            last_success: self.last_success.get(&endpoint).copied(),
+        };
+        self.tx.send(TxStockData::Status(status)).ok();
+    }
+
+    fn add_code(&mut self, code: String) {
         if self.stock_codes.contains(&code) {
-            self.stock_codes.push(code);
+            self.send_ack(WatchAck::Added(code));
+            return;
+        }
+        if !valid_code(&code) {
+            self.reject(code, "invalid code".to_string());
+            return;
+        }
+
+        if !self.probing.insert(code.clone()) {
+            return;
+        }
+
+        // probe alone first, a bad code would otherwise fail every batch
+        let provider = self.provider.clone();
+        let done = self.done.0.clone();
+        thread::spawn(move || {
+            let row = match provider.fetch_quotes(std::slice::from_ref(&code)) {
+                Ok(rows) => rows.into_iter().next().map(|x| x.map(Box::new)),
+                Err(e) => Some(Err(e)),
+            };
+            done.send(Done::Probed(code, row)).ok();
+        });
+    }
+
+    fn probed(&mut self, code: String, row: Option<Result<Box<Stock>, TrackerError>>) {
+        // removed while probing
+        if !self.probing.remove(&code) {
+            return;
+        }
+        match row {
+            Some(Ok(stock)) => {
+                self.stock_codes.push(code.clone());
+                self.send_ack(WatchAck::Added(code.clone()));
+                self.tx.send(TxStockData::Stock(stock)).ok();
+                let scale = self
+                    .kline_scale_map
+                    .get(&code)
+                    .copied()
+                    .unwrap_or_else(|| KLineScale::default_for(Market::from_code(&code)));
+                self.refresh_kline(&code, scale);
+            }
+            Some(Err(e)) => self.reject(code, e.to_string()),
+            None => self.reject(code, "no quote".to_string()),
         }
     }
 
     fn remove_code(&mut self, code: &str) {
+        self.probing.remove(code);
         self.stock_codes.retain(|x| x != code);
+        self.kline_scale_map.remove(code);
+        self.send_ack(WatchAck::Removed(code.to_string()));
+    }
+
+    fn move_code(&mut self, code: &str, index: usize) {
+        if let Some(from) = self.stock_codes.iter().position(|x| x == code) {
+            let code = self.stock_codes.remove(from);
+            let index = index.min(self.stock_codes.len());
+            self.stock_codes.insert(index, code);
+        }
+        self.send_ack(WatchAck::Order(self.stock_codes.clone()));
+    }
+
+    fn reject(&self, code: String, reason: String) {
+        tracing::warn!("rejected {code}: {reason}");
+        self.send_ack(WatchAck::Rejected { code, reason });
+    }
+
+    fn send_ack(&self, ack: WatchAck) {
+        self.tx.send(TxStockData::Watch(ack)).ok();
     }
 }
@@ -0,0 +1,222 @@
+use std::{
+    fs::{self, File},
+    io::{BufRead, BufReader, BufWriter, ErrorKind, Write},
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+
+use chrono::{DateTime, Local};
+use crossbeam::channel::Sender;
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use serde::{Deserialize, Serialize};
+
+use crate::error::TrackerError;
+
+use super::TxStockData;
+
+const EXTENSION: &str = "jsonl.gz";
+
+/// A message as the backend sent it.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Record {
+    pub at: DateTime<Local>,
+    pub data: TxStockData,
+}
+
+/// Writes messages to a gzipped json lines file, one record a line.
+pub struct Recorder {
+    path: PathBuf,
+    writer: GzEncoder<BufWriter<File>>,
+}
+
+impl Recorder {
+    /// A new recording in `dir`, named after the time it starts.
+    pub fn create(dir: &Path) -> Result<Self, TrackerError> {
+        fs::create_dir_all(dir)?;
+        let name = Local::now().format("%Y%m%d-%H%M%S");
+        let path = dir.join(format!("{name}.{EXTENSION}"));
+        let file = BufWriter::new(File::create(&path)?);
+        Ok(Self {
+            path,
+            writer: GzEncoder::new(file, Compression::default()),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn record(&mut self, data: &TxStockData) -> Result<(), TrackerError> {
+        #[derive(Serialize)]
+        struct RecordRef<'a> {
+            at: DateTime<Local>,
+            data: &'a TxStockData,
+        }
+        let record = RecordRef {
+            at: Local::now(),
+            data,
+        };
+        serde_json::to_writer(&mut self.writer, &record)?;
+        self.writer.write_all(b"\n")?;
+        Ok(())
+    }
+
+    /// Writes the gzip trailer, a file without it is cut short on replay.
+    pub fn finish(self) -> Result<PathBuf, TrackerError> {
+        self.writer.finish()?.flush()?;
+        Ok(self.path)
+    }
+}
+
+/// Recordings in `dir`, the latest first.
+pub fn recordings(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return vec![];
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|x| x.ok().map(|x| x.path()))
+        .filter(|x| x.to_string_lossy().ends_with(EXTENSION))
+        .collect();
+    paths.sort_by(|a, b| b.cmp(a));
+    paths
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ReplaySpeed {
+    #[default]
+    Real,
+    Fast,
+    /// No waiting at all.
+    Max,
+}
+
+impl ReplaySpeed {
+    pub const ALL: [ReplaySpeed; 3] = [ReplaySpeed::Real, ReplaySpeed::Fast, ReplaySpeed::Max];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ReplaySpeed::Real => "1x",
+            ReplaySpeed::Fast => "10x",
+            ReplaySpeed::Max => "max",
+        }
+    }
+
+    fn wait(&self, gap: Duration) -> Duration {
+        match self {
+            ReplaySpeed::Real => gap,
+            ReplaySpeed::Fast => gap / 10,
+            ReplaySpeed::Max => Duration::ZERO,
+        }
+    }
+}
+
+/// Sends the records of `path` to `tx` keeping their pace, until the file
+/// ends or nobody listens. Returns how many were sent.
+pub fn replay(
+    path: &Path,
+    tx: &Sender<TxStockData>,
+    speed: ReplaySpeed,
+) -> Result<usize, TrackerError> {
+    let reader = BufReader::new(GzDecoder::new(File::open(path)?));
+    let mut last: Option<DateTime<Local>> = None;
+    let mut sent = 0;
+    for line in reader.lines() {
+        // a recording cut short by a crash plays up to where it stops
+        let record: Record = match line.map(|x| serde_json::from_str(&x)) {
+            Ok(Ok(record)) => record,
+            Ok(Err(e)) if e.is_eof() => break,
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => break,
+            Ok(Err(e)) => return Err(e.into()),
+            Err(e) => return Err(e.into()),
+        };
+        if let Some(last) = last {
+            let gap = (record.at - last).to_std().unwrap_or_default();
+            thread::sleep(speed.wait(gap));
+        }
+        last = Some(record.at);
+        if tx.send(record.data).is_err() {
+            break;
+        }
+        sent += 1;
+    }
+    Ok(sent)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        backend::WatchAck,
+        model::{stock::KLineScale, Stock},
+        test_util::temp_dir,
+    };
+
+    fn messages() -> Vec<TxStockData> {
+        let stock = Stock {
+            name: "赛力斯".into(),
+            code: "sh601127".into(),
+            data: crate::model::StockData {
+                new: "132.79".parse().unwrap(),
+                vol: 22615984,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        vec![
+            TxStockData::StockList(vec![stock]),
+            TxStockData::Watch(WatchAck::Added("sh601127".into())),
+            TxStockData::Kline((
+                "sh601127".into(),
+                crate::model::stock::Klines {
+                    scale: KLineScale::Day,
+                    klines: vec![Default::default()],
+                    ..Default::default()
+                },
+            )),
+        ]
+    }
+
+    #[test]
+    fn test_record_and_replay() {
+        let dir = temp_dir("record");
+        let mut recorder = Recorder::create(&dir).unwrap();
+        for data in messages() {
+            recorder.record(&data).unwrap();
+        }
+        let path = recorder.finish().unwrap();
+        assert_eq!(recordings(&dir), vec![path.clone()]);
+
+        let (tx, rx) = crossbeam::channel::unbounded();
+        assert_eq!(replay(&path, &tx, ReplaySpeed::Max).unwrap(), 3);
+        let replayed: Vec<TxStockData> = rx.try_iter().collect();
+        assert!(matches!(&replayed[0], TxStockData::StockList(x)
+            if x[0].code == "sh601127" && x[0].data.new.to_string() == "132.79"));
+        assert!(matches!(&replayed[1], TxStockData::Watch(WatchAck::Added(x)) if x == "sh601127"));
+        assert!(matches!(&replayed[2], TxStockData::Kline((_, x)) if x.scale == KLineScale::Day));
+
+        // a crash leaves no trailer and maybe half a block
+        let bytes = fs::read(&path).unwrap();
+        let cut = dir.join("cut.jsonl.gz");
+        fs::write(&cut, &bytes[..bytes.len() - 12]).unwrap();
+        let (cut_tx, cut_rx) = crossbeam::channel::unbounded();
+        // the last record lost its tail, the whole ones before it play
+        assert_eq!(replay(&cut, &cut_tx, ReplaySpeed::Max).unwrap(), 2);
+        let replayed: Vec<TxStockData> = cut_rx.try_iter().collect();
+        assert_eq!(replayed.len(), 2);
+        assert!(matches!(&replayed[1], TxStockData::Watch(WatchAck::Added(x)) if x == "sh601127"));
+
+        // nobody listening ends the replay
+        drop(rx);
+        assert_eq!(replay(&path, &tx, ReplaySpeed::Max).unwrap(), 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_replay_speed() {
+        let gap = Duration::from_secs(10);
+        assert_eq!(ReplaySpeed::Real.wait(gap), gap);
+        assert_eq!(ReplaySpeed::Fast.wait(gap), Duration::from_secs(1));
+        assert_eq!(ReplaySpeed::Max.wait(gap), Duration::ZERO);
+    }
+}
@@ -0,0 +1,118 @@
+// websocket client on tungstenite, text frames only
+
+use std::{io, net::TcpStream, time::Duration};
+
+use reqwest::Url;
+use tungstenite::{client_tls, stream::MaybeTlsStream, Message, WebSocket};
+
+use crate::error::TrackerError;
+
+// the handshake may take longer than a read
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum WsMessage {
+    Text(String),
+    Pong,
+}
+
+pub struct WsClient {
+    socket: WebSocket<MaybeTlsStream<TcpStream>>,
+}
+
+impl WsClient {
+    /// Connects to a `ws://` or `wss://` url. Reads wait at most `timeout`
+    /// for the next message.
+    pub fn connect(url: &str, timeout: Duration) -> Result<Self, TrackerError> {
+        let parsed = Url::parse(url).map_err(|e| TrackerError::WebSocket(e.to_string()))?;
+        let host = parsed
+            .host_str()
+            .ok_or_else(|| TrackerError::WebSocket(format!("no host in {url}")))?;
+        let port = parsed
+            .port_or_known_default()
+            .ok_or_else(|| TrackerError::WebSocket(format!("bad scheme {}", parsed.scheme())))?;
+
+        let tcp = TcpStream::connect((host, port))?;
+        tcp.set_read_timeout(Some(CONNECT_TIMEOUT))?;
+        tcp.set_nodelay(true)?;
+        let (socket, _response) = client_tls(url, tcp).map_err(|e| match e {
+            tungstenite::HandshakeError::Failure(e) => ws_error(e),
+            tungstenite::HandshakeError::Interrupted(_) => {
+                TrackerError::WebSocket("handshake timed out".into())
+            }
+        })?;
+        tcp_stream(&socket)
+            .ok_or_else(|| TrackerError::WebSocket("unknown stream".into()))?
+            .set_read_timeout(Some(timeout))?;
+        Ok(Self { socket })
+    }
+
+    pub fn send_text(&mut self, text: &str) -> Result<(), TrackerError> {
+        self.socket.send(Message::text(text)).map_err(ws_error)
+    }
+
+    pub fn send_ping(&mut self) -> Result<(), TrackerError> {
+        self.socket
+            .send(Message::Ping(Default::default()))
+            .map_err(ws_error)
+    }
+
+    pub fn close(&mut self) {
+        let _ = self.socket.close(None);
+        let _ = self.socket.flush();
+        if let Some(tcp) = tcp_stream(&self.socket) {
+            let _ = tcp.shutdown(std::net::Shutdown::Both);
+        }
+    }
+
+    /// Next message, `None` when nothing arrived within the read timeout.
+    /// Pings are answered by tungstenite.
+    pub fn read_message(&mut self) -> Result<Option<WsMessage>, TrackerError> {
+        loop {
+            let message = match self.socket.read() {
+                Ok(message) => message,
+                Err(tungstenite::Error::Io(e)) if is_timeout(&e) => return Ok(None),
+                Err(e) => return Err(ws_error(e)),
+            };
+            match message {
+                Message::Text(text) => return Ok(Some(WsMessage::Text(text.to_string()))),
+                Message::Binary(data) => {
+                    let text = String::from_utf8(data.to_vec())
+                        .map_err(|e| TrackerError::WebSocket(e.to_string()))?;
+                    return Ok(Some(WsMessage::Text(text)));
+                }
+                Message::Pong(_) => return Ok(Some(WsMessage::Pong)),
+                Message::Close(_) => {
+                    return Err(TrackerError::WebSocket("closed by server".into()))
+                }
+                Message::Ping(_) | Message::Frame(_) => {}
+            }
+        }
+    }
+}
+
+fn tcp_stream(socket: &WebSocket<MaybeTlsStream<TcpStream>>) -> Option<&TcpStream> {
+    match socket.get_ref() {
+        MaybeTlsStream::NativeTls(stream) => Some(stream.get_ref()),
+        MaybeTlsStream::Plain(stream) => Some(stream),
+        // only native-tls is enabled
+        _ => None,
+    }
+}
+
+fn is_timeout(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+    )
+}
+
+fn ws_error(e: tungstenite::Error) -> TrackerError {
+    match e {
+        tungstenite::Error::Io(e) => e.into(),
+        tungstenite::Error::ConnectionClosed | tungstenite::Error::AlreadyClosed => {
+            TrackerError::WebSocket("connection closed".into())
+        }
+        e => TrackerError::WebSocket(e.to_string()),
+    }
+}
@@ -1,7 +1,62 @@
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
 pub enum TrackerError {
     #[error("request http error")]
     HttpError(#[from] reqwest::Error),
+    #[error("failed to parse `{field}` from: {line}")]
+    ParseError { field: &'static str, line: String },
+    #[error("empty quote for {0}")]
+    EmptyQuote(String),
+    #[error("{code}: {source}")]
+    BadQuote {
+        code: String,
+        source: Box<TrackerError>,
+    },
+    #[error("no {scale} klines for {code}")]
+    UnsupportedScale { code: String, scale: &'static str },
+    #[error("unknown quote format: {0}")]
+    UnknownFormat(String),
+    #[error("io error")]
+    IoError(#[from] std::io::Error),
+    #[error("json error")]
+    JsonError(#[from] serde_json::Error),
+    #[error("websocket error: {0}")]
+    WebSocket(String),
+}
+
+impl TrackerError {
+    /// The stock a quote row failed for.
+    pub fn code(&self) -> Option<&str> {
+        match self {
+            TrackerError::EmptyQuote(code) | TrackerError::BadQuote { code, .. } => Some(code),
+            _ => None,
+        }
+    }
+}
+
+/// A transaction the ledger refuses.
+#[derive(Debug, Error, PartialEq)]
+pub enum LedgerError {
+    #[error("{date} is before the last transaction on {last}")]
+    OutOfOrder { date: NaiveDate, last: NaiveDate },
+    #[error("selling {qty} {code} in {account}, only {held} held")]
+    Oversell {
+        code: String,
+        account: String,
+        held: Decimal,
+        qty: Decimal,
+    },
+    #[error("no lot of {code} opened on {date} in {account}")]
+    NoLot {
+        code: String,
+        account: String,
+        date: NaiveDate,
+    },
+    #[error("no {code} held in {account}")]
+    NoPosition { code: String, account: String },
+    #[error("invalid {0}")]
+    Invalid(&'static str),
 }
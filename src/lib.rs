@@ -4,4 +4,7 @@ pub mod error;
 pub mod model;
 pub mod view;
 
+#[cfg(test)]
+mod test_util;
+
 pub use view::StockTrackerView;
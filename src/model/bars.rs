@@ -0,0 +1,353 @@
+use chrono::{
+    Datelike, Duration, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Timelike,
+};
+
+use super::{
+    calendar::us_eastern_offset,
+    price::to_f64,
+    stock::{KLineScale, KlineItem, Klines},
+    Market, StockData,
+};
+
+/// Regular sessions in local minutes after midnight.
+fn sessions(market: Market) -> &'static [(u32, u32)] {
+    match market {
+        Market::ZhShare => &[(9 * 60 + 30, 11 * 60 + 30), (13 * 60, 15 * 60)],
+        Market::HkShare => &[(9 * 60 + 30, 12 * 60), (13 * 60, 16 * 60)],
+        Market::UsShare => &[(9 * 60 + 30, 16 * 60)],
+    }
+}
+
+/// A trade between two quotes, in market local time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tick {
+    pub at: NaiveDateTime,
+    pub price: f64,
+    /// Traded since the previous quote.
+    pub volume: f64,
+    pub amount: f64,
+}
+
+impl Tick {
+    /// The trade from `prev` to `data`, none without a price or a time.
+    pub fn between(market: Market, prev: &StockData, data: &StockData) -> Option<Tick> {
+        let price = to_f64(data.new);
+        if price <= 0.0 {
+            return None;
+        }
+        let at = quote_time(market, data)?;
+        // totals restart with the day
+        let (volume, amount) = if quote_time(market, prev).is_some_and(|x| x.date() == at.date()) {
+            (
+                data.vol.saturating_sub(prev.vol) as f64,
+                (to_f64(data.amount) - to_f64(prev.amount)).max(0.0),
+            )
+        } else {
+            (data.vol as f64, to_f64(data.amount))
+        };
+        Some(Tick {
+            at,
+            price,
+            volume,
+            amount,
+        })
+    }
+}
+
+// us quotes carry beijing time, their klines new york time
+fn quote_time(market: Market, data: &StockData) -> Option<NaiveDateTime> {
+    let date = NaiveDate::parse_from_str(&data.date, "%Y-%m-%d").ok()?;
+    let time = NaiveTime::parse_from_str(&data.time, "%H:%M:%S")
+        .or_else(|_| NaiveTime::parse_from_str(&data.time, "%H:%M"))
+        .ok()?;
+    let at = date.and_time(time);
+    match market {
+        Market::UsShare => {
+            let utc = FixedOffset::east_opt(8 * 3600)?
+                .from_local_datetime(&at)
+                .single()?
+                .to_utc();
+            Some(utc.with_timezone(&us_eastern_offset(utc)).naive_local())
+        }
+        _ => Some(at),
+    }
+}
+
+/// The candle a trade at `at` belongs to, labelled by its close like the
+/// source does. None before the open.
+pub fn bar_day(market: Market, scale: KLineScale, at: NaiveDateTime) -> Option<NaiveDateTime> {
+    let minute = trading_minute(market, at.time())?;
+    let day = match scale {
+        KLineScale::Day | KLineScale::Week | KLineScale::Month => at.date().into(),
+        _ => {
+            let len = scale.to_usize() as u32;
+            // the opening trade falls in the first bar
+            let end = minute.max(1).div_ceil(len) * len;
+            at.date().and_time(NaiveTime::MIN)
+                + Duration::minutes(clock_minute(market, end.min(session_length(market))) as i64)
+        }
+    };
+    Some(day)
+}
+
+/// Minutes of regular trading in a day, 240 for a-shares.
+pub fn session_length(market: Market) -> u32 {
+    sessions(market)
+        .iter()
+        .map(|(open, close)| close - open)
+        .sum()
+}
+
+/// Minutes traded until `time`, rounded up, none before the open. A break
+/// or the close count as the end of the session before.
+pub fn trading_minute(market: Market, time: NaiveTime) -> Option<u32> {
+    let secs = time.num_seconds_from_midnight();
+    let mut elapsed = 0;
+    for (open, close) in sessions(market) {
+        if secs < open * 60 {
+            return Some(elapsed).filter(|x| *x > 0);
+        }
+        if secs <= close * 60 {
+            return Some(elapsed + (secs - open * 60).div_ceil(60));
+        }
+        elapsed += close - open;
+    }
+    Some(elapsed)
+}
+
+/// Local minutes after midnight of a trading minute, the inverse of
+/// [`trading_minute`].
+pub fn clock_minute(market: Market, mut minute: u32) -> u32 {
+    let sessions = sessions(market);
+    for (open, close) in sessions {
+        if minute <= close - open {
+            return open + minute;
+        }
+        minute -= close - open;
+    }
+    sessions.last().map_or(0, |(_, close)| *close)
+}
+
+/// Week and month candles are labelled by their last trading day so far.
+pub fn same_bar(scale: KLineScale, bar: NaiveDateTime, day: NaiveDateTime) -> bool {
+    match scale {
+        KLineScale::Week => bar.iso_week() == day.iso_week(),
+        KLineScale::Month => (bar.year(), bar.month()) == (day.year(), day.month()),
+        _ => bar == day,
+    }
+}
+
+impl Klines {
+    /// Folds a trade into the last candle, or starts the next one.
+    pub fn fold_tick(&mut self, market: Market, tick: &Tick) {
+        let Some(day) = bar_day(market, self.scale, tick.at) else {
+            return;
+        };
+        // nothing to build on until the source answered
+        let Some(last) = self.klines.last_mut() else {
+            return;
+        };
+        if same_bar(self.scale, last.day, day) {
+            last.high = last.high.max(tick.price);
+            last.low = last.low.min(tick.price);
+            last.close = tick.price;
+            last.volume += tick.volume;
+            last.amount += tick.amount;
+            if last.day != day {
+                last.day = day;
+                last.date = format_day(self.scale, day);
+            }
+        } else if day > last.day {
+            self.klines.push(KlineItem {
+                day,
+                open: tick.price,
+                high: tick.price,
+                low: tick.price,
+                close: tick.price,
+                volume: tick.volume,
+                amount: tick.amount,
+                date: format_day(self.scale, day),
+            });
+            self.cap();
+        }
+    }
+}
+
+fn format_day(scale: KLineScale, day: NaiveDateTime) -> String {
+    if scale.is_intraday() {
+        day.format("%Y-%m-%d %H:%M:%S").to_string()
+    } else {
+        day.format("%Y-%m-%d").to_string()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn at(x: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(x, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    fn bar(market: Market, scale: KLineScale, x: &str) -> Option<String> {
+        bar_day(market, scale, at(x)).map(|x| x.format("%m-%d %H:%M").to_string())
+    }
+
+    fn quote(date: &str, time: &str, new: &str, vol: u64) -> StockData {
+        StockData {
+            date: date.into(),
+            time: time.into(),
+            new: new.parse().unwrap(),
+            vol,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_bar_day() {
+        let zh = |scale, x| bar(Market::ZhShare, scale, x);
+        assert_eq!(zh(KLineScale::Munute5, "2025-01-27 09:25:00"), None);
+        assert_eq!(
+            zh(KLineScale::Munute5, "2025-01-27 09:30:00").as_deref(),
+            Some("01-27 09:35")
+        );
+        assert_eq!(
+            zh(KLineScale::Munute5, "2025-01-27 09:35:00").as_deref(),
+            Some("01-27 09:35")
+        );
+        assert_eq!(
+            zh(KLineScale::Munute5, "2025-01-27 09:35:01").as_deref(),
+            Some("01-27 09:40")
+        );
+        // the lunch break and the closing auction stay in the bar before
+        assert_eq!(
+            zh(KLineScale::Munute5, "2025-01-27 12:00:00").as_deref(),
+            Some("01-27 11:30")
+        );
+        assert_eq!(
+            zh(KLineScale::Munute5, "2025-01-27 15:00:01").as_deref(),
+            Some("01-27 15:00")
+        );
+        // hour bars count trading minutes, not the clock
+        assert_eq!(
+            zh(KLineScale::Hour, "2025-01-27 11:00:00").as_deref(),
+            Some("01-27 11:30")
+        );
+        assert_eq!(
+            zh(KLineScale::Hour, "2025-01-27 13:10:00").as_deref(),
+            Some("01-27 14:00")
+        );
+        assert_eq!(
+            zh(KLineScale::Hour, "2025-01-27 14:30:00").as_deref(),
+            Some("01-27 15:00")
+        );
+        assert_eq!(
+            zh(KLineScale::Day, "2025-01-27 14:30:00").as_deref(),
+            Some("01-27 00:00")
+        );
+
+        let hk = |scale, x| bar(Market::HkShare, scale, x);
+        assert_eq!(
+            hk(KLineScale::Munute30, "2025-01-27 11:45:00").as_deref(),
+            Some("01-27 12:00")
+        );
+        assert_eq!(
+            hk(KLineScale::Munute30, "2025-01-27 16:08:00").as_deref(),
+            Some("01-27 16:00")
+        );
+    }
+
+    #[test]
+    fn test_tick_between() {
+        let prev = quote("2025-01-27", "10:00:00", "10.0", 1000);
+        let data = quote("2025-01-27", "10:00:03", "10.1", 1500);
+        let tick = Tick::between(Market::ZhShare, &prev, &data).unwrap();
+        assert_eq!(tick.at, at("2025-01-27 10:00:03"));
+        assert_eq!(tick.price, 10.1);
+        assert_eq!(tick.volume, 500.0);
+
+        // a new day starts from its own totals
+        let data = quote("2025-01-28", "09:30:03", "10.2", 300);
+        assert_eq!(
+            Tick::between(Market::ZhShare, &prev, &data).unwrap().volume,
+            300.0
+        );
+        // suspended
+        let data = quote("2025-01-27", "10:00:03", "0", 1500);
+        assert_eq!(Tick::between(Market::ZhShare, &prev, &data), None);
+
+        // hk sends minutes, us beijing time
+        let data = quote("2025-01-27", "16:08", "373.6", 1500);
+        assert_eq!(
+            Tick::between(Market::HkShare, &prev, &data).unwrap().at,
+            at("2025-01-27 16:08:00")
+        );
+        let data = quote("2025-01-28", "04:59:00", "229.86", 1500);
+        assert_eq!(
+            Tick::between(Market::UsShare, &prev, &data).unwrap().at,
+            at("2025-01-27 15:59:00")
+        );
+    }
+
+    #[test]
+    fn test_fold_tick() {
+        let tick = |x: &str, price: f64, volume: f64| Tick {
+            at: at(x),
+            price,
+            volume,
+            amount: price * volume,
+        };
+        let mut klines = Klines {
+            scale: KLineScale::Munute5,
+            ..Default::default()
+        };
+        // nothing before the first fetch
+        klines.fold_tick(Market::ZhShare, &tick("2025-01-27 09:36:00", 10.0, 100.0));
+        assert!(klines.klines.is_empty());
+
+        klines.klines.push(KlineItem {
+            day: at("2025-01-27 09:40:00"),
+            open: 10.0,
+            high: 10.2,
+            low: 9.9,
+            close: 10.1,
+            volume: 1000.0,
+            amount: 10000.0,
+            date: "2025-01-27 09:40:00".into(),
+        });
+        klines.fold_tick(Market::ZhShare, &tick("2025-01-27 09:39:00", 10.5, 100.0));
+        klines.fold_tick(Market::ZhShare, &tick("2025-01-27 09:39:30", 10.3, 100.0));
+        assert_eq!(klines.klines.len(), 1);
+        let last = &klines.klines[0];
+        assert_eq!((last.high, last.low, last.close), (10.5, 9.9, 10.3));
+        assert_eq!(last.volume, 1200.0);
+
+        // rolls over on the boundary
+        klines.fold_tick(Market::ZhShare, &tick("2025-01-27 09:40:01", 10.4, 50.0));
+        assert_eq!(klines.klines.len(), 2);
+        let last = &klines.klines[1];
+        assert_eq!(last.date, "2025-01-27 09:45:00");
+        assert_eq!((last.open, last.close, last.volume), (10.4, 10.4, 50.0));
+        // a late quote of a closed bar is dropped
+        klines.fold_tick(Market::ZhShare, &tick("2025-01-27 09:38:00", 1.0, 50.0));
+        assert_eq!(klines.klines[0].low, 9.9);
+
+        // a week candle moves its label along
+        let mut week = Klines {
+            scale: KLineScale::Week,
+            klines: vec![KlineItem {
+                day: at("2025-01-20 00:00:00"),
+                close: 10.0,
+                high: 10.0,
+                low: 10.0,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        week.fold_tick(Market::ZhShare, &tick("2025-01-22 10:00:00", 11.0, 10.0));
+        assert_eq!(week.klines.len(), 1);
+        assert_eq!(week.klines[0].date, "2025-01-22");
+        week.fold_tick(Market::ZhShare, &tick("2025-01-27 10:00:00", 12.0, 10.0));
+        assert_eq!(week.klines.len(), 2);
+    }
+}
@@ -0,0 +1,370 @@
+use std::{collections::HashSet, fs, io, path::Path};
+
+use chrono::{DateTime, Datelike, FixedOffset, NaiveDate, NaiveTime, Utc, Weekday};
+
+use crate::error::TrackerError;
+
+use super::Market;
+
+// market,first day[,last day],name per line
+const HOLIDAYS: &str = include_str!("../../assets/data/holidays.csv");
+/// Holiday list in the storage dir, replaces the bundled one.
+pub const HOLIDAYS_FILE: &str = "holidays.csv";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Session {
+    PreMarket,
+    /// Opening or closing call auction.
+    Auction,
+    Regular,
+    /// Lunch break between the two sessions.
+    Break,
+    PostMarket,
+    Closed,
+}
+
+impl Session {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Session::PreMarket => "盘前",
+            Session::Auction => "集合竞价",
+            Session::Regular => "交易中",
+            Session::Break => "午休",
+            Session::PostMarket => "盘后",
+            Session::Closed => "休市",
+        }
+    }
+
+    /// Extended hours quotes are the latest price.
+    pub fn is_extended(&self) -> bool {
+        matches!(self, Session::PreMarket | Session::PostMarket)
+    }
+
+    /// Quotes can move.
+    pub fn is_live(&self) -> bool {
+        !matches!(self, Session::Break | Session::Closed)
+    }
+}
+
+/// Exchange holidays of every market.
+#[derive(Debug, Clone, Default)]
+pub struct Holidays {
+    days: HashSet<(Market, NaiveDate)>,
+}
+
+impl Holidays {
+    pub fn parse(data: &str) -> Result<Self, TrackerError> {
+        let mut days = HashSet::new();
+        for line in data.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |field| TrackerError::ParseError {
+                field,
+                line: line.to_string(),
+            };
+            let fields: Vec<&str> = line.split(',').collect();
+            let market = match fields[0] {
+                "zh" => Market::ZhShare,
+                "hk" => Market::HkShare,
+                "us" => Market::UsShare,
+                _ => return Err(error("market")),
+            };
+            let date = |x: &str| NaiveDate::parse_from_str(x, "%Y-%m-%d");
+            let first = fields
+                .get(1)
+                .and_then(|x| date(x).ok())
+                .ok_or_else(|| error("date"))?;
+            // the third field is either the last day or the name
+            let last = fields.get(2).and_then(|x| date(x).ok()).unwrap_or(first);
+            if last < first {
+                return Err(error("last day"));
+            }
+            days.extend(
+                first
+                    .iter_days()
+                    .take_while(|x| *x <= last)
+                    .map(|x| (market, x)),
+            );
+        }
+        Ok(Self { days })
+    }
+
+    pub fn contains(&self, market: Market, date: NaiveDate) -> bool {
+        self.days.contains(&(market, date))
+    }
+
+    /// Any holiday of `market` is listed in `year`.
+    pub fn covers(&self, market: Market, year: i32) -> bool {
+        self.days
+            .iter()
+            .any(|(m, x)| *m == market && x.year() == year)
+    }
+}
+
+/// Trading sessions of each market, holidays included.
+#[derive(Debug, Clone)]
+pub struct Calendar {
+    holidays: Holidays,
+}
+
+impl Default for Calendar {
+    /// With the bundled holiday list.
+    fn default() -> Self {
+        Self::new(Holidays::parse(HOLIDAYS).expect("bundled holidays"))
+    }
+}
+
+impl Calendar {
+    pub fn new(holidays: Holidays) -> Self {
+        Self { holidays }
+    }
+
+    /// With [`HOLIDAYS_FILE`] of `dir` when there is one, the bundled list
+    /// otherwise or when it doesn't parse.
+    pub fn load(dir: &Path) -> Self {
+        let path = dir.join(HOLIDAYS_FILE);
+        let holidays = match fs::read_to_string(&path) {
+            Ok(data) => Holidays::parse(&data),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Self::default(),
+            Err(e) => Err(e.into()),
+        };
+        match holidays {
+            Ok(holidays) => Self::new(holidays),
+            Err(e) => {
+                tracing::warn!("{}: {e}, using the bundled holidays", path.display());
+                Self::default()
+            }
+        }
+    }
+
+    /// Markets without a holiday listed in `year`, their sessions only skip
+    /// weekends.
+    pub fn uncovered(&self, year: i32) -> Vec<Market> {
+        [Market::ZhShare, Market::HkShare, Market::UsShare]
+            .into_iter()
+            .filter(|m| !self.holidays.covers(*m, year))
+            .collect()
+    }
+
+    pub fn session(&self, market: Market, now: DateTime<Utc>) -> Session {
+        let offset = match market {
+            Market::ZhShare | Market::HkShare => FixedOffset::east_opt(8 * 3600).unwrap(),
+            Market::UsShare => us_eastern_offset(now),
+        };
+        let local = now.with_timezone(&offset);
+        if self.holidays.contains(market, local.date_naive()) {
+            return Session::Closed;
+        }
+        match market {
+            Market::ZhShare => zh_session(local),
+            Market::HkShare => hk_session(local),
+            Market::UsShare => us_session(now),
+        }
+    }
+}
+
+fn hm(h: u32, m: u32) -> NaiveTime {
+    NaiveTime::from_hms_opt(h, m, 0).unwrap()
+}
+
+fn is_weekend(local: DateTime<FixedOffset>) -> bool {
+    matches!(local.weekday(), Weekday::Sat | Weekday::Sun)
+}
+
+/// A-shares: call auction 9:15-9:30, 9:30-11:30 and 13:00-14:57, closing
+/// call auction until 15:00 beijing time.
+fn zh_session(local: DateTime<FixedOffset>) -> Session {
+    if is_weekend(local) {
+        return Session::Closed;
+    }
+    match local.time() {
+        t if t < hm(9, 15) => Session::Closed,
+        t if t < hm(9, 30) => Session::Auction,
+        t if t < hm(11, 30) => Session::Regular,
+        t if t < hm(13, 0) => Session::Break,
+        t if t < hm(14, 57) => Session::Regular,
+        t if t < hm(15, 0) => Session::Auction,
+        _ => Session::Closed,
+    }
+}
+
+/// HKEX: pre-opening 9:00-9:30, 9:30-12:00 and 13:00-16:00, closing auction
+/// until 16:10 hong kong time.
+fn hk_session(local: DateTime<FixedOffset>) -> Session {
+    if is_weekend(local) {
+        return Session::Closed;
+    }
+    match local.time() {
+        t if t < hm(9, 0) => Session::Closed,
+        t if t < hm(9, 30) => Session::Auction,
+        t if t < hm(12, 0) => Session::Regular,
+        t if t < hm(13, 0) => Session::Break,
+        t if t < hm(16, 0) => Session::Regular,
+        t if t < hm(16, 10) => Session::Auction,
+        _ => Session::Closed,
+    }
+}
+
+/// New York offset, daylight saving runs from the second sunday of march to
+/// the first sunday of november, switching at 2:00 local time.
+pub fn us_eastern_offset(now: DateTime<Utc>) -> FixedOffset {
+    let year = now.year();
+    let switch = |month, n, utc_hour| {
+        NaiveDate::from_weekday_of_month_opt(year, month, Weekday::Sun, n)
+            .and_then(|d| d.and_hms_opt(utc_hour, 0, 0))
+            .map(|d| d.and_utc())
+    };
+
+    let dst = match (switch(3, 2, 7), switch(11, 1, 6)) {
+        (Some(start), Some(end)) => now >= start && now < end,
+        _ => false,
+    };
+    let hours = if dst { -4 } else { -5 };
+    FixedOffset::east_opt(hours * 3600).unwrap()
+}
+
+/// US equities session: pre-market 4:00, regular 9:30-16:00, post-market
+/// until 20:00 new york time.
+fn us_session(now: DateTime<Utc>) -> Session {
+    let local = now.with_timezone(&us_eastern_offset(now));
+    if is_weekend(local) {
+        return Session::Closed;
+    }
+
+    match local.time() {
+        t if t < hm(4, 0) => Session::Closed,
+        t if t < hm(9, 30) => Session::PreMarket,
+        t if t < hm(16, 0) => Session::Regular,
+        t if t < hm(20, 0) => Session::PostMarket,
+        _ => Session::Closed,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().to_utc()
+    }
+
+    #[test]
+    fn test_us_eastern_offset() {
+        let est = FixedOffset::west_opt(5 * 3600).unwrap();
+        let edt = FixedOffset::west_opt(4 * 3600).unwrap();
+
+        assert_eq!(us_eastern_offset(utc("2025-01-27T15:00:00Z")), est);
+        // 2025-03-09 02:00 EST
+        assert_eq!(us_eastern_offset(utc("2025-03-09T06:59:59Z")), est);
+        assert_eq!(us_eastern_offset(utc("2025-03-09T07:00:00Z")), edt);
+        assert_eq!(us_eastern_offset(utc("2025-07-04T12:00:00Z")), edt);
+        // 2025-11-02 02:00 EDT
+        assert_eq!(us_eastern_offset(utc("2025-11-02T05:59:59Z")), edt);
+        assert_eq!(us_eastern_offset(utc("2025-11-02T06:00:00Z")), est);
+    }
+
+    #[test]
+    fn test_us_session() {
+        // winter, monday 2025-01-27
+        assert_eq!(us_session(utc("2025-01-27T08:59:00Z")), Session::Closed);
+        assert_eq!(us_session(utc("2025-01-27T09:00:00Z")), Session::PreMarket);
+        assert_eq!(us_session(utc("2025-01-27T14:30:00Z")), Session::Regular);
+        assert_eq!(us_session(utc("2025-01-27T20:59:59Z")), Session::Regular);
+        assert_eq!(us_session(utc("2025-01-27T21:00:00Z")), Session::PostMarket);
+        assert_eq!(us_session(utc("2025-01-28T01:00:00Z")), Session::Closed);
+
+        // summer, the same local times are an hour earlier in utc
+        assert_eq!(us_session(utc("2025-07-07T13:30:00Z")), Session::Regular);
+        assert_eq!(us_session(utc("2025-07-07T20:00:00Z")), Session::PostMarket);
+
+        // saturday
+        assert_eq!(us_session(utc("2025-02-01T15:00:00Z")), Session::Closed);
+    }
+
+    #[test]
+    fn test_zh_session() {
+        let calendar = Calendar::default();
+        let zh = |s| calendar.session(Market::ZhShare, utc(s));
+
+        // monday 2025-01-27, beijing is utc+8
+        assert_eq!(zh("2025-01-27T01:14:59Z"), Session::Closed);
+        assert_eq!(zh("2025-01-27T01:15:00Z"), Session::Auction);
+        assert_eq!(zh("2025-01-27T01:30:00Z"), Session::Regular);
+        assert_eq!(zh("2025-01-27T03:30:00Z"), Session::Break);
+        assert_eq!(zh("2025-01-27T05:00:00Z"), Session::Regular);
+        assert_eq!(zh("2025-01-27T06:57:00Z"), Session::Auction);
+        assert_eq!(zh("2025-01-27T07:00:00Z"), Session::Closed);
+        // 00:30 utc is already 8:30 the next day in beijing
+        assert_eq!(zh("2025-01-26T23:30:00Z"), Session::Closed);
+
+        // spring festival and a weekend
+        assert_eq!(zh("2025-01-28T02:00:00Z"), Session::Closed);
+        assert_eq!(zh("2025-02-04T02:00:00Z"), Session::Closed);
+        assert_eq!(zh("2025-02-05T02:00:00Z"), Session::Regular);
+        assert_eq!(zh("2025-02-08T02:00:00Z"), Session::Closed);
+    }
+
+    #[test]
+    fn test_hk_and_us_holidays() {
+        let calendar = Calendar::default();
+
+        let hk = |s| calendar.session(Market::HkShare, utc(s));
+        assert_eq!(hk("2025-01-27T01:00:00Z"), Session::Auction);
+        assert_eq!(hk("2025-01-27T04:30:00Z"), Session::Break);
+        assert_eq!(hk("2025-01-27T08:05:00Z"), Session::Auction);
+        assert_eq!(hk("2025-01-27T08:10:00Z"), Session::Closed);
+        // lunar new year, a-shares are closed that day too
+        assert_eq!(hk("2025-01-29T02:00:00Z"), Session::Closed);
+
+        // good friday, the whole day including extended hours
+        let us = |s| calendar.session(Market::UsShare, utc(s));
+        assert_eq!(us("2025-04-18T10:00:00Z"), Session::Closed);
+        assert_eq!(us("2025-04-18T15:00:00Z"), Session::Closed);
+        assert_eq!(us("2025-04-17T15:00:00Z"), Session::Regular);
+    }
+
+    #[test]
+    fn test_load_holidays() {
+        let dir = crate::test_util::temp_dir("holidays");
+        fs::create_dir_all(&dir).unwrap();
+        let monday = utc("2025-01-27T02:00:00Z");
+
+        // nothing in the dir, the bundled list
+        let calendar = Calendar::load(&dir);
+        assert_eq!(calendar.session(Market::ZhShare, monday), Session::Regular);
+        assert!(calendar.uncovered(2025).is_empty());
+
+        fs::write(dir.join(HOLIDAYS_FILE), "zh,2025-01-27,休市\n").unwrap();
+        let calendar = Calendar::load(&dir);
+        assert_eq!(calendar.session(Market::ZhShare, monday), Session::Closed);
+        assert_eq!(calendar.uncovered(2025), [Market::HkShare, Market::UsShare]);
+        assert_eq!(calendar.uncovered(2099).len(), 3);
+
+        // a broken file falls back
+        fs::write(dir.join(HOLIDAYS_FILE), "xx,2025-01-27\n").unwrap();
+        let calendar = Calendar::load(&dir);
+        assert_eq!(calendar.session(Market::ZhShare, monday), Session::Regular);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_parse_holidays() {
+        let holidays = Holidays::parse(
+            "# comment\nzh,2025-10-01,2025-10-08,国庆节\nus,2025-07-04,Independence Day\n",
+        )
+        .unwrap();
+        let date = |s| NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap();
+
+        assert!(holidays.contains(Market::ZhShare, date("2025-10-01")));
+        assert!(holidays.contains(Market::ZhShare, date("2025-10-08")));
+        assert!(!holidays.contains(Market::ZhShare, date("2025-10-09")));
+        assert!(holidays.contains(Market::UsShare, date("2025-07-04")));
+        assert!(!holidays.contains(Market::HkShare, date("2025-07-04")));
+
+        assert!(Holidays::parse("jp,2025-01-01,元日").is_err());
+        assert!(Holidays::parse("zh,2025-13-01,?").is_err());
+        assert!(Holidays::parse("zh,2025-10-08,2025-10-01,?").is_err());
+    }
+}
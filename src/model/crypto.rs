@@ -0,0 +1,360 @@
+use chrono::DateTime;
+use serde_json::Value;
+
+use crate::error::TrackerError;
+
+use super::{
+    price::to_f64,
+    provider::CryptoProvider,
+    stock::{KLineScale, KlineItem, Klines},
+    Percent, Price,
+};
+
+const BINANCE_URL: &str = "https://api.binance.com";
+const BINANCE_STREAM_URL: &str = "wss://stream.binance.com:9443/ws";
+
+// symbols are quoted against usdt unless stated otherwise
+const QUOTE_ASSETS: [&str; 4] = ["USDT", "USDC", "BTC", "ETH"];
+
+#[derive(Clone, Default, Debug)]
+pub struct Coin {
+    pub symbol: String,
+    pub price: Price,
+    /// 24h change in percent.
+    pub rise_per: Percent,
+    pub high: Price,
+    pub low: Price,
+    pub vol: f64,
+    pub kline: Klines,
+}
+
+impl Coin {
+    /// Base asset, `BTCUSDT` -> `BTC`.
+    pub fn name(&self) -> &str {
+        QUOTE_ASSETS
+            .iter()
+            .find_map(|q| self.symbol.strip_suffix(q).filter(|base| !base.is_empty()))
+            .unwrap_or(&self.symbol)
+    }
+}
+
+impl KLineScale {
+    /// Binance kline interval.
+    pub fn to_interval(&self) -> &'static str {
+        match self {
+            KLineScale::Munute5 => "5m",
+            KLineScale::Munute15 => "15m",
+            KLineScale::Munute30 => "30m",
+            KLineScale::Hour => "1h",
+            KLineScale::Day => "1d",
+            KLineScale::Week => "1w",
+            KLineScale::Month => "1M",
+        }
+    }
+}
+
+/// Spot market data from the binance rest api.
+#[derive(Debug, Clone)]
+pub struct BinanceProvider {
+    base_url: String,
+    stream_url: String,
+}
+
+impl Default for BinanceProvider {
+    fn default() -> Self {
+        Self {
+            base_url: BINANCE_URL.to_string(),
+            stream_url: BINANCE_STREAM_URL.to_string(),
+        }
+    }
+}
+
+impl BinanceProvider {
+    /// `{base_url}/api/v3/...` is requested.
+    pub fn with_base_url(mut self, url: impl Into<String>) -> Self {
+        self.base_url = url.into();
+        self
+    }
+
+    /// Raw websocket stream, `<symbol>@ticker` is subscribed.
+    pub fn with_stream_url(mut self, url: impl Into<String>) -> Self {
+        self.stream_url = url.into();
+        self
+    }
+}
+
+impl CryptoProvider for BinanceProvider {
+    fn fetch_tickers(&self, symbols: &[String]) -> Result<Vec<Coin>, TrackerError> {
+        let symbols = serde_json::to_string(symbols).unwrap_or_default();
+        let str = reqwest::blocking::Client::new()
+            .get(format!("{}/api/v3/ticker/24hr", self.base_url))
+            .query(&[("symbols", symbols)])
+            .send()?
+            .error_for_status()?
+            .text()?;
+
+        decode_binance_tickers(&str)
+    }
+
+    fn fetch_klines(
+        &self,
+        symbol: &str,
+        scale: KLineScale,
+        limit: u32,
+    ) -> Result<Klines, TrackerError> {
+        let str = reqwest::blocking::get(format!(
+            "{}/api/v3/klines?symbol={symbol}&interval={}&limit={limit}",
+            self.base_url,
+            scale.to_interval()
+        ))?
+        .error_for_status()?
+        .text()?;
+
+        decode_binance_klines(&str, scale)
+    }
+
+    fn stream_url(&self) -> Option<String> {
+        Some(self.stream_url.clone())
+    }
+
+    fn subscribe_message(&self, symbols: &[String]) -> String {
+        let params = symbols
+            .iter()
+            .map(|x| format!("{}@ticker", x.to_lowercase()))
+            .collect::<Vec<String>>();
+        serde_json::json!({"method": "SUBSCRIBE", "params": params, "id": 1}).to_string()
+    }
+
+    fn decode_stream_message(&self, msg: &str) -> Result<Option<Coin>, TrackerError> {
+        decode_binance_ticker_event(msg)
+    }
+}
+
+// [{"symbol":"BTCUSDT","priceChangePercent":"-1.234","lastPrice":"97123.45","highPrice":"...","lowPrice":"...","volume":"..."}]
+fn decode_binance_tickers(str: &str) -> Result<Vec<Coin>, TrackerError> {
+    let items: Vec<Value> =
+        serde_json::from_str(str).map_err(|_| TrackerError::UnknownFormat(str.to_string()))?;
+
+    items
+        .into_iter()
+        .map(|x| {
+            let line = x.to_string();
+            let symbol = x["symbol"]
+                .as_str()
+                .ok_or_else(|| TrackerError::ParseError {
+                    field: "symbol",
+                    line: line.clone(),
+                })?;
+            let number = |field: &'static str| -> Result<Price, TrackerError> {
+                x[field]
+                    .as_str()
+                    .and_then(|v| v.parse::<Price>().ok())
+                    .ok_or_else(|| TrackerError::ParseError {
+                        field,
+                        line: line.clone(),
+                    })
+            };
+
+            Ok(Coin {
+                symbol: symbol.to_string(),
+                price: number("lastPrice")?,
+                rise_per: number("priceChangePercent")?,
+                high: number("highPrice")?,
+                low: number("lowPrice")?,
+                vol: to_f64(number("volume")?),
+                kline: Klines::default(),
+            })
+        })
+        .collect()
+}
+
+// {"e":"24hrTicker","E":1737936000000,"s":"BTCUSDT","P":"-1.234","c":"97123.45","h":"...","l":"...","v":"..."}
+// subscription acks look like {"result":null,"id":1}
+fn decode_binance_ticker_event(str: &str) -> Result<Option<Coin>, TrackerError> {
+    let x: Value =
+        serde_json::from_str(str).map_err(|_| TrackerError::UnknownFormat(str.to_string()))?;
+    if x["e"].as_str() != Some("24hrTicker") {
+        return Ok(None);
+    }
+
+    // same shape as the rest ticker with short keys
+    let ticker = serde_json::json!([{
+        "symbol": x["s"],
+        "lastPrice": x["c"],
+        "priceChangePercent": x["P"],
+        "highPrice": x["h"],
+        "lowPrice": x["l"],
+        "volume": x["v"],
+    }]);
+    let coin = decode_binance_tickers(&ticker.to_string())?.pop();
+    Ok(coin)
+}
+
+// [[open time ms, "open", "high", "low", "close", "volume", close time ms, "quote volume", ...]]
+fn decode_binance_klines(str: &str, scale: KLineScale) -> Result<Klines, TrackerError> {
+    let items: Vec<Vec<Value>> =
+        serde_json::from_str(str).map_err(|_| TrackerError::UnknownFormat(str.to_string()))?;
+
+    let mut klines = vec![];
+    let mut skipped = vec![];
+    for item in items {
+        match decode_binance_kline(item) {
+            Ok(item) => klines.push(item),
+            Err(e) => skipped.push(e.to_string()),
+        }
+    }
+
+    Ok(Klines {
+        scale,
+        klines,
+        skipped,
+    })
+}
+
+// [open time, open, high, low, close, volume, close time, quote volume, ...]
+fn decode_binance_kline(x: Vec<Value>) -> Result<KlineItem, TrackerError> {
+    let line = Value::from(x.clone()).to_string();
+    let err = |field| TrackerError::ParseError {
+        field,
+        line: line.clone(),
+    };
+    // exchange time, utc
+    let day = x
+        .first()
+        .and_then(Value::as_i64)
+        .and_then(DateTime::from_timestamp_millis)
+        .ok_or_else(|| err("open_time"))?
+        .naive_utc();
+    let number = |i: usize, field: &'static str| -> Result<f64, TrackerError> {
+        x.get(i)
+            .and_then(Value::as_str)
+            .and_then(|v| v.parse::<f64>().ok())
+            .ok_or_else(|| err(field))
+    };
+
+    Ok(KlineItem {
+        day,
+        open: number(1, "open")?,
+        high: number(2, "high")?,
+        low: number(3, "low")?,
+        close: number(4, "close")?,
+        volume: number(5, "volume")?,
+        amount: number(7, "amount")?,
+        date: day.format("%Y-%m-%d %H:%M:%S").to_string(),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::model::price::format_crypto_price;
+
+    fn d(x: &str) -> Price {
+        x.parse().unwrap()
+    }
+
+    const TICKER_24HR: &str = include_str!("../../tests/fixtures/binance/ticker_24hr.json");
+    const TICKER_EVENT: &str = include_str!("../../tests/fixtures/binance/ticker_event.json");
+    const KLINES_M15: &str = include_str!("../../tests/fixtures/binance/klines_btcusdt_15m.json");
+
+    #[test]
+    fn test_decode_binance_tickers() {
+        let coins = decode_binance_tickers(TICKER_24HR).unwrap();
+        assert_eq!(coins.len(), 2);
+
+        let btc = &coins[0];
+        assert_eq!(btc.symbol, "BTCUSDT");
+        assert_eq!(btc.name(), "BTC");
+        assert_eq!(btc.price, d("97123.45"));
+        assert_eq!(format_crypto_price(btc.price), "97123.45");
+        assert_eq!(btc.rise_per, d("-1.234"));
+        assert_eq!(btc.high, d("99000"));
+        assert_eq!(btc.low, d("96500.5"));
+        assert_eq!(btc.vol, 18234.567);
+
+        assert_eq!(coins[1].name(), "ETH");
+        assert_eq!(coins[1].rise_per, d("2.5"));
+    }
+
+    #[test]
+    fn test_decode_binance_tickers_malformed() {
+        assert!(matches!(
+            decode_binance_tickers("{\"code\":-1121,\"msg\":\"Invalid symbol.\"}"),
+            Err(TrackerError::UnknownFormat(_))
+        ));
+        assert!(matches!(
+            decode_binance_tickers("[{\"symbol\":\"BTCUSDT\",\"lastPrice\":\"abc\"}]"),
+            Err(TrackerError::ParseError {
+                field: "lastPrice",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_decode_binance_klines() {
+        let klines = decode_binance_klines(KLINES_M15, KLineScale::Munute15).unwrap();
+        assert_eq!(klines.scale, KLineScale::Munute15);
+        assert_eq!(klines.klines.len(), 3);
+
+        let first = &klines.klines[0];
+        assert_eq!(first.date, "2025-01-27 00:00:00");
+        assert_eq!(first.open, 102100.0);
+        assert_eq!(first.high, 102300.5);
+        assert_eq!(first.low, 101900.0);
+        assert_eq!(first.close, 102200.1);
+        assert_eq!(first.volume, 120.5);
+        assert_eq!(first.amount, 12310000.0);
+        assert_eq!(klines.klines[2].date, "2025-01-27 00:30:00");
+
+        assert!(klines.skipped.is_empty());
+
+        // a broken row is left out, the others still show
+        let broken = KLINES_M15.replacen("[[", "[[1737935100000,\"1\"],[", 1);
+        let klines = decode_binance_klines(&broken, KLineScale::Munute15).unwrap();
+        assert_eq!(klines.klines.len(), 3);
+        assert_eq!(klines.skipped.len(), 1);
+        assert!(klines.skipped[0].contains("`high`"));
+        assert!(matches!(
+            decode_binance_klines("{}", KLineScale::Munute15),
+            Err(TrackerError::UnknownFormat(_))
+        ));
+    }
+
+    #[test]
+    fn test_decode_binance_ticker_event() {
+        let coin = decode_binance_ticker_event(TICKER_EVENT).unwrap().unwrap();
+        assert_eq!(coin.symbol, "BTCUSDT");
+        assert_eq!(coin.price, d("97150"));
+        assert_eq!(coin.rise_per, d("-1.2"));
+        assert_eq!(coin.vol, 18240.5);
+
+        assert!(decode_binance_ticker_event("{\"result\":null,\"id\":1}")
+            .unwrap()
+            .is_none());
+        assert!(decode_binance_ticker_event("ping").is_err());
+    }
+
+    #[test]
+    fn test_subscribe_message() {
+        let msg = BinanceProvider::default()
+            .subscribe_message(&["BTCUSDT".to_string(), "ETHUSDT".to_string()]);
+        let msg: Value = serde_json::from_str(&msg).unwrap();
+        assert_eq!(msg["method"], "SUBSCRIBE");
+        assert_eq!(
+            msg["params"],
+            serde_json::json!(["btcusdt@ticker", "ethusdt@ticker"])
+        );
+    }
+
+    #[test]
+    fn test_coin_name() {
+        let coin = |symbol: &str| Coin {
+            symbol: symbol.to_string(),
+            ..Default::default()
+        };
+        assert_eq!(coin("ETHBTC").name(), "ETH");
+        assert_eq!(coin("SOLUSDC").name(), "SOL");
+        assert_eq!(coin("USDT").name(), "USDT");
+    }
+}
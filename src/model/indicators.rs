@@ -0,0 +1,580 @@
+use std::collections::VecDeque;
+
+use super::stock::{KlineItem, Klines};
+
+/// A technical indicator fed one candle at a time.
+pub trait Indicator: Clone {
+    type Output: Copy;
+
+    /// Value after the next candle.
+    fn next(&mut self, item: &KlineItem) -> Self::Output;
+}
+
+/// Values of an indicator along the klines. The state before the last
+/// candle is kept so a still moving candle can be fed again.
+#[derive(Debug, Clone)]
+pub struct Series<I: Indicator> {
+    indicator: I,
+    before_last: Option<I>,
+    values: Vec<I::Output>,
+}
+
+impl<I: Indicator> Series<I> {
+    pub fn new(indicator: I) -> Self {
+        Self {
+            indicator,
+            before_last: None,
+            values: vec![],
+        }
+    }
+
+    pub fn from_klines(indicator: I, klines: &Klines) -> Self {
+        let mut series = Self::new(indicator);
+        klines.klines.iter().for_each(|x| series.push(x));
+        series
+    }
+
+    /// A new candle.
+    pub fn push(&mut self, item: &KlineItem) {
+        self.before_last = Some(self.indicator.clone());
+        let value = self.indicator.next(item);
+        self.values.push(value);
+    }
+
+    /// The last candle changed.
+    pub fn update_last(&mut self, item: &KlineItem) {
+        match self.before_last.clone() {
+            Some(before) => {
+                self.indicator = before;
+                let value = self.indicator.next(item);
+                if let Some(last) = self.values.last_mut() {
+                    *last = value;
+                }
+            }
+            None => self.push(item),
+        }
+    }
+
+    pub fn values(&self) -> &[I::Output] {
+        &self.values
+    }
+
+    pub fn last(&self) -> Option<I::Output> {
+        self.values.last().copied()
+    }
+}
+
+/// Simple moving average of the close.
+#[derive(Debug, Clone)]
+pub struct Ma {
+    period: usize,
+    window: VecDeque<f64>,
+    sum: f64,
+}
+
+impl Ma {
+    pub fn new(period: usize) -> Self {
+        Self {
+            period: period.max(1),
+            window: VecDeque::new(),
+            sum: 0.0,
+        }
+    }
+
+    fn add(&mut self, x: f64) -> Option<f64> {
+        self.window.push_back(x);
+        self.sum += x;
+        if self.window.len() > self.period {
+            self.sum -= self.window.pop_front().unwrap_or_default();
+        }
+        (self.window.len() == self.period).then(|| self.sum / self.period as f64)
+    }
+}
+
+impl Indicator for Ma {
+    /// None until `period` candles.
+    type Output = Option<f64>;
+
+    fn next(&mut self, item: &KlineItem) -> Self::Output {
+        self.add(item.close)
+    }
+}
+
+/// Exponential moving average of the close, seeded with the first close the
+/// way chinese charting software does.
+#[derive(Debug, Clone)]
+pub struct Ema {
+    alpha: f64,
+    value: Option<f64>,
+}
+
+impl Ema {
+    pub fn new(period: usize) -> Self {
+        Self {
+            alpha: 2.0 / (period.max(1) as f64 + 1.0),
+            value: None,
+        }
+    }
+
+    fn add(&mut self, x: f64) -> f64 {
+        let value = match self.value {
+            Some(prev) => prev + self.alpha * (x - prev),
+            None => x,
+        };
+        self.value = Some(value);
+        value
+    }
+}
+
+impl Indicator for Ema {
+    type Output = f64;
+
+    fn next(&mut self, item: &KlineItem) -> Self::Output {
+        self.add(item.close)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct MacdValue {
+    pub dif: f64,
+    pub dea: f64,
+    /// 2 × (dif - dea), the bars.
+    pub macd: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct Macd {
+    fast: Ema,
+    slow: Ema,
+    signal: Ema,
+}
+
+impl Default for Macd {
+    /// 12, 26, 9.
+    fn default() -> Self {
+        Self::new(12, 26, 9)
+    }
+}
+
+impl Macd {
+    pub fn new(fast: usize, slow: usize, signal: usize) -> Self {
+        Self {
+            fast: Ema::new(fast),
+            slow: Ema::new(slow),
+            signal: Ema::new(signal),
+        }
+    }
+}
+
+impl Indicator for Macd {
+    type Output = MacdValue;
+
+    fn next(&mut self, item: &KlineItem) -> Self::Output {
+        let dif = self.fast.add(item.close) - self.slow.add(item.close);
+        let dea = self.signal.add(dif);
+        MacdValue {
+            dif,
+            dea,
+            macd: 2.0 * (dif - dea),
+        }
+    }
+}
+
+/// Wilder's relative strength index.
+#[derive(Debug, Clone)]
+pub struct Rsi {
+    period: usize,
+    prev_close: Option<f64>,
+    changes: usize,
+    gain: f64,
+    loss: f64,
+}
+
+impl Rsi {
+    pub fn new(period: usize) -> Self {
+        Self {
+            period: period.max(1),
+            prev_close: None,
+            changes: 0,
+            gain: 0.0,
+            loss: 0.0,
+        }
+    }
+}
+
+impl Indicator for Rsi {
+    /// None until `period` changes, the first value averages them.
+    type Output = Option<f64>;
+
+    fn next(&mut self, item: &KlineItem) -> Self::Output {
+        let prev = self.prev_close.replace(item.close)?;
+        let change = item.close - prev;
+        let n = self.period as f64;
+        self.changes += 1;
+        if self.changes <= self.period {
+            self.gain += change.max(0.0) / n;
+            self.loss += (-change).max(0.0) / n;
+            if self.changes < self.period {
+                return None;
+            }
+        } else {
+            self.gain = (self.gain * (n - 1.0) + change.max(0.0)) / n;
+            self.loss = (self.loss * (n - 1.0) + (-change).max(0.0)) / n;
+        }
+        Some(match (self.gain, self.loss) {
+            (g, l) if g + l == 0.0 => 50.0,
+            (g, l) => 100.0 * g / (g + l),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct KdjValue {
+    pub k: f64,
+    pub d: f64,
+    pub j: f64,
+}
+
+/// Stochastic KDJ, K and D start at 50 and early candles use the shorter
+/// window available.
+#[derive(Debug, Clone)]
+pub struct Kdj {
+    period: usize,
+    k_period: f64,
+    d_period: f64,
+    window: VecDeque<(f64, f64)>,
+    k: f64,
+    d: f64,
+}
+
+impl Default for Kdj {
+    /// 9, 3, 3.
+    fn default() -> Self {
+        Self::new(9, 3, 3)
+    }
+}
+
+impl Kdj {
+    pub fn new(period: usize, k_period: usize, d_period: usize) -> Self {
+        Self {
+            period: period.max(1),
+            k_period: k_period.max(1) as f64,
+            d_period: d_period.max(1) as f64,
+            window: VecDeque::new(),
+            k: 50.0,
+            d: 50.0,
+        }
+    }
+}
+
+impl Indicator for Kdj {
+    type Output = KdjValue;
+
+    fn next(&mut self, item: &KlineItem) -> Self::Output {
+        self.window.push_back((item.high, item.low));
+        if self.window.len() > self.period {
+            self.window.pop_front();
+        }
+        let high = self.window.iter().map(|x| x.0).fold(f64::MIN, f64::max);
+        let low = self.window.iter().map(|x| x.1).fold(f64::MAX, f64::min);
+        // a flat window keeps k where it is
+        let rsv = if high > low {
+            (item.close - low) / (high - low) * 100.0
+        } else {
+            self.k
+        };
+        self.k += (rsv - self.k) / self.k_period;
+        self.d += (self.k - self.d) / self.d_period;
+        KdjValue {
+            k: self.k,
+            d: self.d,
+            j: 3.0 * self.k - 2.0 * self.d,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct BollValue {
+    pub mid: f64,
+    pub upper: f64,
+    pub lower: f64,
+}
+
+/// Bollinger bands, the population deviation of the close around its MA.
+#[derive(Debug, Clone)]
+pub struct Boll {
+    ma: Ma,
+    width: f64,
+}
+
+impl Default for Boll {
+    /// 20, 2.
+    fn default() -> Self {
+        Self::new(20, 2.0)
+    }
+}
+
+impl Boll {
+    pub fn new(period: usize, width: f64) -> Self {
+        Self {
+            ma: Ma::new(period),
+            width,
+        }
+    }
+}
+
+impl Indicator for Boll {
+    type Output = Option<BollValue>;
+
+    fn next(&mut self, item: &KlineItem) -> Self::Output {
+        let mid = self.ma.add(item.close)?;
+        let window = &self.ma.window;
+        let var = window.iter().map(|x| (x - mid).powi(2)).sum::<f64>() / window.len() as f64;
+        let band = self.width * var.sqrt();
+        Some(BollValue {
+            mid,
+            upper: mid + band,
+            lower: mid - band,
+        })
+    }
+}
+
+/// Wilder's average true range.
+#[derive(Debug, Clone)]
+pub struct Atr {
+    period: usize,
+    prev_close: Option<f64>,
+    count: usize,
+    value: f64,
+}
+
+impl Atr {
+    pub fn new(period: usize) -> Self {
+        Self {
+            period: period.max(1),
+            prev_close: None,
+            count: 0,
+            value: 0.0,
+        }
+    }
+}
+
+impl Indicator for Atr {
+    /// None until `period` candles, the first value averages them.
+    type Output = Option<f64>;
+
+    fn next(&mut self, item: &KlineItem) -> Self::Output {
+        let range = item.high - item.low;
+        let tr = match self.prev_close.replace(item.close) {
+            Some(pc) => range.max((item.high - pc).abs()).max((item.low - pc).abs()),
+            None => range,
+        };
+        let n = self.period as f64;
+        self.count += 1;
+        if self.count <= self.period {
+            self.value += tr / n;
+            (self.count == self.period).then_some(self.value)
+        } else {
+            self.value = (self.value * (n - 1.0) + tr) / n;
+            Some(self.value)
+        }
+    }
+}
+
+/// On balance volume, starting at 0.
+#[derive(Debug, Clone, Default)]
+pub struct Obv {
+    prev_close: Option<f64>,
+    value: f64,
+}
+
+impl Indicator for Obv {
+    type Output = f64;
+
+    fn next(&mut self, item: &KlineItem) -> Self::Output {
+        if let Some(pc) = self.prev_close.replace(item.close) {
+            if item.close > pc {
+                self.value += item.volume;
+            } else if item.close < pc {
+                self.value -= item.volume;
+            }
+        }
+        self.value
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // closes of the stockcharts RSI worksheet
+    const CLOSES: [f64; 33] = [
+        44.34, 44.09, 44.15, 43.61, 44.33, 44.83, 45.10, 45.42, 45.84, 46.08, 45.89, 46.03, 45.61,
+        46.28, 46.28, 46.00, 46.03, 46.41, 46.22, 45.64, 46.21, 46.25, 45.71, 46.45, 45.78, 45.35,
+        44.03, 44.18, 44.22, 44.57, 43.42, 42.66, 43.13,
+    ];
+
+    // highs, lows and volumes made up around the closes
+    fn klines() -> Klines {
+        let klines = CLOSES
+            .iter()
+            .enumerate()
+            .map(|(i, &close)| KlineItem {
+                open: close,
+                high: close + 0.25 * (i % 3 + 1) as f64,
+                low: close - 0.2 * (i % 4 + 1) as f64,
+                close,
+                volume: (1000 + 100 * (i % 5)) as f64,
+                ..Default::default()
+            })
+            .collect();
+        Klines {
+            klines,
+            ..Default::default()
+        }
+    }
+
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() < 1e-6, "{a} != {b}");
+    }
+
+    #[test]
+    fn test_ma_ema() {
+        let ma = Series::from_klines(Ma::new(5), &klines());
+        assert_eq!(ma.values()[3], None);
+        assert_close(ma.values()[4].unwrap(), 44.104);
+        assert_close(ma.last().unwrap().unwrap(), 43.6);
+
+        let ema = Series::from_klines(Ema::new(3), &klines());
+        assert_close(ema.values()[0], 44.34);
+        assert_close(ema.values()[1], 44.215);
+        assert_close(ema.values()[2], 44.1825);
+    }
+
+    // candles worked through by hand below
+    fn hlc(candles: &[(f64, f64, f64)]) -> Klines {
+        let klines = candles
+            .iter()
+            .map(|&(high, low, close)| KlineItem {
+                open: close,
+                high,
+                low,
+                close,
+                ..Default::default()
+            })
+            .collect();
+        Klines {
+            klines,
+            ..Default::default()
+        }
+    }
+
+    const HLC: [(f64, f64, f64); 4] = [
+        (10.0, 6.0, 9.0),
+        (12.0, 9.0, 11.0),
+        (11.0, 7.0, 8.0),
+        (13.0, 10.0, 12.0),
+    ];
+
+    #[test]
+    fn test_macd() {
+        // a flat close has no spread whatever the periods
+        let flat = hlc(&[(10.0, 10.0, 10.0); 40]);
+        let macd = Series::from_klines(Macd::default(), &flat);
+        assert!(macd.values().iter().all(|x| *x == MacdValue::default()));
+
+        // alphas 1/2, 1/3 and 2/3, each ema starts at the first value
+        let macd = Series::from_klines(Macd::new(3, 5, 2), &hlc(&HLC[1..3]));
+        assert_eq!(macd.values()[0], MacdValue::default());
+        // fast 11 + (8 - 11) / 2, slow 11 + (8 - 11) / 3
+        let x = macd.values()[1];
+        assert_close(x.dif, 9.5 - 10.0);
+        assert_close(x.dea, 2.0 / 3.0 * -0.5);
+        assert_close(x.macd, 2.0 * (-0.5 + 1.0 / 3.0));
+    }
+
+    #[test]
+    fn test_kdj() {
+        // rsv 75, 250/3, 100/3 and 250/3 once the first low leaves the window
+        let kdj = Series::from_klines(Kdj::new(3, 3, 3), &hlc(&HLC));
+        for (i, (k, d)) in [
+            (0, (175.0 / 3.0, 475.0 / 9.0)),
+            (1, (200.0 / 3.0, 1550.0 / 27.0)),
+            (2, (500.0 / 9.0, 4600.0 / 81.0)),
+            (3, (1750.0 / 27.0, 14450.0 / 243.0)),
+        ] {
+            let x = kdj.values()[i];
+            assert_close(x.k, k);
+            assert_close(x.d, d);
+            assert_close(x.j, 3.0 * k - 2.0 * d);
+        }
+    }
+
+    #[test]
+    fn test_boll() {
+        // the textbook population deviation: mean 5, deviation 2
+        let closes = [2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0];
+        let klines = hlc(&closes.map(|x| (x, x, x)));
+        let boll = Series::from_klines(Boll::new(8, 2.0), &klines);
+        assert!(boll.values()[..7].iter().all(Option::is_none));
+        assert_eq!(
+            boll.last().unwrap(),
+            Some(BollValue {
+                mid: 5.0,
+                upper: 9.0,
+                lower: 1.0
+            })
+        );
+
+        // 5, 5, 7, 9: mean 6.5, variance 11 / 4
+        let x = Series::from_klines(Boll::new(4, 2.0), &klines)
+            .last()
+            .unwrap()
+            .unwrap();
+        assert_close(x.mid, 6.5);
+        assert_close(x.upper, 6.5 + 2.0 * 2.75f64.sqrt());
+        assert_close(x.lower, 6.5 - 2.0 * 2.75f64.sqrt());
+    }
+
+    #[test]
+    fn test_atr_obv() {
+        // true ranges 4, 3, 4, 5 from the close at 8 and 4 for the gap down
+        let mut candles = HLC.to_vec();
+        candles.push((9.0, 8.0, 8.5));
+        let atr = Series::from_klines(Atr::new(3), &hlc(&candles));
+        assert_eq!(atr.values()[1], None);
+        assert_close(atr.values()[2].unwrap(), 11.0 / 3.0);
+        assert_close(atr.values()[3].unwrap(), (11.0 / 3.0 * 2.0 + 5.0) / 3.0);
+        assert_close(atr.values()[4].unwrap(), (37.0 / 9.0 * 2.0 + 4.0) / 3.0);
+
+        // up, down, unchanged, up
+        let mut klines = hlc(&[10.0, 11.0, 10.5, 10.5, 12.0].map(|x| (x, x, x)));
+        for (i, x) in klines.klines.iter_mut().enumerate() {
+            x.volume = 100.0 * (i + 1) as f64;
+        }
+        let obv = Series::from_klines(Obv::default(), &klines);
+        assert_eq!(obv.values(), [0.0, 200.0, -100.0, -100.0, 400.0]);
+    }
+
+    #[test]
+    fn test_update_last() {
+        let klines = klines();
+        let (head, tail) = klines.klines.split_at(20);
+        let full = Series::from_klines(Macd::default(), &klines);
+
+        // the last candle ticking before it closes
+        let mut series = Series::new(Macd::default());
+        head.iter().for_each(|x| series.push(x));
+        for item in tail {
+            let mut moving = item.clone();
+            moving.close += 1.0;
+            series.push(&moving);
+            series.update_last(item);
+        }
+        assert_eq!(series.values(), full.values());
+
+        let mut rsi = Series::new(Rsi::new(14));
+        rsi.update_last(&klines.klines[0]);
+        assert_eq!(rsi.values().len(), 1);
+    }
+}
@@ -0,0 +1,505 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, BufWriter, ErrorKind, Write},
+    path::{Path, PathBuf},
+};
+
+use chrono::{NaiveDate, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+
+use crate::error::TrackerError;
+
+use super::{
+    bars::same_bar,
+    stock::{KLineScale, KlineItem, Klines, KLINE_PAGE, MAX_KLINES},
+    QuoteProvider,
+};
+
+// first line of every file, anything else is not ours
+const HEADER: &str = "# wealth-tracker klines v1";
+const EXTENSION: &str = "klines";
+/// Candles asked for when the cache is warm, a gap falls back to a full page.
+pub const KLINE_TAIL: u32 = 10;
+
+/// One cached code and scale, for inspection.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CacheEntry {
+    pub code: String,
+    pub scale: KLineScale,
+    pub candles: usize,
+    pub first: Option<NaiveDateTime>,
+    pub last: Option<NaiveDateTime>,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Cached {
+    klines: Klines,
+    // lines in the file, replaced candles included
+    records: usize,
+}
+
+/// Klines on disk, an append-only file per code and scale.
+///
+/// Each line is a candle with a checksum. A candle at or before the period of
+/// the last one read replaces the tail, so a refreshed bar is just appended
+/// again.
+#[derive(Debug, Clone)]
+pub struct KlineStore {
+    dir: PathBuf,
+    held: HashMap<(String, KLineScale), Cached>,
+}
+
+impl KlineStore {
+    pub fn open(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            held: HashMap::new(),
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Cached candles, read from disk the first time.
+    pub fn get(&mut self, code: &str, scale: KLineScale) -> &Klines {
+        &self.cached(code, scale).klines
+    }
+
+    /// Serves the cache and only fetches the missing tail.
+    pub fn sync(
+        &mut self,
+        provider: &dyn QuoteProvider,
+        code: &str,
+        scale: KLineScale,
+    ) -> Result<Klines, TrackerError> {
+        let last = self.get(code, scale).klines.last().map(|x| x.day);
+        let mut newer = match last {
+            Some(last) => {
+                let tail = provider.fetch_klines(code, scale, KLINE_TAIL)?;
+                if tail.klines.first().is_some_and(|x| x.day <= last) {
+                    tail
+                } else {
+                    provider.fetch_klines(code, scale, KLINE_PAGE)?
+                }
+            }
+            None => provider.fetch_klines(code, scale, KLINE_PAGE)?,
+        };
+        // the file is named by the scale asked for, other candles can't go in
+        if newer.scale != scale {
+            return Err(TrackerError::UnsupportedScale {
+                code: code.to_string(),
+                scale: scale.label(),
+            });
+        }
+        let skipped = std::mem::take(&mut newer.skipped);
+        // the fetch is what counts, a full disk only loses the cache
+        if let Err(e) = self.append(code, newer) {
+            tracing::warn!("kline cache {code} {scale:?}: {e}");
+        }
+        let mut klines = self.get(code, scale).clone();
+        klines.skipped = skipped;
+        Ok(klines)
+    }
+
+    /// Merges fresh candles, only the changed ones are written.
+    pub fn append(&mut self, code: &str, newer: Klines) -> Result<(), TrackerError> {
+        if newer.klines.is_empty() {
+            return Ok(());
+        }
+        let scale = newer.scale;
+        let path = self.path(code, scale);
+        let cached = self.cached(code, scale);
+        let held = &cached.klines.klines;
+        let overlaps = held
+            .last()
+            .is_some_and(|last| newer.klines[0].day <= last.day);
+        // candles already on disk as they are need no new line
+        let from = held.partition_point(|x| x.day < newer.klines[0].day);
+        let same = held[from..]
+            .iter()
+            .zip(&newer.klines)
+            .take_while(|(a, b)| a == b)
+            .count();
+        let changed = newer.klines[same..].to_vec();
+        cached.klines.merge_newer(newer);
+
+        let len = cached.klines.klines.len();
+        if !overlaps || cached.records + changed.len() > len + MAX_KLINES {
+            cached.records = len;
+            return write_file(&path, &cached.klines.klines);
+        }
+        cached.records += changed.len();
+        append_file(&path, &changed)
+    }
+
+    /// Puts older candles in front, the file is rewritten.
+    pub fn prepend(&mut self, code: &str, older: Klines) -> Result<usize, TrackerError> {
+        let path = self.path(code, older.scale);
+        let cached = self.cached(code, older.scale);
+        let added = cached.klines.merge_older(older);
+        if added > 0 {
+            cached.records = cached.klines.klines.len();
+            write_file(&path, &cached.klines.klines)?;
+        }
+        Ok(added)
+    }
+
+    /// Every cached code and scale as found on disk.
+    pub fn entries(&self) -> Result<Vec<CacheEntry>, TrackerError> {
+        let mut entries = vec![];
+        for (path, code, scale) in self.files()? {
+            let bytes = fs::metadata(&path)?.len();
+            let (cached, _) = read_file(&path, scale)?;
+            let klines = &cached.klines.klines;
+            entries.push(CacheEntry {
+                code,
+                scale,
+                candles: klines.len(),
+                first: klines.first().map(|x| x.day),
+                last: klines.last().map(|x| x.day),
+                bytes,
+            });
+        }
+        entries.sort_by(|a, b| (&a.code, a.scale.to_usize()).cmp(&(&b.code, b.scale.to_usize())));
+        Ok(entries)
+    }
+
+    /// Removes the files of `code`, or all of them, returns how many.
+    pub fn clear(&mut self, code: Option<&str>) -> Result<usize, TrackerError> {
+        let mut removed = 0;
+        for (path, file_code, scale) in self.files()? {
+            if code.is_some_and(|x| x != file_code) {
+                continue;
+            }
+            fs::remove_file(&path)?;
+            self.held.remove(&(file_code, scale));
+            removed += 1;
+        }
+        Ok(removed)
+    }
+
+    fn cached(&mut self, code: &str, scale: KLineScale) -> &mut Cached {
+        let path = self.path(code, scale);
+        self.held
+            .entry((code.to_string(), scale))
+            .or_insert_with(|| load(&path, scale))
+    }
+
+    fn path(&self, code: &str, scale: KLineScale) -> PathBuf {
+        self.dir
+            .join(format!("{code}_{}.{EXTENSION}", scale.to_usize()))
+    }
+
+    fn files(&self) -> Result<Vec<(PathBuf, String, KLineScale)>, TrackerError> {
+        let dir = match fs::read_dir(&self.dir) {
+            Ok(dir) => dir,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e.into()),
+        };
+        let mut files = vec![];
+        for entry in dir {
+            let path = entry?.path();
+            if path.extension().is_none_or(|x| x != EXTENSION) {
+                continue;
+            }
+            let Some(stem) = path.file_stem().and_then(|x| x.to_str()) else {
+                continue;
+            };
+            // codes like gb_aapl have an underscore too
+            let Some((code, scale)) = stem.rsplit_once('_') else {
+                continue;
+            };
+            let Some(scale) = KLineScale::ALL
+                .into_iter()
+                .find(|x| x.to_usize().to_string() == scale)
+            else {
+                continue;
+            };
+            files.push((path.clone(), code.to_string(), scale));
+        }
+        Ok(files)
+    }
+}
+
+/// Reads a file, a damaged one keeps its valid head and is rewritten.
+fn load(path: &Path, scale: KLineScale) -> Cached {
+    match read_file(path, scale) {
+        Ok((cached, true)) => cached,
+        Ok((mut cached, false)) => {
+            tracing::warn!(
+                "kline cache {path:?} is damaged, {} kept",
+                cached.klines.klines.len()
+            );
+            cached.records = cached.klines.klines.len();
+            if let Err(e) = write_file(path, &cached.klines.klines) {
+                tracing::warn!("kline cache {path:?}: {e}");
+            }
+            cached
+        }
+        Err(e) => {
+            tracing::warn!("kline cache {path:?}: {e}");
+            Cached {
+                klines: Klines {
+                    scale,
+                    ..Default::default()
+                },
+                records: 0,
+            }
+        }
+    }
+}
+
+/// The candles read and whether every line checked out.
+fn read_file(path: &Path, scale: KLineScale) -> Result<(Cached, bool), TrackerError> {
+    let mut cached = Cached {
+        klines: Klines {
+            scale,
+            ..Default::default()
+        },
+        records: 0,
+    };
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok((cached, true)),
+        Err(e) => return Err(e.into()),
+    };
+    let mut lines = BufReader::new(file).lines();
+    if lines.next().transpose()?.as_deref() != Some(HEADER) {
+        return Ok((cached, false));
+    }
+    for line in lines {
+        // a torn write or a flipped bit, nothing after it is trusted
+        let Some(item) = decode_record(&line?) else {
+            return Ok((cached, false));
+        };
+        replay(&mut cached.klines.klines, scale, item);
+        cached.records += 1;
+    }
+    Ok((cached, true))
+}
+
+// a candle replaces the one of its period and any after it, a week or month
+// candle moves its day as the period goes on
+fn replay(klines: &mut Vec<KlineItem>, scale: KLineScale, item: KlineItem) {
+    let from = klines.partition_point(|x| x.day < item.day);
+    klines.truncate(from);
+    if klines
+        .last()
+        .is_some_and(|x| same_bar(scale, x.day, item.day))
+    {
+        klines.pop();
+    }
+    klines.push(item);
+    if klines.len() > MAX_KLINES {
+        klines.drain(..klines.len() - MAX_KLINES);
+    }
+}
+
+fn write_file(path: &Path, klines: &[KlineItem]) -> Result<(), TrackerError> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    // a crash leaves the old file, never half of the new one
+    let tmp = path.with_extension("tmp");
+    let mut file = BufWriter::new(File::create(&tmp)?);
+    writeln!(file, "{HEADER}")?;
+    for item in klines {
+        writeln!(file, "{}", encode_record(item))?;
+    }
+    file.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+fn append_file(path: &Path, klines: &[KlineItem]) -> Result<(), TrackerError> {
+    if klines.is_empty() {
+        return Ok(());
+    }
+    let mut file = OpenOptions::new().append(true).open(path)?;
+    let mut lines = String::new();
+    for item in klines {
+        lines.push_str(&encode_record(item));
+        lines.push('\n');
+    }
+    file.write_all(lines.as_bytes())?;
+    Ok(())
+}
+
+fn checksum(body: &str) -> String {
+    let digest = Sha1::digest(body.as_bytes());
+    digest[..4].iter().map(|x| format!("{x:02x}")).collect()
+}
+
+// date,open,high,low,close,volume,amount,checksum
+fn encode_record(item: &KlineItem) -> String {
+    let body = format!(
+        "{},{},{},{},{},{},{}",
+        item.date, item.open, item.high, item.low, item.close, item.volume, item.amount
+    );
+    let sum = checksum(&body);
+    format!("{body},{sum}")
+}
+
+fn decode_record(line: &str) -> Option<KlineItem> {
+    let (body, sum) = line.rsplit_once(',')?;
+    if checksum(body) != sum {
+        return None;
+    }
+    let fields: Vec<&str> = body.split(',').collect();
+    let [date, open, high, low, close, volume, amount] = fields[..] else {
+        return None;
+    };
+    let day = NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M:%S")
+        .or_else(|_| NaiveDate::parse_from_str(date, "%Y-%m-%d").map(Into::into))
+        .ok()?;
+    Some(KlineItem {
+        day,
+        open: open.parse().ok()?,
+        high: high.parse().ok()?,
+        low: low.parse().ok()?,
+        close: close.parse().ok()?,
+        volume: volume.parse().ok()?,
+        amount: amount.parse().ok()?,
+        date: date.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::{self, temp_dir};
+
+    fn store(name: &str) -> KlineStore {
+        KlineStore::open(temp_dir(&format!("klines-{name}")))
+    }
+
+    fn candles(minutes: std::ops::Range<u32>, close: f64) -> Klines {
+        test_util::candles(KLineScale::Munute5, minutes, close)
+    }
+
+    fn lines(store: &KlineStore, code: &str) -> Vec<String> {
+        let path = store.path(code, KLineScale::Munute5);
+        fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|x| x.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_store_roundtrip() {
+        let mut store = store("roundtrip");
+        store.append("sh601127", candles(0..5, 2.0)).unwrap();
+        // the last bar moved and a new one started
+        let mut tail = candles(3..6, 2.0);
+        tail.klines[1].close = 2.5;
+        store.append("sh601127", tail).unwrap();
+        assert_eq!(lines(&store, "sh601127").len(), 1 + 5 + 2);
+
+        let expected = store.get("sh601127", KLineScale::Munute5).clone();
+        assert_eq!(expected.klines.len(), 6);
+        assert_eq!(expected.klines[4].close, 2.5);
+        // a fresh store reads the same from disk, floats included
+        let mut reopened = KlineStore::open(store.dir());
+        assert_eq!(
+            reopened.get("sh601127", KLineScale::Munute5).klines,
+            expected.klines
+        );
+        fs::remove_dir_all(store.dir()).unwrap();
+    }
+
+    #[test]
+    fn test_store_week_roundtrip() {
+        let week = |days: &[u32]| test_util::candles(KLineScale::Week, days.to_vec(), 2.0);
+        let mut store = store("week");
+        // friday, friday and tuesday of the current week
+        store.append("sh601127", week(&[2, 9, 13])).unwrap();
+        // a day later the current week is labelled wednesday
+        let mut tail = week(&[9, 14]);
+        tail.klines[1].close = 2.5;
+        store.append("sh601127", tail).unwrap();
+
+        let expected = store.get("sh601127", KLineScale::Week).clone();
+        let days: Vec<&str> = expected.klines.iter().map(|x| x.date.as_str()).collect();
+        assert_eq!(days, ["2025-01-03", "2025-01-10", "2025-01-15"]);
+        assert_eq!(expected.klines[2].close, 2.5);
+
+        // the moved candle is one more line, replayed over tuesday
+        let path = store.path("sh601127", KLineScale::Week);
+        assert_eq!(fs::read_to_string(path).unwrap().lines().count(), 1 + 3 + 1);
+        let mut reopened = KlineStore::open(store.dir());
+        assert_eq!(
+            reopened.get("sh601127", KLineScale::Week).klines,
+            expected.klines
+        );
+        fs::remove_dir_all(store.dir()).unwrap();
+    }
+
+    #[test]
+    fn test_store_repairs_damage() {
+        let mut store = store("damage");
+        store.append("sh601127", candles(0..5, 2.0)).unwrap();
+        let path = store.path("sh601127", KLineScale::Munute5);
+
+        // a torn last write
+        let mut text = fs::read_to_string(&path).unwrap();
+        text.truncate(text.len() - 10);
+        fs::write(&path, &text).unwrap();
+        let mut reopened = KlineStore::open(store.dir());
+        assert_eq!(
+            reopened.get("sh601127", KLineScale::Munute5).klines.len(),
+            4
+        );
+        assert_eq!(lines(&reopened, "sh601127").len(), 1 + 4);
+
+        // a changed price fails its checksum, the rest after it goes too
+        let text = fs::read_to_string(&path)
+            .unwrap()
+            .replacen("09:01:00,1.5", "09:01:00,9.5", 1);
+        fs::write(&path, text).unwrap();
+        let mut reopened = KlineStore::open(store.dir());
+        assert_eq!(
+            reopened.get("sh601127", KLineScale::Munute5).klines.len(),
+            1
+        );
+
+        fs::write(&path, "not a kline file\n").unwrap();
+        let mut reopened = KlineStore::open(store.dir());
+        assert!(reopened
+            .get("sh601127", KLineScale::Munute5)
+            .klines
+            .is_empty());
+        fs::remove_dir_all(store.dir()).unwrap();
+    }
+
+    #[test]
+    fn test_store_entries_and_clear() {
+        let mut store = store("entries");
+        store.append("sh601127", candles(10..20, 2.0)).unwrap();
+        assert_eq!(store.prepend("sh601127", candles(0..15, 2.0)).unwrap(), 10);
+        store.append("gb_aapl", candles(0..3, 2.0)).unwrap();
+
+        let entries = store.entries().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].code, "gb_aapl");
+        assert_eq!(entries[1].code, "sh601127");
+        assert_eq!(entries[1].scale, KLineScale::Munute5);
+        assert_eq!(entries[1].candles, 20);
+        assert_eq!(
+            entries[1].first.map(|x| x.to_string()).as_deref(),
+            Some("2025-01-27 09:00:00")
+        );
+        assert!(entries[1].bytes > 0);
+
+        assert_eq!(store.clear(Some("gb_aapl")).unwrap(), 1);
+        assert_eq!(store.entries().unwrap().len(), 1);
+        assert_eq!(store.clear(None).unwrap(), 1);
+        assert!(store.entries().unwrap().is_empty());
+        assert!(store.get("sh601127", KLineScale::Munute5).klines.is_empty());
+        fs::remove_dir_all(store.dir()).unwrap();
+    }
+}
@@ -0,0 +1,461 @@
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::error::LedgerError;
+
+use super::{
+    lots::{CostMethod, Lot, LotBook},
+    portfolio::Portfolio,
+};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TxKind {
+    #[default]
+    Buy,
+    Sell,
+    /// Cash dividend, lowers the cost basis.
+    Dividend,
+    /// Bonus shares at no cost (送股/转增).
+    Bonus,
+    /// Fees outside a trade, raise the cost basis.
+    Fee,
+    /// Moves shares to `to_account` at their cost.
+    Transfer,
+}
+
+impl TxKind {
+    pub const ALL: [TxKind; 6] = [
+        TxKind::Buy,
+        TxKind::Sell,
+        TxKind::Dividend,
+        TxKind::Bonus,
+        TxKind::Fee,
+        TxKind::Transfer,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            TxKind::Buy => "买入",
+            TxKind::Sell => "卖出",
+            TxKind::Dividend => "分红",
+            TxKind::Bonus => "送股",
+            TxKind::Fee => "费用",
+            TxKind::Transfer => "转托管",
+        }
+    }
+
+    pub fn has_qty(&self) -> bool {
+        matches!(
+            self,
+            TxKind::Buy | TxKind::Sell | TxKind::Bonus | TxKind::Transfer
+        )
+    }
+
+    pub fn has_price(&self) -> bool {
+        matches!(self, TxKind::Buy | TxKind::Sell)
+    }
+
+    /// Commission of a trade, the cash of a dividend or fee.
+    pub fn has_amount(&self) -> bool {
+        !matches!(self, TxKind::Bonus | TxKind::Transfer)
+    }
+
+    /// Shares leave a lot.
+    pub fn has_lot(&self) -> bool {
+        matches!(self, TxKind::Sell | TxKind::Transfer)
+    }
+}
+
+/// Amounts are decimal so gains match broker statements to the cent.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Transaction {
+    pub date: NaiveDate,
+    pub kind: TxKind,
+    pub code: String,
+    pub account: String,
+    pub qty: Decimal,
+    pub price: Decimal,
+    pub amount: Decimal,
+    pub to_account: String,
+    /// Opening date of the lot sold with [`CostMethod::Specific`].
+    #[serde(default)]
+    pub lot: Option<NaiveDate>,
+}
+
+/// Every transaction in date order, positions are replayed from it.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Ledger {
+    transactions: Vec<Transaction>,
+    #[serde(default)]
+    method: CostMethod,
+}
+
+impl Ledger {
+    pub fn transactions(&self) -> &[Transaction] {
+        &self.transactions
+    }
+
+    pub fn method(&self) -> CostMethod {
+        self.method
+    }
+
+    /// Switches the cost method if every transaction still replays with it.
+    pub fn set_method(&mut self, method: CostMethod) -> Result<(), LedgerError> {
+        replay(&self.transactions, method)?;
+        self.method = method;
+        Ok(())
+    }
+
+    /// Inserts `tx` after every transaction of its date or earlier, if
+    /// every position stays valid.
+    pub fn push(&mut self, tx: Transaction) -> Result<(), LedgerError> {
+        let mut transactions = self.transactions.clone();
+        let index = transactions.partition_point(|x| x.date <= tx.date);
+        transactions.insert(index, tx);
+        replay(&transactions, self.method)?;
+        self.transactions = transactions;
+        Ok(())
+    }
+
+    /// Removes the transaction at `index` unless a later one depends on it.
+    pub fn remove(&mut self, index: usize) -> Result<Transaction, LedgerError> {
+        let mut transactions = self.transactions.clone();
+        if index >= transactions.len() {
+            return Err(LedgerError::Invalid("index"));
+        }
+        let tx = transactions.remove(index);
+        replay(&transactions, self.method)?;
+        self.transactions = transactions;
+        Ok(tx)
+    }
+
+    pub fn lots(&self) -> Result<LotBook, LedgerError> {
+        replay(&self.transactions, self.method)
+    }
+
+    pub fn portfolio(&self) -> Result<Portfolio, LedgerError> {
+        Ok(self.lots()?.portfolio())
+    }
+}
+
+fn replay(transactions: &[Transaction], method: CostMethod) -> Result<LotBook, LedgerError> {
+    let mut book = LotBook::new(method);
+    let mut last: Option<NaiveDate> = None;
+    for tx in transactions {
+        if let Some(last) = last.filter(|x| tx.date < *x) {
+            return Err(LedgerError::OutOfOrder {
+                date: tx.date,
+                last,
+            });
+        }
+        last = Some(tx.date);
+        apply(&mut book, tx)?;
+    }
+    Ok(book)
+}
+
+fn apply(book: &mut LotBook, tx: &Transaction) -> Result<(), LedgerError> {
+    if tx.code.trim().is_empty() {
+        return Err(LedgerError::Invalid("code"));
+    }
+    if tx.kind.has_qty() && tx.qty <= Decimal::ZERO {
+        return Err(LedgerError::Invalid("quantity"));
+    }
+    if tx.kind.has_price() && tx.price.is_sign_negative() {
+        return Err(LedgerError::Invalid("price"));
+    }
+    if tx.kind.has_amount() && tx.amount.is_sign_negative() {
+        return Err(LedgerError::Invalid("amount"));
+    }
+
+    let (code, account) = (tx.code.as_str(), tx.account.as_str());
+    match tx.kind {
+        // commission is part of the cost
+        TxKind::Buy => book.open(Lot {
+            code: tx.code.clone(),
+            account: tx.account.clone(),
+            opened: tx.date,
+            qty: tx.qty,
+            cost: tx.qty * tx.price + tx.amount,
+        }),
+        TxKind::Sell => {
+            let proceeds = tx.qty * tx.price - tx.amount;
+            book.sell(tx.date, code, account, tx.qty, proceeds, tx.lot)?
+        }
+        TxKind::Transfer => {
+            let to = tx.to_account.trim();
+            if to.is_empty() || to == tx.account {
+                return Err(LedgerError::Invalid("target account"));
+            }
+            book.transfer(code, account, to, tx.qty, tx.lot)?
+        }
+        TxKind::Dividend => book.adjust_cost(code, account, -tx.amount)?,
+        TxKind::Fee => book.adjust_cost(code, account, tx.amount)?,
+        TxKind::Bonus => book.bonus(code, account, tx.qty)?,
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use rust_decimal::prelude::FromPrimitive;
+
+    use crate::model::portfolio::Position;
+
+    use super::*;
+
+    fn d(x: &str) -> Decimal {
+        x.parse().unwrap()
+    }
+
+    fn tx(date: &str, kind: TxKind, code: &str, qty: f64, price: f64, amount: f64) -> Transaction {
+        let d = |x| Decimal::from_f64(x).unwrap();
+        Transaction {
+            date: NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap(),
+            kind,
+            code: code.to_string(),
+            account: "main".to_string(),
+            qty: d(qty),
+            price: d(price),
+            amount: d(amount),
+            ..Default::default()
+        }
+    }
+
+    fn position(ledger: &Ledger, code: &str, account: &str) -> Option<Position> {
+        ledger
+            .portfolio()
+            .unwrap()
+            .positions
+            .into_iter()
+            .find(|x| x.code == code && x.account == account)
+    }
+
+    #[test]
+    fn test_buy_and_sell() {
+        let mut ledger = Ledger::default();
+        ledger
+            .push(tx("2025-01-02", TxKind::Buy, "sh601127", 100.0, 10.0, 5.0))
+            .unwrap();
+        ledger
+            .push(tx("2025-01-03", TxKind::Buy, "sh601127", 100.0, 12.0, 5.0))
+            .unwrap();
+        let p = position(&ledger, "sh601127", "main").unwrap();
+        assert_eq!(p.qty, d("200"));
+        assert_eq!(p.avg_cost, d("11.05"));
+
+        // selling keeps the average cost
+        ledger
+            .push(tx("2025-01-03", TxKind::Sell, "sh601127", 50.0, 13.0, 5.0))
+            .unwrap();
+        let p = position(&ledger, "sh601127", "main").unwrap();
+        assert_eq!(p.qty, d("150"));
+        assert_eq!(p.avg_cost, d("11.05"));
+
+        ledger
+            .push(tx("2025-01-04", TxKind::Sell, "sh601127", 150.0, 13.0, 5.0))
+            .unwrap();
+        assert!(ledger.portfolio().unwrap().positions.is_empty());
+    }
+
+    #[test]
+    fn test_validation() {
+        let mut ledger = Ledger::default();
+        ledger
+            .push(tx("2025-01-02", TxKind::Buy, "sh601127", 100.0, 10.0, 0.0))
+            .unwrap();
+
+        assert_eq!(
+            ledger.push(tx("2025-01-03", TxKind::Sell, "sh601127", 101.0, 10.0, 0.0)),
+            Err(LedgerError::Oversell {
+                code: "sh601127".into(),
+                account: "main".into(),
+                held: Decimal::from(100),
+                qty: Decimal::from(101),
+            })
+        );
+        // a backdated sell leaves nothing for the later one
+        ledger
+            .push(tx("2025-01-05", TxKind::Sell, "sh601127", 60.0, 10.0, 0.0))
+            .unwrap();
+        assert!(matches!(
+            ledger.push(tx("2025-01-04", TxKind::Sell, "sh601127", 50.0, 10.0, 0.0)),
+            Err(LedgerError::Oversell { .. })
+        ));
+        ledger.remove(1).unwrap();
+        assert!(matches!(
+            ledger.push(tx(
+                "2025-01-03",
+                TxKind::Dividend,
+                "sz000625",
+                0.0,
+                0.0,
+                10.0
+            )),
+            Err(LedgerError::NoPosition { .. })
+        ));
+        assert_eq!(
+            ledger.push(tx("2025-01-03", TxKind::Buy, "sh601127", 0.0, 10.0, 0.0)),
+            Err(LedgerError::Invalid("quantity"))
+        );
+        assert_eq!(
+            ledger.push(tx("2025-01-03", TxKind::Buy, " ", 1.0, 10.0, 0.0)),
+            Err(LedgerError::Invalid("code"))
+        );
+        // rejected transactions are not kept
+        assert_eq!(ledger.transactions().len(), 1);
+
+        // the sell depends on the buy
+        ledger
+            .push(tx("2025-01-03", TxKind::Sell, "sh601127", 100.0, 10.0, 0.0))
+            .unwrap();
+        assert!(matches!(
+            ledger.remove(0),
+            Err(LedgerError::Oversell { .. })
+        ));
+        assert_eq!(ledger.remove(1).unwrap().kind, TxKind::Sell);
+        assert_eq!(ledger.transactions().len(), 1);
+    }
+
+    #[test]
+    fn test_backdated_push() {
+        let mut ledger = Ledger::default();
+        ledger
+            .push(tx("2025-01-03", TxKind::Sell, "sh601127", 1.0, 10.0, 0.0))
+            .unwrap_err();
+        ledger
+            .push(tx("2025-01-03", TxKind::Buy, "sh601127", 100.0, 12.0, 0.0))
+            .unwrap();
+        ledger
+            .push(tx("2025-01-05", TxKind::Sell, "sh601127", 150.0, 13.0, 0.0))
+            .unwrap_err();
+        // an older buy goes first and covers the sell
+        ledger
+            .push(tx("2025-01-02", TxKind::Buy, "sh601127", 100.0, 10.0, 0.0))
+            .unwrap();
+        ledger
+            .push(tx("2025-01-05", TxKind::Sell, "sh601127", 150.0, 13.0, 0.0))
+            .unwrap();
+        // same day goes after the ones already there
+        ledger
+            .push(tx("2025-01-03", TxKind::Fee, "sh601127", 0.0, 0.0, 5.0))
+            .unwrap();
+
+        let kinds: Vec<(String, TxKind)> = ledger
+            .transactions()
+            .iter()
+            .map(|x| (x.date.to_string(), x.kind))
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                ("2025-01-02".to_string(), TxKind::Buy),
+                ("2025-01-03".to_string(), TxKind::Buy),
+                ("2025-01-03".to_string(), TxKind::Fee),
+                ("2025-01-05".to_string(), TxKind::Sell),
+            ]
+        );
+        let p = position(&ledger, "sh601127", "main").unwrap();
+        assert_eq!(p.qty, d("50"));
+        assert_eq!(p.avg_cost, d("11.025"));
+    }
+
+    #[test]
+    fn test_corporate_actions() {
+        let mut ledger = Ledger::default();
+        ledger
+            .push(tx("2025-01-02", TxKind::Buy, "sh601127", 100.0, 10.0, 0.0))
+            .unwrap();
+        ledger
+            .push(tx(
+                "2025-03-01",
+                TxKind::Dividend,
+                "sh601127",
+                0.0,
+                0.0,
+                100.0,
+            ))
+            .unwrap();
+        assert_eq!(
+            position(&ledger, "sh601127", "main").unwrap().avg_cost,
+            d("9")
+        );
+
+        ledger
+            .push(tx("2025-04-01", TxKind::Bonus, "sh601127", 50.0, 0.0, 0.0))
+            .unwrap();
+        let p = position(&ledger, "sh601127", "main").unwrap();
+        assert_eq!(p.qty, d("150"));
+        assert_eq!(p.avg_cost, d("6"));
+
+        ledger
+            .push(tx("2025-05-01", TxKind::Fee, "sh601127", 0.0, 0.0, 15.0))
+            .unwrap();
+        assert_eq!(
+            position(&ledger, "sh601127", "main").unwrap().avg_cost,
+            d("6.1")
+        );
+    }
+
+    #[test]
+    fn test_transfer() {
+        let mut ledger = Ledger::default();
+        ledger
+            .push(tx("2025-01-02", TxKind::Buy, "sh601127", 100.0, 10.0, 0.0))
+            .unwrap();
+
+        let mut transfer = tx("2025-01-03", TxKind::Transfer, "sh601127", 40.0, 0.0, 0.0);
+        assert_eq!(
+            ledger.push(transfer.clone()),
+            Err(LedgerError::Invalid("target account"))
+        );
+        transfer.to_account = "ira".to_string();
+        ledger.push(transfer).unwrap();
+
+        assert_eq!(position(&ledger, "sh601127", "main").unwrap().qty, d("60"));
+        let moved = position(&ledger, "sh601127", "ira").unwrap();
+        assert_eq!(moved.qty, d("40"));
+        assert_eq!(moved.avg_cost, d("10"));
+    }
+
+    #[test]
+    fn test_cost_methods() {
+        let mut ledger = Ledger::default();
+        ledger
+            .push(tx("2024-03-01", TxKind::Buy, "sh601127", 100.0, 10.0, 5.0))
+            .unwrap();
+        ledger
+            .push(tx("2025-01-02", TxKind::Buy, "sh601127", 100.0, 12.0, 5.0))
+            .unwrap();
+        let mut sell = tx("2025-02-03", TxKind::Sell, "sh601127", 50.0, 13.0, 5.0);
+        sell.lot = NaiveDate::from_ymd_opt(2025, 1, 2);
+        ledger.push(sell).unwrap();
+
+        let gain = |ledger: &Ledger| ledger.lots().unwrap().realized()[0].gain();
+        // 645 net proceeds against 2210 / 200 per share
+        assert_eq!(gain(&ledger), Decimal::new(925, 1));
+        ledger.set_method(CostMethod::Fifo).unwrap();
+        assert_eq!(gain(&ledger), Decimal::new(1425, 1));
+        ledger.set_method(CostMethod::Specific).unwrap();
+        assert_eq!(gain(&ledger), Decimal::new(425, 1));
+
+        let mut sell = tx("2025-02-04", TxKind::Sell, "sh601127", 60.0, 13.0, 0.0);
+        sell.lot = NaiveDate::from_ymd_opt(2025, 1, 2);
+        assert!(matches!(ledger.push(sell), Err(LedgerError::NoLot { .. })));
+        // the lot date only matters to the specific method
+        ledger.set_method(CostMethod::Lifo).unwrap();
+        assert_eq!(gain(&ledger), Decimal::new(425, 1));
+    }
+
+    #[test]
+    fn test_serde_roundtrip() {
+        let mut ledger = Ledger::default();
+        ledger
+            .push(tx("2025-01-02", TxKind::Buy, "sh601127", 100.0, 10.0, 5.0))
+            .unwrap();
+        let json = serde_json::to_string(&ledger).unwrap();
+        let back: Ledger = serde_json::from_str(&json).unwrap();
+        assert_eq!(back.transactions(), ledger.transactions());
+    }
+}
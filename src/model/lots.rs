@@ -0,0 +1,556 @@
+use std::collections::BTreeMap;
+
+use chrono::{Datelike, NaiveDate};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::error::LedgerError;
+
+use super::portfolio::{Portfolio, Position};
+
+// money split between lots is kept to the cent, the remainder goes to the
+// last piece so totals stay exact
+const MONEY_DP: u32 = 2;
+
+/// Which lots a sell or transfer takes shares from.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CostMethod {
+    Fifo,
+    Lifo,
+    /// Lots are pooled at their average cost (移动平均), as A-share brokers do.
+    #[default]
+    Average,
+    /// The lot opened on the transaction's `lot` date, FIFO when it is not set.
+    Specific,
+}
+
+impl CostMethod {
+    pub const ALL: [CostMethod; 4] = [
+        CostMethod::Fifo,
+        CostMethod::Lifo,
+        CostMethod::Average,
+        CostMethod::Specific,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            CostMethod::Fifo => "FIFO",
+            CostMethod::Lifo => "LIFO",
+            CostMethod::Average => "均价",
+            CostMethod::Specific => "指定批次",
+        }
+    }
+}
+
+/// Shares bought together, `cost` includes commissions.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Lot {
+    pub code: String,
+    pub account: String,
+    pub opened: NaiveDate,
+    pub qty: Decimal,
+    pub cost: Decimal,
+}
+
+impl Lot {
+    pub fn unit_cost(&self) -> Decimal {
+        if self.qty.is_zero() {
+            Decimal::ZERO
+        } else {
+            self.cost / self.qty
+        }
+    }
+
+    pub fn unrealized(&self, price: Decimal) -> Decimal {
+        self.qty * price - self.cost
+    }
+
+    fn is_of(&self, code: &str, account: &str) -> bool {
+        self.code == code && self.account == account
+    }
+}
+
+/// The part of a lot closed by one sell.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Realized {
+    pub date: NaiveDate,
+    pub code: String,
+    pub account: String,
+    pub opened: NaiveDate,
+    pub qty: Decimal,
+    /// Net of commission.
+    pub proceeds: Decimal,
+    pub cost: Decimal,
+}
+
+impl Realized {
+    pub fn gain(&self) -> Decimal {
+        self.proceeds - self.cost
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct YearReport {
+    pub year: i32,
+    pub proceeds: Decimal,
+    pub cost: Decimal,
+    pub gain: Decimal,
+}
+
+/// Open lots and closed pieces of a replayed ledger.
+#[derive(Clone, Debug, Default)]
+pub struct LotBook {
+    method: CostMethod,
+    // ordered by opening date
+    lots: Vec<Lot>,
+    realized: Vec<Realized>,
+}
+
+impl LotBook {
+    pub fn new(method: CostMethod) -> Self {
+        Self {
+            method,
+            ..Default::default()
+        }
+    }
+
+    pub fn method(&self) -> CostMethod {
+        self.method
+    }
+
+    pub fn lots(&self) -> &[Lot] {
+        &self.lots
+    }
+
+    pub fn realized(&self) -> &[Realized] {
+        &self.realized
+    }
+
+    pub fn held(&self, code: &str, account: &str) -> Decimal {
+        self.lots
+            .iter()
+            .filter(|x| x.is_of(code, account))
+            .map(|x| x.qty)
+            .sum()
+    }
+
+    pub fn open(&mut self, lot: Lot) {
+        let at = self.lots.partition_point(|x| x.opened <= lot.opened);
+        self.lots.insert(at, lot);
+    }
+
+    /// Closes `qty` shares, `proceeds` are split between the lots by quantity.
+    pub fn sell(
+        &mut self,
+        date: NaiveDate,
+        code: &str,
+        account: &str,
+        qty: Decimal,
+        proceeds: Decimal,
+        lot: Option<NaiveDate>,
+    ) -> Result<(), LedgerError> {
+        let pieces = self.take(code, account, qty, lot)?;
+        let shares = split(proceeds, pieces.iter().map(|x| x.qty), qty);
+        for (piece, proceeds) in pieces.into_iter().zip(shares) {
+            self.realized.push(Realized {
+                date,
+                code: piece.code,
+                account: piece.account,
+                opened: piece.opened,
+                qty: piece.qty,
+                proceeds,
+                cost: piece.cost,
+            });
+        }
+        Ok(())
+    }
+
+    /// Moves `qty` shares to `to`, keeping their opening dates and cost.
+    pub fn transfer(
+        &mut self,
+        code: &str,
+        account: &str,
+        to: &str,
+        qty: Decimal,
+        lot: Option<NaiveDate>,
+    ) -> Result<(), LedgerError> {
+        for mut piece in self.take(code, account, qty, lot)? {
+            piece.account = to.to_string();
+            self.open(piece);
+        }
+        Ok(())
+    }
+
+    /// Spreads `amount` over the cost of the open lots by quantity, negative
+    /// for dividends.
+    pub fn adjust_cost(
+        &mut self,
+        code: &str,
+        account: &str,
+        amount: Decimal,
+    ) -> Result<(), LedgerError> {
+        let held = self.held_or_err(code, account)?;
+        let shares = split(amount, self.qtys(code, account), held);
+        let lots = self.lots.iter_mut().filter(|x| x.is_of(code, account));
+        for (lot, share) in lots.zip(shares) {
+            lot.cost += share;
+        }
+        Ok(())
+    }
+
+    /// Bonus shares are added to every open lot by quantity at no cost.
+    pub fn bonus(&mut self, code: &str, account: &str, qty: Decimal) -> Result<(), LedgerError> {
+        let held = self.held_or_err(code, account)?;
+        let qtys = self.qtys(code, account);
+        let count = qtys.len();
+        let mut left = qty;
+        let lots = self.lots.iter_mut().filter(|x| x.is_of(code, account));
+        for (i, (lot, lot_qty)) in lots.zip(qtys).enumerate() {
+            let share = if i + 1 == count {
+                left
+            } else {
+                qty * lot_qty / held
+            };
+            lot.qty += share;
+            left -= share;
+        }
+        Ok(())
+    }
+
+    /// Positions at the average cost of their open lots, in opening order.
+    pub fn portfolio(&self) -> Portfolio {
+        let mut positions: Vec<(String, String, Decimal, Decimal)> = vec![];
+        for lot in self.lots.iter() {
+            match positions
+                .iter_mut()
+                .find(|x| x.0 == lot.code && x.1 == lot.account)
+            {
+                Some(p) => {
+                    p.2 += lot.qty;
+                    p.3 += lot.cost;
+                }
+                None => positions.push((lot.code.clone(), lot.account.clone(), lot.qty, lot.cost)),
+            }
+        }
+        Portfolio {
+            positions: positions
+                .into_iter()
+                .map(|(code, account, qty, cost)| Position {
+                    code,
+                    account,
+                    qty,
+                    avg_cost: cost / qty,
+                })
+                .collect(),
+        }
+    }
+
+    /// Realized gains per calendar year of the sell.
+    pub fn yearly(&self) -> Vec<YearReport> {
+        let mut years: BTreeMap<i32, YearReport> = BTreeMap::new();
+        for r in self.realized.iter() {
+            let year = r.date.year();
+            let report = years.entry(year).or_insert_with(|| YearReport {
+                year,
+                ..Default::default()
+            });
+            report.proceeds += r.proceeds;
+            report.cost += r.cost;
+            report.gain += r.gain();
+        }
+        years.into_values().collect()
+    }
+
+    fn held_or_err(&self, code: &str, account: &str) -> Result<Decimal, LedgerError> {
+        match self.held(code, account) {
+            x if x.is_zero() => Err(LedgerError::NoPosition {
+                code: code.to_string(),
+                account: account.to_string(),
+            }),
+            x => Ok(x),
+        }
+    }
+
+    /// Removes `qty` shares from the lots picked by the method, returns the
+    /// pieces taken.
+    fn take(
+        &mut self,
+        code: &str,
+        account: &str,
+        qty: Decimal,
+        lot: Option<NaiveDate>,
+    ) -> Result<Vec<Lot>, LedgerError> {
+        let held = self.held(code, account);
+        if qty > held {
+            return Err(LedgerError::Oversell {
+                code: code.to_string(),
+                account: account.to_string(),
+                held,
+                qty,
+            });
+        }
+
+        if self.method == CostMethod::Average {
+            self.pool(code, account);
+        }
+        let mut order: Vec<usize> = match (self.method, lot) {
+            (CostMethod::Specific, Some(date)) => {
+                let order: Vec<usize> = self.indices(code, account, |x| x.opened == date);
+                let in_lot: Decimal = order.iter().map(|&i| self.lots[i].qty).sum();
+                if in_lot < qty {
+                    return Err(LedgerError::NoLot {
+                        code: code.to_string(),
+                        account: account.to_string(),
+                        date,
+                    });
+                }
+                order
+            }
+            _ => self.indices(code, account, |_| true),
+        };
+        if self.method == CostMethod::Lifo {
+            order.reverse();
+        }
+
+        let mut pieces = vec![];
+        let mut left = qty;
+        for i in order {
+            if left.is_zero() {
+                break;
+            }
+            let lot = &mut self.lots[i];
+            if lot.qty <= left {
+                left -= lot.qty;
+                pieces.push(lot.clone());
+                lot.qty = Decimal::ZERO;
+                lot.cost = Decimal::ZERO;
+            } else {
+                let cost = (lot.cost * left / lot.qty).round_dp(MONEY_DP);
+                pieces.push(Lot {
+                    qty: left,
+                    cost,
+                    ..lot.clone()
+                });
+                lot.qty -= left;
+                lot.cost -= cost;
+                left = Decimal::ZERO;
+            }
+        }
+        self.lots.retain(|x| !x.qty.is_zero());
+        Ok(pieces)
+    }
+
+    /// Merges the lots of a position into one opened on the earliest date.
+    fn pool(&mut self, code: &str, account: &str) {
+        let Some(first) = self.lots.iter().position(|x| x.is_of(code, account)) else {
+            return;
+        };
+        let (qty, cost) = self
+            .lots
+            .iter()
+            .filter(|x| x.is_of(code, account))
+            .fold((Decimal::ZERO, Decimal::ZERO), |(q, c), x| {
+                (q + x.qty, c + x.cost)
+            });
+        self.lots[first].qty = qty;
+        self.lots[first].cost = cost;
+        let mut i = 0;
+        self.lots.retain(|x| {
+            i += 1;
+            i - 1 == first || !x.is_of(code, account)
+        });
+    }
+
+    fn qtys(&self, code: &str, account: &str) -> Vec<Decimal> {
+        self.lots
+            .iter()
+            .filter(|x| x.is_of(code, account))
+            .map(|x| x.qty)
+            .collect()
+    }
+
+    fn indices(&self, code: &str, account: &str, f: impl Fn(&Lot) -> bool) -> Vec<usize> {
+        (0..self.lots.len())
+            .filter(|&i| self.lots[i].is_of(code, account) && f(&self.lots[i]))
+            .collect()
+    }
+}
+
+/// Splits `amount` by `weights` out of `total`, the last share takes the
+/// rounding remainder.
+fn split(
+    amount: Decimal,
+    weights: impl IntoIterator<Item = Decimal>,
+    total: Decimal,
+) -> Vec<Decimal> {
+    let weights: Vec<Decimal> = weights.into_iter().collect();
+    let mut left = amount;
+    let mut shares = Vec::with_capacity(weights.len());
+    for (i, w) in weights.iter().enumerate() {
+        let share = if i + 1 == weights.len() {
+            left
+        } else {
+            (amount * w / total).round_dp(MONEY_DP)
+        };
+        left -= share;
+        shares.push(share);
+    }
+    shares
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn d(x: &str) -> Decimal {
+        x.parse().unwrap()
+    }
+
+    fn date(x: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(x, "%Y-%m-%d").unwrap()
+    }
+
+    fn lot(opened: &str, qty: &str, cost: &str) -> Lot {
+        Lot {
+            code: "sh601127".to_string(),
+            account: "main".to_string(),
+            opened: date(opened),
+            qty: d(qty),
+            cost: d(cost),
+        }
+    }
+
+    fn book(method: CostMethod) -> LotBook {
+        let mut book = LotBook::new(method);
+        book.open(lot("2024-03-01", "100", "1005"));
+        book.open(lot("2024-06-01", "100", "1205"));
+        book.open(lot("2025-01-02", "100", "1505"));
+        book
+    }
+
+    fn sell(book: &mut LotBook, day: &str, qty: &str, proceeds: &str) {
+        book.sell(date(day), "sh601127", "main", d(qty), d(proceeds), None)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_fifo_and_lifo() {
+        let mut fifo = book(CostMethod::Fifo);
+        sell(&mut fifo, "2025-02-01", "150", "2095");
+        let closed: Vec<_> = fifo.realized().iter().map(|x| (x.opened, x.cost)).collect();
+        assert_eq!(
+            closed,
+            [
+                (date("2024-03-01"), d("1005")),
+                (date("2024-06-01"), d("602.5"))
+            ]
+        );
+        // proceeds split 2:1
+        assert_eq!(fifo.realized()[0].proceeds, d("1396.67"));
+        assert_eq!(fifo.realized()[1].proceeds, d("698.33"));
+        assert_eq!(fifo.held("sh601127", "main"), d("150"));
+        assert_eq!(fifo.lots()[0].cost, d("602.5"));
+
+        let mut lifo = book(CostMethod::Lifo);
+        sell(&mut lifo, "2025-02-01", "150", "2095");
+        assert_eq!(lifo.realized()[0].opened, date("2025-01-02"));
+        assert_eq!(lifo.realized()[1].cost, d("602.5"));
+        let gain: Decimal = lifo.realized().iter().map(|x| x.gain()).sum();
+        assert_eq!(gain, d("2095") - d("1505") - d("602.5"));
+    }
+
+    #[test]
+    fn test_average_cost() {
+        let mut book = book(CostMethod::Average);
+        sell(&mut book, "2025-02-01", "100", "1395");
+        // 3715 / 300 per share
+        assert_eq!(book.realized()[0].cost, d("1238.33"));
+        assert_eq!(book.lots().len(), 1);
+        assert_eq!(book.lots()[0].opened, date("2024-03-01"));
+        assert_eq!(book.lots()[0].cost, d("2476.67"));
+        // nothing lost to rounding
+        assert_eq!(book.realized()[0].cost + book.lots()[0].cost, d("3715"));
+    }
+
+    #[test]
+    fn test_specific_lot() {
+        let mut book = book(CostMethod::Specific);
+        book.sell(
+            date("2025-02-01"),
+            "sh601127",
+            "main",
+            d("50"),
+            d("700"),
+            Some(date("2024-06-01")),
+        )
+        .unwrap();
+        assert_eq!(book.realized()[0].cost, d("602.5"));
+        assert_eq!(
+            book.sell(
+                date("2025-02-01"),
+                "sh601127",
+                "main",
+                d("60"),
+                d("700"),
+                Some(date("2024-06-01")),
+            ),
+            Err(LedgerError::NoLot {
+                code: "sh601127".into(),
+                account: "main".into(),
+                date: date("2024-06-01"),
+            })
+        );
+        // without a lot it falls back to FIFO
+        sell(&mut book, "2025-02-02", "10", "140");
+        assert_eq!(book.realized()[1].opened, date("2024-03-01"));
+    }
+
+    #[test]
+    fn test_yearly_report() {
+        let mut book = book(CostMethod::Fifo);
+        sell(&mut book, "2024-12-31", "100", "1100");
+        sell(&mut book, "2025-01-02", "100", "1100");
+        sell(&mut book, "2025-06-30", "50", "800");
+        let years = book.yearly();
+        assert_eq!(
+            years,
+            [
+                YearReport {
+                    year: 2024,
+                    proceeds: d("1100"),
+                    cost: d("1005"),
+                    gain: d("95"),
+                },
+                YearReport {
+                    year: 2025,
+                    proceeds: d("1900"),
+                    cost: d("1957.5"),
+                    gain: d("-57.5"),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_corporate_actions_keep_lots() {
+        let mut book = book(CostMethod::Fifo);
+        book.adjust_cost("sh601127", "main", d("-30")).unwrap();
+        assert_eq!(book.lots()[0].cost, d("995"));
+        assert_eq!(book.lots()[2].cost, d("1495"));
+
+        book.bonus("sh601127", "main", d("90")).unwrap();
+        assert_eq!(book.lots()[0].qty, d("130"));
+        assert_eq!(book.held("sh601127", "main"), d("390"));
+
+        book.transfer("sh601127", "main", "ira", d("130"), None)
+            .unwrap();
+        assert_eq!(book.lots()[0].account, "ira");
+        assert_eq!(book.lots()[0].opened, date("2024-03-01"));
+        assert_eq!(book.held("sh601127", "main"), d("260"));
+
+        assert!(matches!(
+            book.adjust_cost("sz000625", "main", d("10")),
+            Err(LedgerError::NoPosition { .. })
+        ));
+    }
+}
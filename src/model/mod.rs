@@ -1,23 +1,56 @@
+use serde::{Deserialize, Serialize};
 use stock::Klines;
+use timeshare::TimeShare;
 
+pub mod bars;
+pub mod calendar;
+pub mod crypto;
+pub mod indicators;
+pub mod kline_store;
+pub mod ledger;
+pub mod lots;
+pub mod portfolio;
+pub mod price;
+pub mod provider;
 pub mod stock;
+pub mod symbols;
+pub mod timeshare;
 
-#[derive(Clone, Default, Debug)]
+pub use price::{Percent, Price, Qty};
+pub use provider::{CryptoProvider, QuoteProvider, Symbol};
+
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
 pub struct Stock {
     pub name: String,
     pub code: String,
     pub data: StockData,
     pub kline: Klines,
+    #[serde(default)]
+    pub minutes: TimeShare,
 }
 
 impl Stock {
+    #[inline]
+    pub fn market(&self) -> Market {
+        Market::from_code(&self.code)
+    }
+
     #[inline]
     pub fn data_new(&self) -> Price {
         self.data.new
     }
 
+    /// The latest trade, or the previous close while suspended (quoted 0).
     #[inline]
-    pub fn data_rise_per(&self) -> Price {
+    pub fn last_price(&self) -> Price {
+        match self.data.new {
+            x if x > Price::ZERO => x,
+            _ => self.data.closing,
+        }
+    }
+
+    #[inline]
+    pub fn data_rise_per(&self) -> Percent {
         self.data.rise_per
     }
 
@@ -32,10 +65,56 @@ impl Stock {
     }
 }
 
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Market {
+    #[default]
+    ZhShare,
+    HkShare,
+    UsShare,
+}
+
+impl Market {
+    /// Market of a sina style code, e.g. `sh601127`, `hk00700`, `gb_aapl`.
+    pub fn from_code(code: &str) -> Self {
+        if code.starts_with("hk") {
+            Market::HkShare
+        } else if code.starts_with("gb_") {
+            Market::UsShare
+        } else {
+            Market::ZhShare
+        }
+    }
+
+    pub fn currency(&self) -> &'static str {
+        match self {
+            Market::ZhShare => "CNY",
+            Market::HkShare => "HKD",
+            Market::UsShare => "USD",
+        }
+    }
+}
+
+/// Whether `code` looks like a sina symbol: `sh600000`, `hk00700`, `hkHSI`,
+/// `gb_aapl` or `gb_$dji`.
+pub fn valid_code(code: &str) -> bool {
+    let digits = |x: &str, n: usize| x.len() == n && x.bytes().all(|b| b.is_ascii_digit());
+    if let Some(rest) = code.strip_prefix("gb_") {
+        !rest.is_empty()
+            && rest
+                .bytes()
+                .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'.' || b == b'$')
+    } else if let Some(rest) = code.strip_prefix("hk") {
+        digits(rest, 5) || (!rest.is_empty() && rest.bytes().all(|b| b.is_ascii_uppercase()))
+    } else {
+        ["sh", "sz", "bj"]
+            .iter()
+            .any(|x| code.strip_prefix(x).is_some_and(|rest| digits(rest, 6)))
+    }
+}
+
 pub type Vol = u64;
-pub type Price = f32;
 
-#[derive(Clone, Default, Debug)]
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
 pub struct StockData {
     pub date: String,
     pub time: String,
@@ -44,11 +123,21 @@ pub struct StockData {
     pub hight: Price,
     pub low: Price,
     pub vol: Vol,
-    pub amount: f32,
+    pub amount: Price,
     pub bid: Price,
     pub ask: Price,
     pub new: Price,
-    pub rise_per: f32,
+    pub rise_per: Percent,
     pub bids: Vec<(Vol, Price)>,
     pub asks: Vec<(Vol, Price)>,
+    pub ext: Option<ExtendedHours>,
+}
+
+/// Pre or post market quote of us shares.
+#[derive(Clone, Default, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ExtendedHours {
+    pub price: Price,
+    pub rise_per: Percent,
+    pub vol: Vol,
+    pub time: String,
 }
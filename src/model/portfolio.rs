@@ -0,0 +1,230 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use super::{price::to_f64, Market, Percent, Price, Qty, Stock};
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Position {
+    pub code: String,
+    pub qty: Qty,
+    pub avg_cost: Price,
+    pub account: String,
+}
+
+impl Position {
+    #[inline]
+    pub fn market(&self) -> Market {
+        Market::from_code(&self.code)
+    }
+
+    #[inline]
+    pub fn cost(&self) -> Price {
+        self.qty * self.avg_cost
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Portfolio {
+    pub positions: Vec<Position>,
+}
+
+/// A position valued at the latest quote.
+#[derive(Clone, Debug, Default)]
+pub struct Holding {
+    pub position: Position,
+    pub name: String,
+    /// `None` until the code is quoted.
+    pub price: Option<Price>,
+    pub market_value: Price,
+    pub day_pnl: Price,
+    pub total_pnl: Price,
+    /// Share of the market value among holdings of the same currency.
+    pub weight: f64,
+}
+
+impl Holding {
+    pub fn total_pnl_per(&self) -> Percent {
+        let cost = self.position.cost();
+        if cost.is_zero() {
+            Percent::ZERO
+        } else {
+            self.total_pnl / cost * Percent::ONE_HUNDRED
+        }
+    }
+}
+
+/// Totals of one currency.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Summary {
+    pub market: Market,
+    pub market_value: Price,
+    pub day_pnl: Price,
+    pub total_pnl: Price,
+}
+
+impl Portfolio {
+    /// Joins positions with live quotes, unquoted positions are valued at cost.
+    pub fn holdings(&self, quotes: &HashMap<String, Stock>) -> Vec<Holding> {
+        let mut holdings: Vec<Holding> = self
+            .positions
+            .iter()
+            .map(|p| {
+                let Some(stock) = quotes.get(&p.code) else {
+                    return Holding {
+                        position: p.clone(),
+                        name: p.code.clone(),
+                        market_value: p.cost(),
+                        ..Default::default()
+                    };
+                };
+                let closing = stock.data.closing;
+                let price = stock.last_price();
+                let market_value = p.qty * price;
+                let day_pnl = if closing > Price::ZERO {
+                    p.qty * (price - closing)
+                } else {
+                    Price::ZERO
+                };
+
+                Holding {
+                    position: p.clone(),
+                    name: stock.name.clone(),
+                    price: Some(price),
+                    market_value,
+                    day_pnl,
+                    total_pnl: market_value - p.cost(),
+                    weight: 0.0,
+                }
+            })
+            .collect();
+
+        let totals = summary(&holdings);
+        for h in holdings.iter_mut() {
+            let total = totals
+                .iter()
+                .find(|x| x.market == h.position.market())
+                .map(|x| x.market_value)
+                .unwrap_or_default();
+            if total > Price::ZERO {
+                h.weight = to_f64(h.market_value / total);
+            }
+        }
+        holdings
+    }
+}
+
+/// Totals per currency, currencies without holdings are left out.
+pub fn summary(holdings: &[Holding]) -> Vec<Summary> {
+    [Market::ZhShare, Market::HkShare, Market::UsShare]
+        .into_iter()
+        .filter_map(|market| {
+            let of_market = holdings.iter().filter(|h| h.position.market() == market);
+            of_market.clone().next()?;
+            Some(of_market.fold(
+                Summary {
+                    market,
+                    ..Default::default()
+                },
+                |mut s, h| {
+                    s.market_value += h.market_value;
+                    s.day_pnl += h.day_pnl;
+                    s.total_pnl += h.total_pnl;
+                    s
+                },
+            ))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::model::StockData;
+
+    use super::*;
+
+    fn d(x: &str) -> Price {
+        x.parse().unwrap()
+    }
+
+    fn position(code: &str, qty: &str, avg_cost: &str, account: &str) -> Position {
+        Position {
+            code: code.to_string(),
+            qty: d(qty),
+            avg_cost: d(avg_cost),
+            account: account.to_string(),
+        }
+    }
+
+    fn quote(code: &str, new: &str, closing: &str) -> (String, Stock) {
+        let stock = Stock {
+            name: format!("{code} name"),
+            code: code.to_string(),
+            data: StockData {
+                new: d(new),
+                closing: d(closing),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        (code.to_string(), stock)
+    }
+
+    #[test]
+    fn test_holdings() {
+        let portfolio = Portfolio {
+            positions: vec![
+                position("sh601127", "100", "10", "main"),
+                position("sz000625", "200", "15", "main"),
+                position("gb_aapl", "10", "200", "us"),
+                position("hk00700", "100", "300", "hk"),
+            ],
+        };
+
+        let quotes = HashMap::from([
+            quote("sh601127", "12", "11"),
+            // suspended
+            quote("sz000625", "0", "14"),
+            quote("gb_aapl", "220", "225"),
+        ]);
+        let holdings = portfolio.holdings(&quotes);
+
+        let zh = &holdings[0];
+        assert_eq!(zh.name, "sh601127 name");
+        assert_eq!(zh.price, Some(d("12")));
+        assert_eq!(zh.market_value, d("1200"));
+        assert_eq!(zh.day_pnl, d("100"));
+        assert_eq!(zh.total_pnl, d("200"));
+        assert_eq!(zh.total_pnl_per(), d("20"));
+        assert_eq!(zh.weight, 1200.0 / 4000.0);
+
+        let suspended = &holdings[1];
+        assert_eq!(suspended.price, Some(d("14")));
+        assert_eq!(suspended.day_pnl, d("0"));
+        assert_eq!(suspended.total_pnl, d("-200"));
+        assert_eq!(suspended.weight, 2800.0 / 4000.0);
+
+        // the only usd holding
+        let us = &holdings[2];
+        assert_eq!(us.day_pnl, d("-50"));
+        assert_eq!(us.weight, 1.0);
+
+        let unquoted = &holdings[3];
+        assert_eq!(unquoted.price, None);
+        assert_eq!(unquoted.market_value, d("30000"));
+        assert_eq!(unquoted.total_pnl, d("0"));
+
+        let totals = summary(&holdings);
+        assert_eq!(totals.len(), 3);
+        assert_eq!(
+            totals[0],
+            Summary {
+                market: Market::ZhShare,
+                market_value: d("4000"),
+                day_pnl: d("100"),
+                total_pnl: d("0"),
+            }
+        );
+        assert_eq!(totals[2].market, Market::UsShare);
+    }
+}
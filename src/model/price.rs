@@ -0,0 +1,143 @@
+use rust_decimal::{prelude::ToPrimitive, Decimal, RoundingStrategy};
+
+use super::Market;
+
+/// Exact decimal, quotes are decoded from their text so nothing is lost to
+/// binary floats.
+pub type Price = Decimal;
+
+/// Change in percent, `1.5` is 1.5%.
+pub type Percent = Decimal;
+
+/// Number of shares, decimal like the ledger amounts.
+pub type Qty = Decimal;
+
+// hkex spread table in 0.001 units: (up to and including, tick)
+const HK_SPREADS: [(i64, i64); 10] = [
+    (250, 1),
+    (500, 5),
+    (10_000, 10),
+    (20_000, 20),
+    (100_000, 50),
+    (200_000, 100),
+    (500_000, 200),
+    (1_000_000, 500),
+    (2_000_000, 1_000),
+    (5_000_000, 2_000),
+];
+
+/// Minimum price step of `code` at `price`.
+pub fn tick_size(code: &str, price: Price) -> Price {
+    let thousandths = |x: i64| Decimal::new(x, 3).normalize();
+    match Market::from_code(code) {
+        // funds and bonds quote in 0.001
+        Market::ZhShare
+            if ["sh5", "sh11", "sz12", "sz15", "sz16", "sz18"]
+                .iter()
+                .any(|x| code.starts_with(x)) =>
+        {
+            thousandths(1)
+        }
+        Market::ZhShare => thousandths(10),
+        Market::HkShare => HK_SPREADS
+            .iter()
+            .find(|(upper, _)| price.abs() <= thousandths(*upper))
+            .map(|(_, tick)| thousandths(*tick))
+            .unwrap_or(thousandths(5_000)),
+        // sub-dollar stocks trade in 0.0001
+        Market::UsShare if price.abs() < Decimal::ONE => Decimal::new(1, 4),
+        Market::UsShare => thousandths(10),
+    }
+}
+
+/// `price` with as many decimals as the tick size of `code`.
+pub fn format_price(code: &str, price: Price) -> String {
+    let dp = tick_size(code, price).scale();
+    format_dp(price, dp)
+}
+
+/// Crypto `price` to about six significant digits. Binance pads every price
+/// to 8 decimals whatever its tick.
+pub fn format_crypto_price(price: Price) -> String {
+    let magnitude = to_f64(price.abs()).log10().floor();
+    let dp = match magnitude.is_finite() {
+        true => (5.0 - magnitude).clamp(2.0, 8.0) as u32,
+        false => 2,
+    };
+    format_dp(price, dp)
+}
+
+/// `x` rounded to `dp` decimals, for money and percents.
+pub fn format_dp(x: Price, dp: u32) -> String {
+    format!("{:.*}", dp as usize, round(x, dp))
+}
+
+/// Turnover in 万/亿.
+pub fn format_amount(amount: Price) -> String {
+    let yi = Decimal::new(100_000_000, 0);
+    let wan = Decimal::new(10_000, 0);
+    match amount.abs() {
+        x if x >= yi => format!("{}亿", format_dp(amount / yi, 2)),
+        x if x >= wan => format!("{}万", format_dp(amount / wan, 2)),
+        _ => format_dp(amount, 2),
+    }
+}
+
+// the way quotes are rounded, formatting alone would truncate
+fn round(x: Price, dp: u32) -> Price {
+    x.round_dp_with_strategy(dp, RoundingStrategy::MidpointAwayFromZero)
+}
+
+/// Plots are drawn in floats.
+#[inline]
+pub fn to_f64(price: Price) -> f64 {
+    price.to_f64().unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn d(x: &str) -> Price {
+        x.parse().unwrap()
+    }
+
+    #[test]
+    fn test_tick_size() {
+        assert_eq!(tick_size("sh601127", d("132.79")), d("0.01"));
+        assert_eq!(tick_size("sh510300", d("3.912")), d("0.001"));
+        assert_eq!(tick_size("sz159915", d("2.1")), d("0.001"));
+
+        assert_eq!(tick_size("hk00700", d("0.25")), d("0.001"));
+        assert_eq!(tick_size("hk00700", d("0.26")), d("0.005"));
+        assert_eq!(tick_size("hk00700", d("9.99")), d("0.01"));
+        assert_eq!(tick_size("hk00700", d("373.6")), d("0.2"));
+        assert_eq!(tick_size("hk00700", d("1500")), d("1"));
+        assert_eq!(tick_size("hk00700", d("6000")), d("5"));
+
+        assert_eq!(tick_size("gb_aapl", d("229.86")), d("0.01"));
+        assert_eq!(tick_size("gb_sndl", d("0.9512")), d("0.0001"));
+    }
+
+    #[test]
+    fn test_format() {
+        assert_eq!(format_price("sh601127", d("132.8")), "132.80");
+        assert_eq!(format_price("sh510300", d("3.9")), "3.900");
+        assert_eq!(format_price("hk00700", d("373.600")), "373.6");
+        assert_eq!(format_price("hk00700", d("9.5")), "9.50");
+        assert_eq!(format_price("gb_sndl", d("0.95")), "0.9500");
+        // rounded half away from zero, not by binary representation
+        assert_eq!(format_price("gb_aapl", d("1.005")), "1.01");
+
+        assert_eq!(format_amount(d("3006594293.000")), "30.07亿");
+        assert_eq!(format_amount(d("54712345")), "5471.23万");
+        assert_eq!(format_amount(d("9999.5")), "9999.50");
+        assert_eq!(format_dp(d("-1.235"), 2), "-1.24");
+
+        assert_eq!(format_crypto_price(d("97123.45000000")), "97123.45");
+        assert_eq!(format_crypto_price(d("3202.10000000")), "3202.10");
+        assert_eq!(format_crypto_price(d("0.31234000")), "0.312340");
+        assert_eq!(format_crypto_price(d("0.00001234")), "0.00001234");
+        assert_eq!(format_crypto_price(d("0.00000000")), "0.00");
+    }
+}
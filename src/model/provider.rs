@@ -0,0 +1,73 @@
+use std::fmt::Debug;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::TrackerError;
+
+use super::{
+    crypto::Coin,
+    stock::{KLineScale, Klines},
+    timeshare::TimeShare,
+    Market, Stock,
+};
+
+/// A symbol returned by [`QuoteProvider::search_symbol`].
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Symbol {
+    pub code: String,
+    pub name: String,
+    pub market: Market,
+    /// Lowercase pinyin initials of the name, e.g. `gzmt`, may be empty.
+    #[serde(default)]
+    pub pinyin: String,
+}
+
+/// Source of market data used by the backend.
+pub trait QuoteProvider: Debug + Send + Sync {
+    /// Realtime quotes for `codes`, one result per returned row so a bad row
+    /// doesn't drop the whole batch.
+    fn fetch_quotes(
+        &self,
+        codes: &[String],
+    ) -> Result<Vec<Result<Stock, TrackerError>>, TrackerError>;
+
+    fn fetch_klines(
+        &self,
+        code: &str,
+        scale: KLineScale,
+        datalen: u32,
+    ) -> Result<Klines, TrackerError>;
+
+    fn search_symbol(&self, keyword: &str) -> Result<Vec<Symbol>, TrackerError>;
+
+    /// Today's minute line, empty where the source has none.
+    fn fetch_minutes(&self, code: &str) -> Result<TimeShare, TrackerError>;
+}
+
+/// Source of crypto spot data.
+pub trait CryptoProvider: Debug + Send + Sync {
+    /// Last price and 24h statistics of `symbols`, e.g. `BTCUSDT`.
+    fn fetch_tickers(&self, symbols: &[String]) -> Result<Vec<Coin>, TrackerError>;
+
+    fn fetch_klines(
+        &self,
+        symbol: &str,
+        scale: KLineScale,
+        limit: u32,
+    ) -> Result<Klines, TrackerError>;
+
+    /// Websocket endpoint pushing tickers, `None` falls back to polling.
+    fn stream_url(&self) -> Option<String> {
+        None
+    }
+
+    /// Subscription request, sent again after every reconnect.
+    fn subscribe_message(&self, _symbols: &[String]) -> String {
+        String::new()
+    }
+
+    /// A pushed ticker, `None` for acks and other messages.
+    fn decode_stream_message(&self, _msg: &str) -> Result<Option<Coin>, TrackerError> {
+        Ok(None)
+    }
+}
@@ -1,16 +1,31 @@
 use std::str::FromStr;
 
-use chrono::{NaiveDate, NaiveDateTime};
+use chrono::{Datelike, NaiveDate, NaiveDateTime, NaiveTime};
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
 use crate::error::TrackerError;
 
-use super::{Price, Stock, StockData, Vol};
+use super::{
+    bars::{same_bar, trading_minute},
+    timeshare::{MinutePoint, TimeShare},
+    ExtendedHours, Market, Percent, Price, QuoteProvider, Stock, StockData, Symbol, Vol,
+};
 
 const BASE_URL: &str = "http://hq.sinajs.cn";
-const MIN_LEN: usize = "var hq_str_cc000000=\"\";".len();
+const KLINE_URL: &str =
+    "https://quotes.sina.cn/cn/api/json_v2.php/CN_MarketDataService.getKLineData";
+const SUGGEST_URL: &str = "https://suggest3.sinajs.cn/suggest/";
+const MINLINE_URL: &str =
+    "https://quotes.sina.cn/cn/api/openapi.php/CN_MinlineService.getMinlineData";
+const HK_KLINE_URL: &str =
+    "https://quotes.sina.cn/hk/api/openapi.php/HK_StockService.getHKDayKLine";
+const US_KLINE_URL: &str =
+    "https://stock.finance.sina.com.cn/usstock/api/json_v2.php/US_MinKService";
 
-#[derive(Default, Debug, Clone)]
+/// One candle. Prices stay `f64`: klines only feed the charts and the
+/// indicators, money figures come from quotes and the ledger.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct KlineItem {
     pub day: NaiveDateTime,
     pub open: f64,
@@ -22,13 +37,16 @@ pub struct KlineItem {
     pub date: String,
 }
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Klines {
     pub scale: KLineScale,
     pub klines: Vec<KlineItem>,
+    /// Rows of the response that didn't decode, left out and reported.
+    #[serde(skip)]
+    pub skipped: Vec<String>,
 }
 
-#[derive(Default, Debug, Clone, PartialEq, Eq)]
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum KLineScale {
     Munute5,
     #[default]
@@ -41,6 +59,46 @@ pub enum KLineScale {
 }
 
 impl KLineScale {
+    pub const ALL: [KLineScale; 7] = [
+        KLineScale::Munute5,
+        KLineScale::Munute15,
+        KLineScale::Munute30,
+        KLineScale::Hour,
+        KLineScale::Day,
+        KLineScale::Week,
+        KLineScale::Month,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            KLineScale::Munute5 => "5分",
+            KLineScale::Munute15 => "15分",
+            KLineScale::Munute30 => "30分",
+            KLineScale::Hour => "60分",
+            KLineScale::Day => "日K",
+            KLineScale::Week => "周K",
+            KLineScale::Month => "月K",
+        }
+    }
+
+    /// Candles shorter than a day.
+    pub fn is_intraday(&self) -> bool {
+        !matches!(self, KLineScale::Day | KLineScale::Week | KLineScale::Month)
+    }
+
+    /// The source has candles of this scale for `market`, hk only has days.
+    pub fn is_available(&self, market: Market) -> bool {
+        market != Market::HkShare || !self.is_intraday()
+    }
+
+    /// The scale a chart of `market` opens with.
+    pub fn default_for(market: Market) -> KLineScale {
+        match market {
+            Market::HkShare => KLineScale::Day,
+            _ => KLineScale::default(),
+        }
+    }
+
     pub fn to_usize(&self) -> usize {
         match self {
             KLineScale::Munute5 => 5,
@@ -69,45 +127,111 @@ impl From<usize> for KLineScale {
     }
 }
 
-impl From<Value> for KlineItem {
-    fn from(value: Value) -> Self {
-        let day_str = value["day"].as_str().unwrap();
+impl TryFrom<Value> for KlineItem {
+    type Error = TrackerError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        let line = value.to_string();
+        // `date` in the hk service
+        let day_str = value["day"]
+            .as_str()
+            .or(value["date"].as_str())
+            .ok_or_else(|| TrackerError::ParseError {
+                field: "day",
+                line: line.clone(),
+            })?;
 
         let date = match NaiveDateTime::parse_from_str(day_str, "%Y-%m-%d %H:%M:%S") {
             Ok(d) => d,
             Err(_) => NaiveDate::parse_from_str(day_str, "%Y-%m-%d")
-                .unwrap()
+                .map_err(|_| TrackerError::ParseError {
+                    field: "day",
+                    line: line.clone(),
+                })?
                 .into(),
         };
 
-        Self {
+        // missing numbers are reported as 0 by sina
+        let number = |field: &'static str| -> Result<f64, TrackerError> {
+            parse_field(value[field].as_str().unwrap_or("0"), field, &line)
+        };
+
+        Ok(Self {
             day: date,
-            open: f64::from_str(value["open"].as_str().unwrap_or("0")).unwrap(),
-            high: f64::from_str(value["high"].as_str().unwrap_or("0")).unwrap(),
-            low: f64::from_str(value["low"].as_str().unwrap_or("0")).unwrap(),
-            close: f64::from_str(value["close"].as_str().unwrap_or("0")).unwrap(),
-            volume: f64::from_str(value["volume"].as_str().unwrap_or("0")).unwrap(),
-            amount: f64::from_str(value["amount"].as_str().unwrap_or("0")).unwrap(),
+            open: number("open")?,
+            high: number("high")?,
+            low: number("low")?,
+            close: number("close")?,
+            volume: number("volume")?,
+            amount: number("amount")?,
             date: day_str.to_string(),
-        }
+        })
     }
 }
 
+/// Candles asked for at a time, older history is paged in by this much.
+pub const KLINE_PAGE: u32 = 100;
+/// Most candles kept per code.
+pub const MAX_KLINES: usize = 1000;
+
 impl Klines {
     pub fn get_klines(code: &str, scale: usize, datalen: u32) -> Result<Klines, TrackerError> {
-        let response = reqwest::blocking::get(format!("https://quotes.sina.cn/cn/api/json_v2.php/CN_MarketDataService.getKLineData?symbol={code}&scale={scale}&ma=no&datalen={datalen}"))?.json::<Vec<Value>>()?;
+        SinaProvider::default().fetch_klines(code, KLineScale::from(scale), datalen)
+    }
 
-        let klines = response
-            .into_iter()
-            .map(KlineItem::from)
-            .collect::<Vec<KlineItem>>();
+    /// Fresh candles replace the held ones from their first candle on, older
+    /// history is kept when they overlap.
+    pub fn merge_newer(&mut self, newer: Klines) {
+        let overlaps = match (self.klines.last(), newer.klines.first()) {
+            (Some(last), Some(first)) => first.day <= last.day,
+            _ => false,
+        };
+        if self.scale != newer.scale || !overlaps {
+            *self = newer;
+            return;
+        }
+        let first = newer.klines[0].day;
+        self.klines.retain(|x| x.day < first);
+        // the held week or month candle carries an earlier day of the same period
+        if self
+            .klines
+            .last()
+            .is_some_and(|x| same_bar(self.scale, x.day, first))
+        {
+            self.klines.pop();
+        }
+        self.klines.extend(newer.klines);
+        self.cap();
+    }
 
-        let kline = Klines {
-            klines: klines,
-            scale: KLineScale::from(scale),
+    /// Puts the candles of `older` before the first one held, returns how
+    /// many were added.
+    pub fn merge_older(&mut self, older: Klines) -> usize {
+        if self.scale != older.scale {
+            return 0;
+        }
+        let Some(first) = self.klines.first().map(|x| x.day) else {
+            *self = older;
+            self.cap();
+            return self.klines.len();
         };
+        let mut items: Vec<KlineItem> =
+            older.klines.into_iter().filter(|x| x.day < first).collect();
+        // never past the bound, the oldest go first
+        let room = MAX_KLINES.saturating_sub(self.klines.len());
+        if items.len() > room {
+            items.drain(..items.len() - room);
+        }
+        let added = items.len();
+        items.append(&mut self.klines);
+        self.klines = items;
+        added
+    }
 
-        Ok(kline)
+    pub(super) fn cap(&mut self) {
+        if self.klines.len() > MAX_KLINES {
+            self.klines.drain(..self.klines.len() - MAX_KLINES);
+        }
     }
 }
 
@@ -132,92 +256,596 @@ impl Stock {
 }
 
 pub fn fetch_data_list(codes: Vec<String>) -> Result<Vec<Stock>, TrackerError> {
-    let code_string = codes.join(",");
-    let url = format!("{}/list={}", BASE_URL, code_string);
-
-    // var hq_str_sh601127 = "赛力斯,133.000,132.800,132.790,135.440,131.010,132.790,132.800,22615984,3006594293.000,25300,132.790,31600,132.780,16400,132.770,9800,132.760,8600,132.750,64500,132.800,16900,132.810,11900,132.820,1000,132.830,1900,132.840,2025-01-27,15:00:01,00,";
-    // "var hq_str_s_sh000001=\"上证指数,3250.6007,-2.0257,-0.06,3874676,45023154\";\nvar hq_str_s_sz399001=\"深证成指,10156.07,-136.663,-1.33,532847417,66764113\";\nvar hq_str_s_sh000300=\"沪深300,3817.0802,-15.7835,-0.41,1466707,28294013\";\n"
-    let str = reqwest::blocking::Client::new()
-        .get(&url)
-        .header("Referer", "https://www.sina.com.cn/")
-        .send()?
-        .text()?;
-
-    let stocks: Vec<Stock> = str
-        .trim()
-        .split('\n')
-        .filter(|x| x.len() > MIN_LEN)
-        .filter_map(|stock_item_str| decode_sina_result(stock_item_str))
+    let stocks = SinaProvider::default()
+        .fetch_quotes(&codes)?
+        .into_iter()
+        .filter_map(Result::ok)
         .collect();
 
     Ok(stocks)
 }
 
-fn decode_sina_result(stock_string: &str) -> Option<Stock> {
-    let mut list: Vec<&str> = stock_string.trim().split(",").collect();
-    // ["var hq_str_sh601127=\"赛力斯", "133.000", "132.800", "132.790", "135.440", "131.010", "132.790", "132.800", "22615984", "3006594293.000", "25300", "132.790", "31600", "132.780", "16400", "132.770", "9800", "132.760", "8600", "132.750", "64500", "132.800", "16900", "132.810", "11900", "132.820", "1000", "132.830", "1900", "132.840", "2025-01-27", "15:00:01", "00", "\";"]
+/// Quotes from sina finance.
+#[derive(Debug, Clone)]
+pub struct SinaProvider {
+    base_url: String,
+    kline_url: String,
+    hk_kline_url: String,
+    us_kline_url: String,
+    suggest_url: String,
+    minline_url: String,
+}
+
+impl Default for SinaProvider {
+    fn default() -> Self {
+        Self {
+            base_url: BASE_URL.to_string(),
+            kline_url: KLINE_URL.to_string(),
+            hk_kline_url: HK_KLINE_URL.to_string(),
+            us_kline_url: US_KLINE_URL.to_string(),
+            suggest_url: SUGGEST_URL.to_string(),
+            minline_url: MINLINE_URL.to_string(),
+        }
+    }
+}
+
+impl SinaProvider {
+    /// Realtime quotes endpoint, `{base_url}/list=...` is requested.
+    pub fn with_base_url(mut self, url: impl Into<String>) -> Self {
+        self.base_url = url.into();
+        self
+    }
+
+    /// Kline endpoint, `{kline_url}?symbol=...&scale=...` is requested.
+    pub fn with_kline_url(mut self, url: impl Into<String>) -> Self {
+        self.kline_url = url.into();
+        self
+    }
+
+    /// Hk daily kline endpoint, `{hk_kline_url}?symbol=00700` is requested.
+    pub fn with_hk_kline_url(mut self, url: impl Into<String>) -> Self {
+        self.hk_kline_url = url.into();
+        self
+    }
+
+    /// Us kline service, `{us_kline_url}.getMinK` and `.getDailyK` are requested.
+    pub fn with_us_kline_url(mut self, url: impl Into<String>) -> Self {
+        self.us_kline_url = url.into();
+        self
+    }
+
+    /// Suggest endpoint, `{suggest_url}?type=&key=...` is requested.
+    pub fn with_suggest_url(mut self, url: impl Into<String>) -> Self {
+        self.suggest_url = url.into();
+        self
+    }
+
+    /// Minute line endpoint, `{minline_url}?symbol=...` is requested.
+    pub fn with_minline_url(mut self, url: impl Into<String>) -> Self {
+        self.minline_url = url.into();
+        self
+    }
+}
+
+impl QuoteProvider for SinaProvider {
+    fn fetch_quotes(
+        &self,
+        codes: &[String],
+    ) -> Result<Vec<Result<Stock, TrackerError>>, TrackerError> {
+        let code_string = codes.join(",");
+        let url = format!("{}/list={}", self.base_url, code_string);
+
+        // var hq_str_sh601127 = "赛力斯,133.000,132.800,132.790,135.440,131.010,132.790,132.800,22615984,3006594293.000,25300,132.790,31600,132.780,16400,132.770,9800,132.760,8600,132.750,64500,132.800,16900,132.810,11900,132.820,1000,132.830,1900,132.840,2025-01-27,15:00:01,00,";
+        // "var hq_str_s_sh000001=\"上证指数,3250.6007,-2.0257,-0.06,3874676,45023154\";\nvar hq_str_s_sz399001=\"深证成指,10156.07,-136.663,-1.33,532847417,66764113\";\nvar hq_str_s_sh000300=\"沪深300,3817.0802,-15.7835,-0.41,1466707,28294013\";\n"
+        let str = reqwest::blocking::Client::new()
+            .get(&url)
+            .header("Referer", "https://www.sina.com.cn/")
+            .send()?
+            .error_for_status()?
+            .text()?;
+
+        Ok(decode_sina_list(&str))
+    }
+
+    fn fetch_klines(
+        &self,
+        code: &str,
+        scale: KLineScale,
+        datalen: u32,
+    ) -> Result<Klines, TrackerError> {
+        match Market::from_code(code) {
+            Market::HkShare => return self.fetch_hk_klines(code, scale, datalen),
+            Market::UsShare => return self.fetch_us_klines(code, scale, datalen),
+            Market::ZhShare => {}
+        }
+
+        let scale_int = scale.to_usize();
+        let response = reqwest::blocking::get(format!(
+            "{}?symbol={code}&scale={scale_int}&ma=no&datalen={datalen}",
+            self.kline_url
+        ))?
+        .error_for_status()?
+        .json::<Vec<Value>>()?;
+
+        let (klines, skipped) = decode_rows(response);
+
+        Ok(Klines {
+            klines,
+            scale,
+            skipped,
+        })
+    }
+
+    fn search_symbol(&self, keyword: &str) -> Result<Vec<Symbol>, TrackerError> {
+        // var suggestdata="gzmt,11,600519,sh600519,贵州茅台,,贵州茅台,99,1,ESG,,;..."
+        let str = reqwest::blocking::Client::new()
+            .get(&self.suggest_url)
+            .query(&[("type", ""), ("key", keyword), ("name", "suggestdata")])
+            .header("Referer", "https://www.sina.com.cn/")
+            .send()?
+            .error_for_status()?
+            .text()?;
+
+        Ok(decode_sina_suggest(&str))
+    }
+
+    fn fetch_minutes(&self, code: &str) -> Result<TimeShare, TrackerError> {
+        // hk and us lines are built from quotes alone
+        if Market::from_code(code) != Market::ZhShare {
+            return Ok(TimeShare::default());
+        }
+        let str = reqwest::blocking::get(format!("{}?symbol={code}", self.minline_url))?
+            .error_for_status()?
+            .text()?;
+
+        decode_sina_minline(&str)
+    }
+}
+
+impl SinaProvider {
+    // the hk service only serves the full daily history
+    fn fetch_hk_klines(
+        &self,
+        code: &str,
+        scale: KLineScale,
+        datalen: u32,
+    ) -> Result<Klines, TrackerError> {
+        if !scale.is_available(Market::HkShare) {
+            return Err(TrackerError::UnsupportedScale {
+                code: code.to_string(),
+                scale: scale.label(),
+            });
+        }
+        let symbol = code.trim_start_matches("hk");
+        let response = reqwest::blocking::get(format!("{}?symbol={symbol}", self.hk_kline_url))?
+            .error_for_status()?
+            .json::<Value>()?;
+
+        decode_hk_klines(response, scale, datalen)
+    }
+}
+
+impl SinaProvider {
+    fn fetch_us_klines(
+        &self,
+        code: &str,
+        scale: KLineScale,
+        datalen: u32,
+    ) -> Result<Klines, TrackerError> {
+        let symbol = code.trim_start_matches("gb_").to_uppercase();
+        let url = match scale {
+            KLineScale::Day | KLineScale::Week | KLineScale::Month => {
+                format!("{}.getDailyK?symbol={symbol}", self.us_kline_url)
+            }
+            _ => format!(
+                "{}.getMinK?symbol={symbol}&type={}",
+                self.us_kline_url,
+                scale.to_usize()
+            ),
+        };
+        let str = reqwest::blocking::get(url)?.error_for_status()?.text()?;
+
+        decode_us_klines(&str, scale, datalen)
+    }
+}
+
+// [{"d":"2025-01-27 09:35:00","o":"224.120","h":"225.010","l":"223.980","c":"224.600","v":"3123456"}]
+// sometimes wrapped as `var _AAPL=([...]);`
+fn decode_us_klines(str: &str, scale: KLineScale, datalen: u32) -> Result<Klines, TrackerError> {
+    let unknown = || TrackerError::UnknownFormat(str.to_string());
+    let start = str.find('[').ok_or_else(unknown)?;
+    let end = str.rfind(']').ok_or_else(unknown)?;
+    let items: Vec<Value> = serde_json::from_str(&str[start..=end]).map_err(|_| unknown())?;
+
+    let (items, skipped) = decode_rows(items.into_iter().map(|x| {
+        serde_json::json!({
+            "day": x["d"],
+            "open": x["o"],
+            "high": x["h"],
+            "low": x["l"],
+            "close": x["c"],
+            "volume": x["v"],
+        })
+    }));
+
+    let mut klines = match scale {
+        KLineScale::Week | KLineScale::Month => resample(items, scale),
+        _ => items,
+    };
+    let skip = klines.len().saturating_sub(datalen as usize);
+    klines.drain(..skip);
+
+    Ok(Klines {
+        klines,
+        scale,
+        skipped,
+    })
+}
+
+fn decode_hk_klines(
+    response: Value,
+    scale: KLineScale,
+    datalen: u32,
+) -> Result<Klines, TrackerError> {
+    // {"result":{"status":{"code":0},"data":[{"date":"2025-01-27","open":"..."}]}}
+    let items = match response {
+        Value::Array(items) => items,
+        mut wrapped => match wrapped["result"]["data"].take() {
+            Value::Array(items) => items,
+            _ => return Err(TrackerError::UnknownFormat(wrapped.to_string())),
+        },
+    };
+
+    let (days, skipped) = decode_rows(items);
+
+    let mut klines = resample(days, scale);
+    let skip = klines.len().saturating_sub(datalen as usize);
+    klines.drain(..skip);
+
+    Ok(Klines {
+        klines,
+        scale,
+        skipped,
+    })
+}
+
+/// Candles of the rows that decode, and why the others didn't.
+fn decode_rows(items: impl IntoIterator<Item = Value>) -> (Vec<KlineItem>, Vec<String>) {
+    let mut klines = vec![];
+    let mut skipped = vec![];
+    for item in items {
+        match KlineItem::try_from(item) {
+            Ok(item) => klines.push(item),
+            Err(e) => skipped.push(e.to_string()),
+        }
+    }
+    (klines, skipped)
+}
+
+/// Merge daily candles into weeks or months.
+fn resample(days: Vec<KlineItem>, scale: KLineScale) -> Vec<KlineItem> {
+    let period = |item: &KlineItem| match scale {
+        KLineScale::Week => {
+            let week = item.day.iso_week();
+            (week.year(), week.week())
+        }
+        KLineScale::Month => (item.day.year(), item.day.month()),
+        _ => (item.day.year(), item.day.ordinal()),
+    };
+
+    let mut klines: Vec<KlineItem> = vec![];
+    for item in days {
+        match klines.last_mut() {
+            Some(last) if period(last) == period(&item) => {
+                last.high = last.high.max(item.high);
+                last.low = last.low.min(item.low);
+                last.close = item.close;
+                last.volume += item.volume;
+                last.amount += item.amount;
+                last.day = item.day;
+                last.date = item.date;
+            }
+            _ => klines.push(item),
+        }
+    }
+    klines
+}
+
+fn decode_sina_suggest(suggest_string: &str) -> Vec<Symbol> {
+    let body = suggest_string
+        .split_once('"')
+        .map(|(_, rest)| rest.trim_end().trim_end_matches(';').trim_end_matches('"'))
+        .unwrap_or_default();
+
+    body.split(';')
+        .filter_map(|item| {
+            let fields: Vec<&str> = item.split(',').collect();
+            let (key, kind, code, symbol, name) = match fields.as_slice() {
+                [key, kind, code, symbol, name, ..] => (*key, *kind, *code, *symbol, *name),
+                _ => return None,
+            };
+
+            let code = match kind {
+                "31" => format!("hk{code}"),
+                "41" => format!("gb_{}", code.to_lowercase()),
+                _ if ["sh", "sz", "bj"].iter().any(|p| symbol.starts_with(p)) => symbol.to_string(),
+                _ => return None,
+            };
+
+            // the key matched, letters are the pinyin initials of chinese names,
+            // us keys are just the ticker
+            let chinese = matches!(Market::from_code(&code), Market::ZhShare | Market::HkShare);
+            let pinyin = if chinese && key.bytes().all(|b| b.is_ascii_alphabetic()) {
+                key.to_lowercase()
+            } else {
+                String::new()
+            };
+
+            Some(Symbol {
+                market: Market::from_code(&code),
+                code,
+                name: name.to_string(),
+                pinyin,
+            })
+        })
+        .collect()
+}
+
+// {"result":{"status":{"code":0},"data":[{"m":"09:30:00","v":"273000","p":"133.000","avg_p":"133.000"},...]}}
+fn decode_sina_minline(str: &str) -> Result<TimeShare, TrackerError> {
+    let value: Value = serde_json::from_str(str)?;
+    let Some(data) = value["result"]["data"].as_array() else {
+        return Err(TrackerError::UnknownFormat(str.to_string()));
+    };
+    let mut points = vec![];
+    for item in data {
+        let line = item.to_string();
+        let field = |name: &'static str| item[name].as_str().unwrap_or_default();
+        let time = NaiveTime::parse_from_str(field("m"), "%H:%M:%S").map_err(|_| {
+            TrackerError::ParseError {
+                field: "m",
+                line: line.clone(),
+            }
+        })?;
+        let Some(minute) = trading_minute(Market::ZhShare, time) else {
+            continue;
+        };
+        points.push(MinutePoint {
+            minute,
+            price: parse_field(field("p"), "p", &line)?,
+            avg: parse_field(field("avg_p"), "avg_p", &line)?,
+            volume: parse_field(field("v"), "v", &line)?,
+        });
+    }
+    Ok(TimeShare::new(points))
+}
+
+fn parse_field<T: FromStr>(
+    value: &str,
+    field: &'static str,
+    line: &str,
+) -> Result<T, TrackerError> {
+    value
+        .trim()
+        .parse::<T>()
+        .map_err(|_| TrackerError::ParseError {
+            field,
+            line: line.to_string(),
+        })
+}
+
+fn decode_sina_list(list_string: &str) -> Vec<Result<Stock, TrackerError>> {
+    list_string
+        .trim()
+        .split('\n')
+        .filter(|x| !x.trim().is_empty())
+        .map(decode_sina_result)
+        .collect()
+}
+
+fn decode_sina_result(stock_string: &str) -> Result<Stock, TrackerError> {
+    let line = stock_string.trim();
+    let unknown = || TrackerError::UnknownFormat(line.to_string());
+
+    let (var, body) = line.split_once("=\"").ok_or_else(unknown)?;
+    let code = var.trim().strip_prefix("var hq_str_").ok_or_else(unknown)?;
+    let body = body.trim_end_matches(';').trim_end_matches('"');
+    if body.is_empty() {
+        return Err(TrackerError::EmptyQuote(code.to_string()));
+    }
+    // the code is known from here on, the status bar names the stock
+    decode_sina_fields(code, body, line).map_err(|e| TrackerError::BadQuote {
+        code: code.to_string(),
+        source: Box::new(e),
+    })
+}
+
+fn decode_sina_fields(code: &str, body: &str, line: &str) -> Result<Stock, TrackerError> {
+    let unknown = || TrackerError::UnknownFormat(line.to_string());
+    let mut list: Vec<&str> = body.split(',').collect();
+
+    // 上证指数,3250.6007,-2.0257,-0.06,3874676,45023154
+    if code.starts_with("s_") {
+        return decode_sina_index(code, &list, line);
+    }
+
+    match Market::from_code(code) {
+        Market::HkShare => return decode_sina_hk(code, &list, line),
+        Market::UsShare => return decode_sina_us(code, &list, line),
+        Market::ZhShare => {}
+    }
+
+    // ["赛力斯", "133.000", "132.800", "132.790", "135.440", "131.010", "132.790", "132.800", "22615984", "3006594293.000", "25300", "132.790", "31600", "132.780", "16400", "132.770", "9800", "132.760", "8600", "132.750", "64500", "132.800", "16900", "132.810", "11900", "132.820", "1000", "132.830", "1900", "132.840", "2025-01-27", "15:00:01", "00", ""]
     list.truncate(32);
     match list.as_slice() {
-        [code_and_name, opening_str, closing_str, new_str, high, low, bid, ask, vol, amount, rest @ .., date, time] =>
+        [name, opening_str, closing_str, new_str, high, low, bid, ask, vol, amount, rest @ .., date, time]
+            if rest.len() == 20 =>
         {
-            let name_str: Vec<&str> = code_and_name.split("=\"").collect();
-            let code = name_str[0].replace("var hq_str_", "");
-            let name = name_str[1];
-            let opening = opening_str.parse::<Price>().unwrap();
-            let closing = closing_str.parse::<Price>().unwrap();
-            let new = new_str.parse::<Price>().unwrap();
-            let percent = ((new - closing) / closing * 10000.0).round() / 100.0;
-
-            let bids = rest[0..10]
-                .chunks(2)
-                .into_iter()
-                .map(|x| {
-                    if let [v, p] = x {
-                        (v.parse::<Vol>().unwrap() / 100, p.parse::<Price>().unwrap())
-                    } else {
-                        (0, 0.0)
-                    }
-                })
-                .collect();
-
-            let asks = rest[10..20]
-                .chunks(2)
-                .into_iter()
-                .map(|x| {
-                    if let [v, p] = x {
-                        (v.parse::<Vol>().unwrap() / 100, p.parse::<Price>().unwrap())
-                    } else {
-                        (0, 0.0)
-                    }
-                })
-                .collect();
+            let opening: Price = parse_field(opening_str, "opening", line)?;
+            let closing: Price = parse_field(closing_str, "closing", line)?;
+            let new: Price = parse_field(new_str, "new", line)?;
+            // suspended stocks report 0.000 as the latest price
+            let percent = if new.is_zero() || closing.is_zero() {
+                Percent::ZERO
+            } else {
+                ((new - closing) / closing * Percent::ONE_HUNDRED).round_dp(2)
+            };
+
+            let depth = |fields: &[&str], vol_field, price_field| {
+                fields
+                    .chunks(2)
+                    .map(|x| {
+                        let v: Vol = parse_field(x[0], vol_field, line)?;
+                        let p: Price = parse_field(x[1], price_field, line)?;
+                        Ok((v / 100, p))
+                    })
+                    .collect::<Result<Vec<(Vol, Price)>, TrackerError>>()
+            };
 
             let data = StockData {
                 opening,
                 closing,
                 new,
-                hight: high.parse::<Price>().unwrap(),
-                low: low.parse::<Price>().unwrap(),
-                bid: bid.parse::<Price>().unwrap(),
-                ask: ask.parse::<Price>().unwrap(),
-                vol: vol.parse::<Vol>().unwrap(),
-                amount: amount.parse::<f32>().unwrap(),
+                hight: parse_field(high, "high", line)?,
+                low: parse_field(low, "low", line)?,
+                bid: parse_field(bid, "bid", line)?,
+                ask: parse_field(ask, "ask", line)?,
+                vol: parse_field(vol, "vol", line)?,
+                amount: parse_field(amount, "amount", line)?,
                 date: date.to_string(),
                 time: time.to_string(),
                 rise_per: percent,
-                bids,
-                asks,
+                bids: depth(&rest[0..10], "bid_vol", "bid_price")?,
+                asks: depth(&rest[10..20], "ask_vol", "ask_price")?,
+                ext: None,
+            };
+
+            Ok(Stock {
+                name: name.to_string(),
+                code: code.into(),
+                data,
+                ..Default::default()
+            })
+        }
+
+        _ => Err(unknown()),
+    }
+}
+
+// short index format, vol in lots and amount in 10k yuan
+fn decode_sina_index(code: &str, list: &[&str], line: &str) -> Result<Stock, TrackerError> {
+    match list {
+        [name, new_str, change, percent, vol, amount] => {
+            let new: Price = parse_field(new_str, "new", line)?;
+            let closing = new - parse_field::<Price>(change, "change", line)?;
+
+            let data = StockData {
+                closing,
+                new,
+                vol: parse_field::<Vol>(vol, "vol", line)? * 100,
+                amount: parse_field::<Price>(amount, "amount", line)? * Price::new(10_000, 0),
+                rise_per: parse_field(percent, "rise_per", line)?,
+                ..Default::default()
             };
 
-            Some(Stock {
-                name: name.into(),
+            Ok(Stock {
+                name: name.to_string(),
                 code: code.into(),
                 data,
                 ..Default::default()
             })
         }
+        _ => Err(TrackerError::UnknownFormat(line.to_string())),
+    }
+}
 
-        _ => None,
+// TENCENT,腾讯控股,372.000,370.400,375.800,368.200,373.600,3.200,0.864,373.400,373.600,6541235465.000,17535521,15.330,0.000,417.800,260.200,2025/01/27,16:08
+fn decode_sina_hk(code: &str, list: &[&str], line: &str) -> Result<Stock, TrackerError> {
+    match list {
+        [en_name, name, opening, closing, high, low, new, _change, percent, bid, ask, amount, vol, _pe, _yield, _high_52, _low_52, date, time, ..] =>
+        {
+            let name = if name.is_empty() { en_name } else { name };
+            let new: Price = parse_field(new, "new", line)?;
+            let rise_per = if new.is_zero() {
+                Percent::ZERO
+            } else {
+                parse_field(percent, "rise_per", line)?
+            };
+
+            let data = StockData {
+                opening: parse_field(opening, "opening", line)?,
+                closing: parse_field(closing, "closing", line)?,
+                new,
+                hight: parse_field(high, "high", line)?,
+                low: parse_field(low, "low", line)?,
+                bid: parse_field(bid, "bid", line)?,
+                ask: parse_field(ask, "ask", line)?,
+                // shares, sometimes sent with decimals
+                vol: parse_field::<f64>(vol, "vol", line)? as Vol,
+                amount: parse_field(amount, "amount", line)?,
+                date: date.replace('/', "-"),
+                time: time.to_string(),
+                rise_per,
+                ..Default::default()
+            };
+
+            Ok(Stock {
+                name: name.to_string(),
+                code: code.into(),
+                data,
+                ..Default::default()
+            })
+        }
+        _ => Err(TrackerError::UnknownFormat(line.to_string())),
+    }
+}
+
+// 苹果,229.8600,-3.19,2025-01-28 08:30:47,-7.5700,224.1200,232.1500,223.9800,260.1000,164.0800,94863418,67284567,3452987340000,10.21,22.510000,0.00,0.00,0.99,0.00,15021890000,25,229.4000,-0.20,-0.46,Jan 27 08:00PM EST,Jan 27 04:00PM EST,237.4500,1012467,1,2025,22006849486.0000,...
+fn decode_sina_us(code: &str, list: &[&str], line: &str) -> Result<Stock, TrackerError> {
+    match list {
+        [name, new, percent, date_time, _change, opening, high, low, _high_52, _low_52, vol, _avg_vol, _cap, _eps, _pe, _, _, _, _, _shares, _, ext_price, ext_percent, _ext_change, ext_time, _close_time, closing, ext_vol, rest @ ..] =>
+        {
+            let (date, time) = date_time.split_once(' ').unwrap_or((date_time, ""));
+            let ext_price: Price = parse_field(ext_price, "ext_price", line)?;
+            let ext = if ext_price.is_zero() {
+                None
+            } else {
+                Some(ExtendedHours {
+                    price: ext_price,
+                    rise_per: parse_field(ext_percent, "ext_rise_per", line)?,
+                    vol: parse_field::<f64>(ext_vol, "ext_vol", line)? as Vol,
+                    time: ext_time.to_string(),
+                })
+            };
+            // turnover is only sent by newer responses
+            let amount = match rest {
+                [_, _, amount, ..] => parse_field(amount, "amount", line)?,
+                _ => Price::ZERO,
+            };
+
+            let data = StockData {
+                opening: parse_field(opening, "opening", line)?,
+                closing: parse_field(closing, "closing", line)?,
+                new: parse_field(new, "new", line)?,
+                hight: parse_field(high, "high", line)?,
+                low: parse_field(low, "low", line)?,
+                vol: parse_field::<f64>(vol, "vol", line)? as Vol,
+                amount,
+                date: date.to_string(),
+                time: time.to_string(),
+                rise_per: parse_field(percent, "rise_per", line)?,
+                ext,
+                ..Default::default()
+            };
+
+            Ok(Stock {
+                name: name.to_string(),
+                code: code.into(),
+                data,
+                ..Default::default()
+            })
+        }
+        _ => Err(TrackerError::UnknownFormat(line.to_string())),
     }
 }
 
@@ -225,8 +853,42 @@ fn decode_sina_result(stock_string: &str) -> Option<Stock> {
 mod test {
 
     use super::*;
+    use crate::test_util::candles;
+
+    const HQ_LIST: &str = include_str!("../../tests/fixtures/sina/hq_list.txt");
+    const HQ_INDEX_SHORT: &str = include_str!("../../tests/fixtures/sina/hq_index_short.txt");
+    const HQ_MALFORMED: &str = include_str!("../../tests/fixtures/sina/hq_malformed.txt");
+    const KLINE_M5: &str = include_str!("../../tests/fixtures/sina/kline_sh601127_m5.json");
+    const KLINE_DAY: &str = include_str!("../../tests/fixtures/sina/kline_sh601127_day.json");
+    const HQ_HK: &str = include_str!("../../tests/fixtures/sina/hq_hk.txt");
+    const KLINE_HK_DAY: &str = include_str!("../../tests/fixtures/sina/kline_hk00700_day.json");
+    const HQ_US: &str = include_str!("../../tests/fixtures/sina/hq_us.txt");
+    const KLINE_US_M5: &str = include_str!("../../tests/fixtures/sina/kline_gb_aapl_m5.txt");
+    const KLINE_US_DAY: &str = include_str!("../../tests/fixtures/sina/kline_gb_aapl_day.json");
+    const MINLINE: &str = include_str!("../../tests/fixtures/sina/minline_sh601127.json");
+
+    fn d(x: &str) -> Price {
+        x.parse().unwrap()
+    }
+
+    // what went wrong in a row, past the code it belongs to
+    fn cause(e: &TrackerError) -> &TrackerError {
+        match e {
+            TrackerError::BadQuote { source, .. } => source,
+            e => e,
+        }
+    }
+
+    fn decode_klines(json: &str) -> Vec<KlineItem> {
+        serde_json::from_str::<Vec<Value>>(json)
+            .unwrap()
+            .into_iter()
+            .map(|x| KlineItem::try_from(x).unwrap())
+            .collect()
+    }
 
     #[test]
+    #[ignore = "asks the live sina endpoints"]
     fn test_fetch_data() {
         let codes = vec![
             "sh000001".to_string(),
@@ -244,6 +906,7 @@ mod test {
     }
 
     #[test]
+    #[ignore = "asks the live sina endpoints"]
     fn test_get_klines() {
         let mut stock = Stock::default();
         stock.code = String::from("sh601127");
@@ -252,4 +915,516 @@ mod test {
 
         println!("{:?}", stock.kline);
     }
+
+    #[test]
+    fn test_decode_sina_list() {
+        let mut rows = decode_sina_list(HQ_LIST);
+        assert_eq!(rows.len(), 4);
+
+        assert!(matches!(
+            rows.pop(),
+            Some(Err(TrackerError::EmptyQuote(code))) if code == "sh000000"
+        ));
+        let codes: Vec<String> = rows.into_iter().map(|s| s.unwrap().code).collect();
+        assert_eq!(codes, vec!["sh601127", "sh000001", "sz000004"]);
+    }
+
+    #[test]
+    fn test_decode_stock() {
+        let stock = decode_sina_list(HQ_LIST).remove(0).unwrap();
+        assert_eq!(stock.name, "赛力斯");
+        assert_eq!(stock.code, "sh601127");
+        assert!(stock.kline.klines.is_empty());
+
+        let data = stock.data;
+        assert_eq!(data.date, "2025-01-27");
+        assert_eq!(data.time, "15:00:01");
+        assert_eq!(data.opening, d("133"));
+        assert_eq!(data.closing, d("132.8"));
+        assert_eq!(data.new, d("132.79"));
+        assert_eq!(data.hight, d("135.44"));
+        assert_eq!(data.low, d("131.01"));
+        assert_eq!(data.bid, d("132.79"));
+        assert_eq!(data.ask, d("132.8"));
+        assert_eq!(data.vol, 22615984);
+        assert_eq!(data.amount, d("3006594293"));
+        assert_eq!(data.rise_per, d("-0.01"));
+        assert_eq!(
+            data.bids,
+            vec![
+                (253, d("132.79")),
+                (316, d("132.78")),
+                (164, d("132.77")),
+                (98, d("132.76")),
+                (86, d("132.75"))
+            ]
+        );
+        assert_eq!(
+            data.asks,
+            vec![
+                (645, d("132.8")),
+                (169, d("132.81")),
+                (119, d("132.82")),
+                (10, d("132.83")),
+                (19, d("132.84"))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_decode_index_full_format() {
+        let stock = decode_sina_list(HQ_LIST).remove(1).unwrap();
+        assert_eq!(stock.name, "上证指数");
+        assert_eq!(stock.code, "sh000001");
+
+        let data = stock.data;
+        assert_eq!(data.date, "2025-01-27");
+        assert_eq!(data.time, "15:30:39");
+        assert_eq!(data.opening, d("3247.426"));
+        assert_eq!(data.closing, d("3252.6264"));
+        assert_eq!(data.new, d("3250.6007"));
+        assert_eq!(data.hight, d("3262.806"));
+        assert_eq!(data.low, d("3240.099"));
+        assert_eq!(data.bid, d("0"));
+        assert_eq!(data.ask, d("0"));
+        assert_eq!(data.vol, 387467622);
+        assert_eq!(data.amount, d("450231536582"));
+        assert_eq!(data.rise_per, d("-0.06"));
+        assert_eq!(data.bids, vec![(0, d("0.0")); 5]);
+        assert_eq!(data.asks, vec![(0, d("0.0")); 5]);
+    }
+
+    #[test]
+    fn test_decode_suspended_stock() {
+        let stock = decode_sina_list(HQ_LIST).remove(2).unwrap();
+        assert_eq!(stock.name, "国华网安");
+        assert_eq!(stock.code, "sz000004");
+
+        let data = stock.data;
+        assert_eq!(data.date, "2025-01-27");
+        assert_eq!(data.time, "15:00:00");
+        assert_eq!(data.opening, d("0"));
+        assert_eq!(data.closing, d("15.09"));
+        assert_eq!(data.new, d("0"));
+        assert_eq!(data.hight, d("0"));
+        assert_eq!(data.low, d("0"));
+        assert_eq!(data.bid, d("0"));
+        assert_eq!(data.ask, d("0"));
+        assert_eq!(data.vol, 0);
+        assert_eq!(data.amount, d("0"));
+        assert_eq!(data.rise_per, d("0"));
+        assert_eq!(data.bids, vec![(0, d("0.0")); 5]);
+        assert_eq!(data.asks, vec![(0, d("0.0")); 5]);
+    }
+
+    #[test]
+    fn test_decode_empty_quote() {
+        assert!(matches!(
+            decode_sina_result("var hq_str_sh000000=\"\";"),
+            Err(TrackerError::EmptyQuote(code)) if code == "sh000000"
+        ));
+        assert!(decode_sina_list("").is_empty());
+        assert!(decode_sina_list("\n\n").is_empty());
+    }
+
+    #[test]
+    fn test_decode_index_short_format() {
+        let stocks: Vec<Stock> = decode_sina_list(HQ_INDEX_SHORT)
+            .into_iter()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(stocks.len(), 2);
+
+        let stock = &stocks[0];
+        assert_eq!(stock.name, "上证指数");
+        assert_eq!(stock.code, "s_sh000001");
+
+        let data = &stock.data;
+        assert_eq!(data.date, "");
+        assert_eq!(data.time, "");
+        assert_eq!(data.opening, d("0"));
+        assert_eq!(data.closing, d("3252.6264"));
+        assert_eq!(data.new, d("3250.6007"));
+        assert_eq!(data.hight, d("0"));
+        assert_eq!(data.low, d("0"));
+        assert_eq!(data.bid, d("0"));
+        assert_eq!(data.ask, d("0"));
+        assert_eq!(data.vol, 387467600);
+        assert_eq!(data.amount, d("450231540000"));
+        assert_eq!(data.rise_per, d("-0.06"));
+        assert!(data.bids.is_empty());
+        assert!(data.asks.is_empty());
+
+        assert_eq!(stocks[1].code, "s_sz399001");
+        assert_eq!(stocks[1].data.rise_per, d("-1.33"));
+    }
+
+    #[test]
+    fn test_decode_malformed_lines() {
+        let rows = decode_sina_list(HQ_MALFORMED);
+        assert_eq!(rows.len(), 7);
+
+        let codes: Vec<Option<&str>> = rows
+            .iter()
+            .map(|x| x.as_ref().err().and_then(TrackerError::code))
+            .collect();
+        assert_eq!(
+            codes,
+            [
+                Some("sh601127"),
+                None,
+                None,
+                Some("s_sh000001"),
+                Some("sz000001"),
+                Some("sh600000"),
+                Some("sh600036")
+            ]
+        );
+        for (row, line) in rows[..5].iter().zip(HQ_MALFORMED.lines()) {
+            assert!(
+                matches!(row.as_ref().map_err(cause), Err(TrackerError::UnknownFormat(l)) if l == line),
+                "{row:?}"
+            );
+        }
+        // empty latest price
+        assert!(matches!(
+            rows[5].as_ref().map_err(cause),
+            Err(TrackerError::ParseError { field: "new", line }) if line.contains("浦发银行")
+        ));
+        assert!(matches!(
+            rows[6].as_ref().map_err(cause),
+            Err(TrackerError::ParseError { field: "bid_vol", line }) if line.contains("招商银行")
+        ));
+    }
+
+    #[test]
+    fn test_decode_minute_klines() {
+        let klines = decode_klines(KLINE_M5);
+        assert_eq!(klines.len(), 3);
+
+        let first = &klines[0];
+        assert_eq!(
+            first.day,
+            NaiveDate::from_ymd_opt(2025, 1, 27)
+                .unwrap()
+                .and_hms_opt(14, 50, 0)
+                .unwrap()
+        );
+        assert_eq!(first.date, "2025-01-27 14:50:00");
+        assert_eq!(first.open, 132.34);
+        assert_eq!(first.high, 132.8);
+        assert_eq!(first.low, 132.3);
+        assert_eq!(first.close, 132.75);
+        assert_eq!(first.volume, 412300.0);
+        assert_eq!(first.amount, 54712345.0);
+
+        // amount is missing from the last candle
+        let last = &klines[2];
+        assert_eq!(last.date, "2025-01-27 15:00:00");
+        assert_eq!(last.close, 132.79);
+        assert_eq!(last.volume, 501200.0);
+        assert_eq!(last.amount, 0.0);
+    }
+
+    #[test]
+    fn test_decode_day_klines() {
+        let klines = decode_klines(KLINE_DAY);
+        assert_eq!(klines.len(), 2);
+
+        let last = &klines[1];
+        assert_eq!(
+            last.day,
+            NaiveDate::from_ymd_opt(2025, 1, 27)
+                .unwrap()
+                .and_hms_opt(0, 0, 0)
+                .unwrap()
+        );
+        assert_eq!(last.date, "2025-01-27");
+        assert_eq!(last.open, 133.0);
+        assert_eq!(last.high, 135.44);
+        assert_eq!(last.low, 131.01);
+        assert_eq!(last.close, 132.79);
+        assert_eq!(last.volume, 22615984.0);
+        assert_eq!(last.amount, 3006594293.0);
+    }
+
+    #[test]
+    fn test_decode_bad_klines() {
+        let bad = [
+            (r#"{"open":"1.0"}"#, "day"),
+            (r#"{"day":"27/01/2025"}"#, "day"),
+            (r#"{"day":"2025-01-27","open":"1.0","close":""}"#, "close"),
+            (r#"{"day":"2025-01-27","volume":"n/a"}"#, "volume"),
+        ];
+        for (json, expected) in bad {
+            let value: Value = serde_json::from_str(json).unwrap();
+            let result = KlineItem::try_from(value);
+            assert!(
+                matches!(result, Err(TrackerError::ParseError { field, .. }) if field == expected),
+                "{json}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_decode_klines_skip_bad_rows() {
+        let rows = r#"[{"d":"2025-01-27 09:35:00","o":"1","h":"1","l":"1","c":"1","v":"1"},
+            {"d":"2025-01-27 09:40:00","o":"1","h":"1","l":"1","c":"n/a","v":"1"},
+            {"d":"2025-01-27 09:45:00","o":"1","h":"1","l":"1","c":"2","v":"1"}]"#;
+        let klines = decode_us_klines(rows, KLineScale::Munute5, 100).unwrap();
+        let dates: Vec<&str> = klines.klines.iter().map(|x| x.date.as_str()).collect();
+        assert_eq!(dates, vec!["2025-01-27 09:35:00", "2025-01-27 09:45:00"]);
+        assert_eq!(klines.skipped.len(), 1);
+        assert!(klines.skipped[0].contains("`close`"));
+    }
+
+    #[test]
+    fn test_decode_hk_stock() {
+        let stocks: Vec<Stock> = decode_sina_list(HQ_HK)
+            .into_iter()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(stocks.len(), 3);
+
+        let stock = &stocks[0];
+        assert_eq!(stock.name, "腾讯控股");
+        assert_eq!(stock.code, "hk00700");
+        assert_eq!(stock.market(), Market::HkShare);
+
+        let data = &stock.data;
+        assert_eq!(data.date, "2025-01-27");
+        assert_eq!(data.time, "16:08");
+        assert_eq!(data.opening, d("372"));
+        assert_eq!(data.closing, d("370.4"));
+        assert_eq!(data.new, d("373.6"));
+        assert_eq!(data.hight, d("375.8"));
+        assert_eq!(data.low, d("368.2"));
+        assert_eq!(data.bid, d("373.4"));
+        assert_eq!(data.ask, d("373.6"));
+        assert_eq!(data.vol, 17535521);
+        assert_eq!(data.amount, d("6541235465"));
+        assert_eq!(data.rise_per, d("0.864"));
+        assert!(data.bids.is_empty());
+        assert!(data.asks.is_empty());
+
+        assert_eq!(stocks[1].name, "恒生指数");
+        assert_eq!(stocks[1].code, "hkHSI");
+        assert_eq!(stocks[1].data.rise_per, d("0.656"));
+
+        // suspended, no chinese name
+        assert_eq!(stocks[2].name, "JD LOGISTICS");
+        assert_eq!(stocks[2].data.new, d("0"));
+        assert_eq!(stocks[2].data.rise_per, d("0"));
+    }
+
+    #[test]
+    fn test_decode_hk_malformed() {
+        let line = "var hq_str_hk00700=\"TENCENT,腾讯控股,372.000,370.400\";";
+        assert!(matches!(
+            decode_sina_result(line).as_ref().map_err(cause),
+            Err(TrackerError::UnknownFormat(_))
+        ));
+        let line = "var hq_str_hk00700=\"TENCENT,腾讯控股,372.000,370.400,375.800,368.200,--,3.200,0.864,373.400,373.600,6541235465.000,17535521,15.330,0.000,417.800,260.200,2025/01/27,16:08\";";
+        assert!(matches!(
+            decode_sina_result(line).as_ref().map_err(cause),
+            Err(TrackerError::ParseError { field: "new", .. })
+        ));
+    }
+
+    #[test]
+    fn test_decode_hk_klines() {
+        let response: Value = serde_json::from_str(KLINE_HK_DAY).unwrap();
+
+        let days = decode_hk_klines(response.clone(), KLineScale::Day, 3).unwrap();
+        assert_eq!(days.scale, KLineScale::Day);
+        let dates: Vec<&str> = days.klines.iter().map(|x| x.date.as_str()).collect();
+        assert_eq!(dates, vec!["2025-01-21", "2025-01-24", "2025-01-27"]);
+        assert_eq!(days.klines[2].close, 373.6);
+        assert_eq!(days.klines[2].amount, 0.0);
+
+        let weeks = decode_hk_klines(response, KLineScale::Week, 100).unwrap();
+        assert_eq!(weeks.scale, KLineScale::Week);
+        assert_eq!(weeks.klines.len(), 2);
+        let week = &weeks.klines[0];
+        assert_eq!(week.date, "2025-01-24");
+        assert_eq!(week.open, 385.0);
+        assert_eq!(week.high, 392.0);
+        assert_eq!(week.low, 369.4);
+        assert_eq!(week.close, 370.4);
+        assert_eq!(week.volume, 19843520.0 + 22036481.0 + 24117305.0);
+
+        assert!(matches!(
+            decode_hk_klines(serde_json::json!({"result": {}}), KLineScale::Day, 10),
+            Err(TrackerError::UnknownFormat(_))
+        ));
+    }
+
+    #[test]
+    fn test_decode_us_stock() {
+        let stocks: Vec<Stock> = decode_sina_list(HQ_US)
+            .into_iter()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(stocks.len(), 2);
+
+        let stock = &stocks[0];
+        assert_eq!(stock.name, "苹果");
+        assert_eq!(stock.code, "gb_aapl");
+        assert_eq!(stock.market(), Market::UsShare);
+
+        let data = &stock.data;
+        assert_eq!(data.date, "2025-01-28");
+        assert_eq!(data.time, "08:30:47");
+        assert_eq!(data.opening, d("224.12"));
+        assert_eq!(data.closing, d("237.45"));
+        assert_eq!(data.new, d("229.86"));
+        assert_eq!(data.hight, d("232.15"));
+        assert_eq!(data.low, d("223.98"));
+        assert_eq!(data.bid, d("0"));
+        assert_eq!(data.ask, d("0"));
+        assert_eq!(data.vol, 94863418);
+        assert_eq!(data.amount, d("22006849486"));
+        assert_eq!(data.rise_per, d("-3.19"));
+        assert!(data.bids.is_empty());
+        assert!(data.asks.is_empty());
+        assert_eq!(
+            data.ext,
+            Some(ExtendedHours {
+                price: d("229.4"),
+                rise_per: d("-0.2"),
+                vol: 1012467,
+                time: "Jan 27 08:00PM EST".into(),
+            })
+        );
+
+        // index without extended hours or turnover
+        let index = &stocks[1];
+        assert_eq!(index.name, "纳斯达克");
+        assert_eq!(index.data.new, d("19341.833"));
+        assert_eq!(index.data.closing, d("19954.301"));
+        assert_eq!(index.data.amount, d("0"));
+        assert_eq!(index.data.ext, None);
+    }
+
+    #[test]
+    fn test_decode_us_klines() {
+        let minutes = decode_us_klines(KLINE_US_M5, KLineScale::Munute5, 100).unwrap();
+        assert_eq!(minutes.scale, KLineScale::Munute5);
+        assert_eq!(minutes.klines.len(), 2);
+        let last = &minutes.klines[1];
+        assert_eq!(last.date, "2025-01-27 15:55:00");
+        assert_eq!(last.open, 229.7);
+        assert_eq!(last.high, 230.1);
+        assert_eq!(last.low, 229.5);
+        assert_eq!(last.close, 229.86);
+        assert_eq!(last.volume, 3012455.0);
+
+        let days = decode_us_klines(KLINE_US_DAY, KLineScale::Day, 2).unwrap();
+        let dates: Vec<&str> = days.klines.iter().map(|x| x.date.as_str()).collect();
+        assert_eq!(dates, vec!["2025-01-27", "2025-01-28"]);
+
+        let weeks = decode_us_klines(KLINE_US_DAY, KLineScale::Week, 100).unwrap();
+        assert_eq!(weeks.klines.len(), 2);
+        assert_eq!(weeks.klines[1].open, 224.12);
+        assert_eq!(weeks.klines[1].close, 238.26);
+
+        assert!(matches!(
+            decode_us_klines("null", KLineScale::Day, 10),
+            Err(TrackerError::UnknownFormat(_))
+        ));
+    }
+
+    #[test]
+    fn test_decode_minline() {
+        let minutes = decode_sina_minline(MINLINE).unwrap();
+        let minute: Vec<u32> = minutes.points.iter().map(|x| x.minute).collect();
+        // 11:30 and 13:01 are neighbours on the session axis
+        assert_eq!(minute, vec![0, 1, 120, 121]);
+        assert_eq!(minutes.points[1].price, 133.34);
+        assert_eq!(minutes.points[1].avg, 133.205);
+        assert_eq!(minutes.points[3].volume, 201200.0);
+
+        assert!(decode_sina_minline(r#"{"result":{"status":{"code":-1}}}"#).is_err());
+        assert!(decode_sina_minline(
+            r#"{"result":{"data":[{"m":"09:30:00","v":"1","p":"x","avg_p":"1"}]}}"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_decode_sina_suggest() {
+        let str = "var suggestdata=\"gzmt,11,600519,sh600519,贵州茅台,,贵州茅台,99,1,ESG,,;tx,31,00700,00700,腾讯控股,,腾讯控股,99,1,,,;aapl,41,aapl,aapl,苹果,,苹果,99,1,,,;gzmt,201,gzmt,gzmt,某基金,,某基金,99,1,,,\";";
+        let symbols = decode_sina_suggest(str);
+
+        assert_eq!(
+            symbols,
+            vec![
+                Symbol {
+                    code: "sh600519".into(),
+                    name: "贵州茅台".into(),
+                    market: Market::ZhShare,
+                    pinyin: "gzmt".into(),
+                },
+                Symbol {
+                    code: "hk00700".into(),
+                    name: "腾讯控股".into(),
+                    market: Market::HkShare,
+                    pinyin: "tx".into(),
+                },
+                Symbol {
+                    code: "gb_aapl".into(),
+                    name: "苹果".into(),
+                    market: Market::UsShare,
+                    pinyin: String::new(),
+                },
+            ]
+        );
+        assert!(decode_sina_suggest("var suggestdata=\"\";").is_empty());
+    }
+
+    #[test]
+    fn test_merge_klines() {
+        let mut klines = candles(KLineScale::Day, 10..20, 1.0);
+
+        // refresh overlapping the tail, the new values win
+        klines.merge_newer(candles(KLineScale::Day, 15..22, 2.0));
+        assert_eq!(klines.klines.len(), 12);
+        assert_eq!(klines.klines[4].close, 1.0);
+        assert_eq!(klines.klines[5].close, 2.0);
+
+        // a bigger page, only what is older is added
+        assert_eq!(klines.merge_older(candles(KLineScale::Day, 5..15, 3.0)), 5);
+        assert_eq!(klines.klines.len(), 17);
+        assert_eq!(
+            klines.klines[0].day,
+            candles(KLineScale::Day, 5..6, 0.0).klines[0].day
+        );
+        assert!(klines.klines.windows(2).all(|x| x[0].day < x[1].day));
+        // the start of history
+        assert_eq!(klines.merge_older(candles(KLineScale::Day, 5..15, 3.0)), 0);
+        // another scale arrived meanwhile
+        assert_eq!(klines.merge_older(candles(KLineScale::Week, 0..5, 3.0)), 0);
+
+        // a gap or another scale replaces everything
+        klines.merge_newer(candles(KLineScale::Day, 40..45, 4.0));
+        assert_eq!(klines.klines.len(), 5);
+        klines.merge_newer(candles(KLineScale::Week, 0..3, 5.0));
+        assert_eq!((klines.scale, klines.klines.len()), (KLineScale::Week, 3));
+    }
+
+    #[test]
+    fn test_merge_klines_bound() {
+        let max = MAX_KLINES as u32;
+        let mut klines = candles(KLineScale::Day, 100..max, 1.0);
+        assert_eq!(
+            klines.merge_older(candles(KLineScale::Day, 0..200, 1.0)),
+            100
+        );
+        assert_eq!(klines.klines.len(), MAX_KLINES);
+        assert_eq!(klines.merge_older(candles(KLineScale::Day, 0..50, 1.0)), 0);
+
+        klines.merge_newer(candles(KLineScale::Day, max - 1..max + 10, 2.0));
+        assert_eq!(klines.klines.len(), MAX_KLINES);
+        assert_eq!(klines.klines.last().unwrap().close, 2.0);
+    }
 }
@@ -0,0 +1,174 @@
+use super::{Market, Symbol};
+
+// code,name,pinyin per line
+const BUNDLED: &str = include_str!("../../assets/data/symbols.csv");
+
+/// Symbols searchable without the network: a bundled list of the usual
+/// names plus whatever the suggest endpoint returned before.
+#[derive(Debug, Clone, Default)]
+pub struct SymbolMaster {
+    symbols: Vec<Symbol>,
+}
+
+impl SymbolMaster {
+    /// Bundled list with `cached` symbols from earlier searches.
+    pub fn new(cached: Vec<Symbol>) -> Self {
+        let mut master = Self { symbols: cached };
+        master.merge(parse_master(BUNDLED));
+        master
+    }
+
+    pub fn symbols(&self) -> &[Symbol] {
+        &self.symbols
+    }
+
+    /// Adds new codes and refreshes the names of known ones. A known pinyin
+    /// only gives way to a longer one, keys typed halfway are shorter.
+    pub fn merge(&mut self, symbols: impl IntoIterator<Item = Symbol>) {
+        for symbol in symbols {
+            match self.symbols.iter_mut().find(|x| x.code == symbol.code) {
+                Some(known) => {
+                    known.name = symbol.name;
+                    if symbol.pinyin.len() > known.pinyin.len() {
+                        known.pinyin = symbol.pinyin;
+                    }
+                }
+                None => self.symbols.push(symbol),
+            }
+        }
+    }
+
+    /// Best matches of `keyword` by code, pinyin initials or name.
+    pub fn search(&self, keyword: &str, limit: usize) -> Vec<Symbol> {
+        let key = keyword.trim().to_lowercase();
+        if key.is_empty() {
+            return vec![];
+        }
+        let mut found: Vec<(u8, &Symbol)> = self
+            .symbols
+            .iter()
+            .filter_map(|x| rank(x, &key).map(|r| (r, x)))
+            .collect();
+        found.sort_by(|a, b| a.0.cmp(&b.0).then_with(|| a.1.code.cmp(&b.1.code)));
+        found
+            .into_iter()
+            .take(limit)
+            .map(|(_, x)| x.clone())
+            .collect()
+    }
+}
+
+// lower is better, none when it doesn't match at all
+fn rank(symbol: &Symbol, key: &str) -> Option<u8> {
+    let code = symbol.code.to_lowercase();
+    let bare = bare_code(&code);
+    if code == key || bare == key {
+        Some(0)
+    } else if code.starts_with(key) || bare.starts_with(key) {
+        Some(1)
+    } else if symbol.pinyin.starts_with(key) {
+        Some(2)
+    } else if symbol.name.to_lowercase().contains(key) {
+        Some(3)
+    } else if bare.contains(key) {
+        Some(4)
+    } else {
+        None
+    }
+}
+
+// `600519` of `sh600519`
+fn bare_code(code: &str) -> &str {
+    ["sh", "sz", "bj", "hk", "gb_"]
+        .iter()
+        .find_map(|x| code.strip_prefix(x))
+        .unwrap_or(code)
+}
+
+fn parse_master(csv: &str) -> Vec<Symbol> {
+    csv.lines()
+        .filter(|x| !x.is_empty() && !x.starts_with('#'))
+        .filter_map(|line| {
+            let mut fields = line.split(',').map(str::trim);
+            let code = fields.next()?.to_string();
+            let name = fields.next()?.to_string();
+            let pinyin = fields.next().unwrap_or_default().to_lowercase();
+            Some(Symbol {
+                market: Market::from_code(&code),
+                code,
+                name,
+                pinyin,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::model::valid_code;
+
+    fn codes(symbols: &[Symbol]) -> Vec<&str> {
+        symbols.iter().map(|x| x.code.as_str()).collect()
+    }
+
+    #[test]
+    fn test_bundled_master() {
+        let master = SymbolMaster::new(vec![]);
+        assert!(master.symbols().len() > 40);
+        assert!(master
+            .symbols()
+            .iter()
+            .all(|x| valid_code(&x.code) && !x.pinyin.is_empty()));
+    }
+
+    #[test]
+    fn test_search() {
+        let master = SymbolMaster::new(vec![]);
+
+        assert_eq!(codes(&master.search("600519", 5)), ["sh600519"]);
+        assert_eq!(codes(&master.search("SH6005", 5)), ["sh600519"]);
+        assert_eq!(codes(&master.search("00700", 5)), ["hk00700"]);
+        assert_eq!(codes(&master.search("gzmt", 5)), ["sh600519"]);
+        assert_eq!(codes(&master.search("茅台", 5)), ["sh600519"]);
+        assert_eq!(codes(&master.search("aapl", 5)), ["gb_aapl"]);
+        // pinyin and name matches, then codes only containing it
+        assert_eq!(codes(&master.search("albb", 5)), ["gb_baba", "hk09988"]);
+        assert_eq!(codes(&master.search("比亚迪", 5)), ["hk01211", "sz002594"]);
+        assert_eq!(master.search("byd", 1)[0].code, "hk01211");
+        assert!(master.search(" ", 5).is_empty());
+        assert!(master.search("zzzz", 5).is_empty());
+    }
+
+    #[test]
+    fn test_merge() {
+        let cached = vec![Symbol {
+            code: "sh688981".into(),
+            name: "中芯国际".into(),
+            market: Market::ZhShare,
+            pinyin: "zxgj".into(),
+        }];
+        let mut master = SymbolMaster::new(cached);
+        assert_eq!(codes(&master.search("zxgj", 5)), ["sh688981"]);
+
+        let len = master.symbols().len();
+        master.merge([Symbol {
+            code: "sh601127".into(),
+            name: "赛力斯".into(),
+            market: Market::ZhShare,
+            pinyin: String::new(),
+        }]);
+        // known code, the pinyin is kept
+        assert_eq!(master.symbols().len(), len);
+        assert_eq!(codes(&master.search("sls", 5)), ["sh601127"]);
+
+        // a key typed halfway doesn't replace the full initials
+        master.merge([Symbol {
+            code: "sh688981".into(),
+            name: "中芯国际".into(),
+            market: Market::ZhShare,
+            pinyin: "zx".into(),
+        }]);
+        assert_eq!(codes(&master.search("zxgj", 5)), ["sh688981"]);
+    }
+}
@@ -0,0 +1,148 @@
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    bars::{trading_minute, Tick},
+    Market,
+};
+
+/// Price, average price and volume of a traded minute.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MinutePoint {
+    /// Minutes since the open, 0 is the opening call.
+    pub minute: u32,
+    pub price: f64,
+    /// Volume weighted average price of the day so far.
+    pub avg: f64,
+    pub volume: f64,
+}
+
+/// Minute line of a trading day, 分时.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TimeShare {
+    pub date: Option<NaiveDate>,
+    pub points: Vec<MinutePoint>,
+    // day totals behind the average
+    volume: f64,
+    amount: f64,
+}
+
+impl TimeShare {
+    pub fn new(points: Vec<MinutePoint>) -> Self {
+        let volume = points.iter().map(|x| x.volume).sum();
+        let amount = points.last().map_or(0.0, |x| x.avg * volume);
+        Self {
+            date: None,
+            points,
+            volume,
+            amount,
+        }
+    }
+
+    /// Folds a trade into its minute, a new day starts over.
+    pub fn fold_tick(&mut self, market: Market, tick: &Tick) {
+        let Some(minute) = trading_minute(market, tick.at.time()) else {
+            return;
+        };
+        let date = tick.at.date();
+        if self.date.is_some_and(|x| x != date) {
+            *self = TimeShare::default();
+        }
+        self.date = Some(date);
+
+        if self.points.last().is_some_and(|x| x.minute > minute) {
+            return;
+        }
+        // us quotes come without turnover
+        let amount = match tick.amount {
+            x if x > 0.0 => x,
+            _ => tick.price * tick.volume,
+        };
+        self.volume += tick.volume;
+        self.amount += amount;
+        let avg = match self.volume {
+            x if x > 0.0 => self.amount / x,
+            _ => tick.price,
+        };
+        match self.points.last_mut() {
+            Some(last) if last.minute == minute => {
+                last.price = tick.price;
+                last.avg = avg;
+                last.volume += tick.volume;
+            }
+            _ => self.points.push(MinutePoint {
+                minute,
+                price: tick.price,
+                avg,
+                volume: tick.volume,
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::NaiveDateTime;
+
+    use super::*;
+
+    fn tick(at: &str, price: f64, volume: f64) -> Tick {
+        Tick {
+            at: NaiveDateTime::parse_from_str(at, "%Y-%m-%d %H:%M:%S").unwrap(),
+            price,
+            volume,
+            amount: price * volume,
+        }
+    }
+
+    #[test]
+    fn test_fold_minutes() {
+        let mut minutes = TimeShare::new(vec![
+            MinutePoint {
+                minute: 0,
+                price: 10.0,
+                avg: 10.0,
+                volume: 100.0,
+            },
+            MinutePoint {
+                minute: 1,
+                price: 11.0,
+                avg: 10.5,
+                volume: 100.0,
+            },
+        ]);
+        minutes.date = NaiveDate::from_ymd_opt(2025, 1, 27);
+
+        // still the minute ending 9:31
+        minutes.fold_tick(Market::ZhShare, &tick("2025-01-27 09:30:40", 12.0, 200.0));
+        assert_eq!(minutes.points.len(), 2);
+        let last = &minutes.points[1];
+        assert_eq!((last.price, last.volume), (12.0, 300.0));
+        // (2100 + 2400) / 400
+        assert_eq!(last.avg, 11.25);
+
+        // the lunch break belongs to 11:30, the afternoon goes on from there
+        minutes.fold_tick(Market::ZhShare, &tick("2025-01-27 12:10:00", 12.0, 0.0));
+        assert_eq!(minutes.points[2].minute, 120);
+        minutes.fold_tick(Market::ZhShare, &tick("2025-01-27 13:00:30", 12.5, 100.0));
+        assert_eq!(minutes.points[3].minute, 121);
+        // a late quote changes nothing
+        minutes.fold_tick(Market::ZhShare, &tick("2025-01-27 11:00:00", 1.0, 100.0));
+        assert_eq!(minutes.points.len(), 4);
+
+        // before the open nothing, then a new day
+        minutes.fold_tick(Market::ZhShare, &tick("2025-01-28 09:25:00", 13.0, 100.0));
+        assert_eq!(minutes.points.len(), 4);
+        minutes.fold_tick(Market::ZhShare, &tick("2025-01-28 09:30:00", 13.0, 100.0));
+        assert_eq!(minutes.date, NaiveDate::from_ymd_opt(2025, 1, 28));
+        assert_eq!(
+            minutes.points,
+            vec![MinutePoint {
+                minute: 0,
+                price: 13.0,
+                avg: 13.0,
+                volume: 100.0
+            }]
+        );
+    }
+}
@@ -0,0 +1,54 @@
+// fixtures shared by the unit tests
+
+use std::{fs, path::PathBuf};
+
+use chrono::{Duration, NaiveDate};
+
+use crate::model::stock::{KLineScale, KlineItem, Klines};
+
+/// An empty directory of its own for the test `name`.
+pub(crate) fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("wt-{name}-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    dir
+}
+
+/// Candles at `steps` closing at `close`. Intraday scales step a minute from
+/// 2025-01-27 09:00, the others a day from 2025-01-01.
+pub(crate) fn candles(
+    scale: KLineScale,
+    steps: impl IntoIterator<Item = u32>,
+    close: f64,
+) -> Klines {
+    let start = NaiveDate::from_ymd_opt(2025, 1, 1).unwrap();
+    let klines = steps
+        .into_iter()
+        .map(|i| {
+            let (day, format) = if scale.is_intraday() {
+                let open = start.and_hms_opt(9, 0, 0).unwrap() + Duration::days(26);
+                (open + Duration::minutes(i.into()), "%Y-%m-%d %H:%M:%S")
+            } else {
+                (
+                    start.and_hms_opt(0, 0, 0).unwrap() + Duration::days(i.into()),
+                    "%Y-%m-%d",
+                )
+            };
+            KlineItem {
+                day,
+                open: 1.5,
+                high: close + 0.25,
+                // not exact in binary, the store must keep it as is
+                low: 0.1 + 0.2,
+                close,
+                volume: 100.0,
+                amount: 150.0,
+                date: day.format(format).to_string(),
+            }
+        })
+        .collect();
+    Klines {
+        scale,
+        klines,
+        ..Default::default()
+    }
+}
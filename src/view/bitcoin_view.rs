@@ -1,24 +1,31 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, thread};
 
-use egui::{Color32, Frame, Grid, Id, Label, RichText};
+use crossbeam::channel::{Receiver, Sender};
+use egui::{Color32, Label, RichText, TopBottomPanel, Vec2};
 use egui_dnd::{dnd, Handle};
 
-#[derive(Hash)]
-pub struct Bitcoin {
-    name: String,
-    amount: String,
-}
+use crate::{
+    backend::{CryptoCommand, CryptoTask, Endpoint, FetchError, FetchStatus, TxCryptoData},
+    model::{
+        crypto::Coin,
+        price::{format_crypto_price, format_dp},
+    },
+};
+
+use super::widgets::{kline_sparkline, rise_color};
+
+// default symbols, quoted in usdt
+const DEFAULT_SYMBOLS: &str = "BTCUSDT,ETHUSDT";
 
 #[derive(Default)]
 pub struct BitcoinView {
+    // display order, drag to reorder
     items: Vec<String>,
-    data: HashMap<String, Bitcoin>,
-}
-
-/// What is being dragged.
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
-struct Location {
-    row: usize,
+    data: HashMap<String, Coin>,
+    tx: Option<Sender<CryptoCommand>>,
+    rx: Option<Receiver<TxCryptoData>>,
+    status: HashMap<Endpoint, FetchStatus>,
+    last_error: Option<FetchError>,
 }
 
 impl BitcoinView {
@@ -27,34 +34,58 @@ impl BitcoinView {
     }
 
     pub fn new() -> Self {
-        let mut m = HashMap::new();
-        m.insert(
-            String::from("BTC"),
-            Bitcoin {
-                name: "BTC".to_string(),
-                amount: "88.88".to_string(),
-            },
-        );
-        m.insert(
-            String::from("ETH"),
-            Bitcoin {
-                name: "ETH".to_string(),
-                amount: "48.88".to_string(),
-            },
-        );
+        let (tx, rx) = crossbeam::channel::unbounded();
+        let (tx2, rx2) = crossbeam::channel::unbounded();
+
+        thread::spawn(|| CryptoTask::new(rx, tx2, DEFAULT_SYMBOLS.to_string()).run());
         Self {
-            items: vec![
-                "alfred".to_string(),
-                "bernhard".to_string(),
-                "christian".to_string(),
-            ],
-            data: m,
+            items: DEFAULT_SYMBOLS.split(',').map(|x| x.to_string()).collect(),
+            tx: Some(tx),
+            rx: Some(rx2),
+            ..Default::default()
+        }
+    }
+
+    fn receiver(&mut self) {
+        let Some(rx) = self.rx.clone() else {
+            return;
+        };
+        while let Ok(data) = rx.try_recv() {
+            match data {
+                TxCryptoData::CoinList(coins) => {
+                    for coin in coins {
+                        if let Some(c) = self.data.get_mut(&coin.symbol) {
+                            // klines are refreshed separately
+                            let kline = std::mem::take(&mut c.kline);
+                            *c = Coin { kline, ..coin };
+                        } else {
+                            if !self.items.contains(&coin.symbol) {
+                                self.items.push(coin.symbol.clone());
+                            }
+                            self.data.insert(coin.symbol.clone(), coin);
+                        }
+                    }
+                    self.last_error = None;
+                }
+                TxCryptoData::Kline((symbol, kline)) => {
+                    if let Some(c) = self.data.get_mut(&symbol) {
+                        c.kline = kline;
+                    }
+                }
+                TxCryptoData::Error(error) => {
+                    self.last_error = Some(error);
+                }
+                TxCryptoData::Status(status) => {
+                    self.status.insert(status.endpoint, status);
+                }
+            }
         }
     }
 }
 
 impl BitcoinView {
     pub fn show(&mut self, ctx: &egui::Context, open: &mut bool) {
+        self.receiver();
         egui::Window::new(self.name())
             .default_width(320.0)
             .default_height(480.0)
@@ -63,92 +94,103 @@ impl BitcoinView {
             .resizable([true, true])
             .scroll(true)
             .show(ctx, |ui| {
+                self.render_status_bar(ui);
                 self.render_coins(ui);
             });
     }
 
+    fn render_status_bar(&mut self, ui: &mut egui::Ui) {
+        TopBottomPanel::bottom("btc_status_bar").show_inside(ui, |ui| {
+            ui.horizontal_wrapped(|ui| {
+                for endpoint in [Endpoint::Quote, Endpoint::Stream] {
+                    let color = match self.status.get(&endpoint).map(|s| s.ok) {
+                        Some(true) => Color32::GREEN,
+                        Some(false) => Color32::RED,
+                        None => Color32::GRAY,
+                    };
+                    let last = self
+                        .status
+                        .get(&endpoint)
+                        .and_then(|s| s.last_success)
+                        .map(|t| t.format("%H:%M:%S").to_string())
+                        .unwrap_or_else(|| "-".to_string());
+                    ui.label(
+                        RichText::new(format!("● {} {last}", endpoint.label()))
+                            .small()
+                            .color(color),
+                    );
+                }
+                if ui.small_button("🔄").clicked() {
+                    if let Some(tx) = &self.tx {
+                        let _ = tx.send(CryptoCommand::Refresh);
+                    }
+                }
+
+                if let Some(error) = &self.last_error {
+                    ui.add(
+                        Label::new(
+                            RichText::new(&error.message)
+                                .small()
+                                .color(Color32::LIGHT_RED),
+                        )
+                        .truncate(),
+                    )
+                    .on_hover_text(&error.message);
+                }
+            });
+        });
+    }
+
     pub fn render_coins(&mut self, ui: &mut egui::Ui) {
         ui.add_space(8.0);
 
-        let mut items = self.data.values_mut();
-        let response = dnd(ui, "stock list").show(&mut items, |ui, item, handle, state| {
-            BitcoinView::render_item(item, ui, handle);
+        let data = &self.data;
+        dnd(ui, "coin list").show_vec(&mut self.items, |ui, item, handle, _state| {
+            if let Some(coin) = data.get(item) {
+                BitcoinView::render_item(coin, ui, handle);
+            }
         });
-        if response.is_drag_finished() {
-            response.update_vec(&mut self.items);
-        }
-
-        // Grid::new("Bitcoin list")
-        //     .max_col_width(60.0)
-        //     .striped(true)
-        //     .show(ui, |ui| {
-
-        // self.data.values_mut().enumerate().for_each(|(idx, stock)| {
-        //     let frame = Frame::default().inner_margin(4.0);
-
-        //     ui.dnd_drop_zone::<Location, ()>(frame, |ui| {
-        //         // row
-        //         let item_id = Id::new(("drag_and_drop", idx));
-
-        //         let item_location = Location { row: idx };
-
-        //         let response = ui
-        //             .dnd_drag_source(item_id, item_location, |ui| {
-        //                 // name
-        //                 ui.centered_and_justified(|ui| {
-        //                     ui.add(
-        //                         Label::new(
-        //                             RichText::new(stock.name.to_string())
-        //                                 .text_style(egui::TextStyle::Body),
-        //                         )
-        //                         .wrap_mode(egui::TextWrapMode::Truncate),
-        //                     );
-        //                 });
-
-        //                 // amount
-        //                 ui.centered_and_justified(|ui| {
-        //                     ui.add(Label::new(
-        //                         RichText::new(stock.data_new().to_string())
-        //                             .text_style(egui::TextStyle::Body),
-        //                     ));
-        //                 });
-
-        //                 let color = match stock.data_rise_per() {
-        //                     p if p < 0.0 => Color32::GREEN,
-        //                     n if n > 0.0 => Color32::RED,
-        //                     _ => Color32::WHITE,
-        //                 };
-
-        //                 ui.centered_and_justified(|ui| {
-        //                     ui.add(Label::new(
-        //                         RichText::new(stock.data_rise_per().to_string())
-        //                             .text_style(egui::TextStyle::Body)
-        //                             .color(color),
-        //                     ));
-        //                 });
-        //             })
-        //             .response;
-        //     });
-        // });
-        // });
     }
 
-    pub fn render_item(coin: &mut Bitcoin, ui: &mut egui::Ui, handle: Handle) {
+    pub fn render_item(coin: &Coin, ui: &mut egui::Ui, handle: Handle) {
+        let cell = Vec2::new(60.0, 18.0);
         ui.horizontal(|ui| {
             handle.ui(ui, |ui| {
                 // name
-                ui.add(
-                    Label::new(
-                        RichText::new(coin.name.to_string()).text_style(egui::TextStyle::Body),
-                    )
-                    .wrap_mode(egui::TextWrapMode::Truncate),
-                );
+                ui.add_sized(
+                    cell,
+                    Label::new(RichText::new(coin.name()).text_style(egui::TextStyle::Body))
+                        .wrap_mode(egui::TextWrapMode::Truncate),
+                )
+                .on_hover_text(&coin.symbol);
             });
 
-            // amount
-            ui.add(Label::new(
-                RichText::new(coin.amount.to_string()).text_style(egui::TextStyle::Body),
+            // price
+            ui.add_sized(
+                cell,
+                Label::new(
+                    RichText::new(format_crypto_price(coin.price))
+                        .text_style(egui::TextStyle::Body),
+                ),
+            )
+            .on_hover_text(format!(
+                "24h H {} L {}\nvol {}",
+                format_crypto_price(coin.high),
+                format_crypto_price(coin.low),
+                coin.vol
             ));
+
+            // 24h change
+            ui.add_sized(
+                cell,
+                Label::new(
+                    RichText::new(format_dp(coin.rise_per, 2))
+                        .text_style(egui::TextStyle::Body)
+                        .color(rise_color(coin.rise_per)),
+                ),
+            );
+
+            kline_sparkline(ui, format!("{}_kline", coin.symbol), &coin.kline);
         });
     }
 }
@@ -0,0 +1,109 @@
+use std::collections::HashMap;
+
+use egui::{Color32, Context, Grid, Label, RichText};
+
+use crate::model::{
+    portfolio::{summary, Portfolio},
+    price::{format_dp, format_price},
+    Stock,
+};
+
+use super::widgets::rise_color;
+
+#[derive(Default)]
+pub struct HoldingsView {}
+
+impl HoldingsView {
+    fn name(&self) -> &'static str {
+        "Holdings"
+    }
+
+    /// Positions come from the ledger, valued at `quotes`.
+    pub fn show(
+        &mut self,
+        ctx: &Context,
+        open: &mut bool,
+        portfolio: &Portfolio,
+        quotes: &HashMap<String, Stock>,
+    ) {
+        egui::Window::new(self.name())
+            .default_width(480.0)
+            .default_height(320.0)
+            .open(open)
+            .resizable([true, true])
+            .scroll(true)
+            .show(ctx, |ui| {
+                self.render_holdings(ui, portfolio, quotes);
+            });
+    }
+
+    fn render_holdings(
+        &mut self,
+        ui: &mut egui::Ui,
+        portfolio: &Portfolio,
+        quotes: &HashMap<String, Stock>,
+    ) {
+        let holdings = portfolio.holdings(quotes);
+
+        Grid::new("holdings")
+            .striped(true)
+            .num_columns(9)
+            .show(ui, |ui| {
+                for title in [
+                    "名称",
+                    "账户",
+                    "数量",
+                    "成本",
+                    "现价",
+                    "市值",
+                    "当日盈亏",
+                    "总盈亏",
+                    "占比",
+                ] {
+                    ui.label(RichText::new(title).small().color(Color32::GRAY));
+                }
+                ui.end_row();
+
+                for h in holdings.iter() {
+                    let p = &h.position;
+                    ui.add(Label::new(&h.name).truncate())
+                        .on_hover_text(&p.code);
+                    ui.label(&p.account);
+                    ui.label(p.qty.normalize().to_string());
+                    ui.label(format_dp(p.avg_cost, 3));
+                    ui.label(
+                        h.price
+                            .map(|x| format_price(&p.code, x))
+                            .unwrap_or_else(|| "-".to_string()),
+                    );
+                    ui.label(format_dp(h.market_value, 2));
+                    ui.label(RichText::new(format_dp(h.day_pnl, 2)).color(rise_color(h.day_pnl)));
+                    ui.label(
+                        RichText::new(format!(
+                            "{} ({}%)",
+                            format_dp(h.total_pnl, 2),
+                            format_dp(h.total_pnl_per(), 2)
+                        ))
+                        .color(rise_color(h.total_pnl)),
+                    );
+                    ui.label(format!("{:.1}%", h.weight * 100.0));
+                    ui.end_row();
+                }
+            });
+
+        for s in summary(&holdings) {
+            ui.horizontal(|ui| {
+                ui.label(RichText::new(s.market.currency()).small());
+                ui.label(format!("市值 {}", format_dp(s.market_value, 2)));
+                ui.label(
+                    RichText::new(format!("当日 {}", format_dp(s.day_pnl, 2)))
+                        .color(rise_color(s.day_pnl)),
+                );
+                ui.label(
+                    RichText::new(format!("总计 {}", format_dp(s.total_pnl, 2)))
+                        .color(rise_color(s.total_pnl)),
+                );
+            });
+        }
+    }
+}
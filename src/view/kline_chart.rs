@@ -0,0 +1,495 @@
+use std::collections::HashMap;
+
+use chrono::{Datelike, NaiveDateTime};
+use egui::{Color32, RichText, Stroke, Vec2};
+use egui_plot::{Bar, BarChart, BoxElem, BoxPlot, BoxSpread, HLine, Line, Plot, PlotPoints};
+use serde::{Deserialize, Serialize};
+
+use crate::model::{
+    indicators::{Kdj, Ma, Macd, Rsi, Series},
+    stock::{KLineScale, KlineItem, Klines},
+};
+
+use super::widgets::rise_color;
+
+const MA_PERIODS: [usize; 4] = [5, 10, 20, 60];
+const RSI_PERIODS: [usize; 3] = [6, 12, 24];
+const MA_COLORS: [Color32; 4] = [
+    Color32::WHITE,
+    Color32::YELLOW,
+    Color32::from_rgb(255, 0, 255),
+    Color32::GREEN,
+];
+// the y axes share a width so the candles line up across plots
+const Y_AXIS_WIDTH: f32 = 56.0;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum SubPanel {
+    #[default]
+    Macd,
+    Rsi,
+    Kdj,
+}
+
+impl SubPanel {
+    const ALL: [SubPanel; 3] = [SubPanel::Macd, SubPanel::Rsi, SubPanel::Kdj];
+
+    fn label(&self) -> &'static str {
+        match self {
+            SubPanel::Macd => "MACD",
+            SubPanel::Rsi => "RSI",
+            SubPanel::Kdj => "KDJ",
+        }
+    }
+}
+
+/// Tabs of the per-stock viewport.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum ChartTab {
+    #[default]
+    Kline,
+    TimeShare,
+}
+
+impl ChartTab {
+    pub(crate) const ALL: [ChartTab; 2] = [ChartTab::Kline, ChartTab::TimeShare];
+
+    pub(crate) fn label(&self) -> &'static str {
+        match self {
+            ChartTab::Kline => "K线",
+            ChartTab::TimeShare => "分时",
+        }
+    }
+}
+
+/// Candles with MA overlays, volume and an indicator panel below.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct KlineChart {
+    /// Shown MAs, one per [`MA_PERIODS`].
+    ma: [bool; 4],
+    sub: SubPanel,
+    pub(crate) tab: ChartTab,
+    #[serde(skip)]
+    hovered: Option<usize>,
+    #[serde(skip)]
+    series: HashMap<(String, KLineScale), ChartSeries>,
+}
+
+impl Default for KlineChart {
+    fn default() -> Self {
+        Self {
+            ma: [true, true, true, false],
+            sub: SubPanel::default(),
+            tab: ChartTab::default(),
+            hovered: None,
+            series: HashMap::new(),
+        }
+    }
+}
+
+impl KlineChart {
+    /// True when panned past the first candle, so older ones are wanted.
+    pub(crate) fn show(&mut self, ui: &mut egui::Ui, id: &str, kline: &Klines) -> bool {
+        ui.horizontal_wrapped(|ui| {
+            for (i, period) in MA_PERIODS.iter().enumerate() {
+                ui.toggle_value(
+                    &mut self.ma[i],
+                    RichText::new(format!("MA{period}")).color(MA_COLORS[i]),
+                );
+            }
+            ui.separator();
+            for sub in SubPanel::ALL {
+                ui.selectable_value(&mut self.sub, sub, sub.label());
+            }
+        });
+        self.render_hovered(ui, kline);
+
+        let klines = &kline.klines;
+        let series = self
+            .series
+            .entry((id.to_string(), kline.scale))
+            .or_insert_with(ChartSeries::new);
+        series.sync(klines);
+        let series = &*series;
+        let group = ui.id().with((id, "kline_group"));
+        let height = ui.available_height();
+        let plot = |name: &str| {
+            Plot::new(format!("{id}_{name}"))
+                .show_background(false)
+                .allow_drag([true, false])
+                .allow_zoom([true, false])
+                .allow_scroll([true, false])
+                .link_axis(group, [true, false])
+                .link_cursor(group, [true, false].into())
+                .y_axis_min_width(Y_AXIS_WIDTH)
+        };
+
+        let mut hovered = None;
+        let mut track = |x: Option<f64>| {
+            if let Some(x) = x {
+                let i = x.round();
+                if i >= 0.0 && (i as usize) < klines.len() {
+                    hovered = Some(i as usize);
+                }
+            }
+        };
+
+        let candles = BoxPlot::new(klines.iter().enumerate().map(candle).collect())
+            .element_formatter(Box::new(|_, _| String::new()));
+        let lines: Vec<Line> = MA_PERIODS
+            .iter()
+            .zip(MA_COLORS)
+            .zip(&series.ma)
+            .zip(self.ma)
+            .filter(|(_, shown)| *shown)
+            .map(|(((period, color), values), _)| {
+                line(values.values().iter().copied(), color).name(format!("MA{period}"))
+            })
+            .collect();
+        // older candles put in front shift every index, keep the view on
+        // the same candles
+        let first_id = ui.id().with((id, "first_day"));
+        let first = klines.first().map(|x| x.day);
+        let seen = ui.data_mut(|d| {
+            let seen = d.get_temp::<NaiveDateTime>(first_id);
+            match first {
+                Some(day) => d.insert_temp(first_id, day),
+                None => d.remove::<NaiveDateTime>(first_id),
+            }
+            seen
+        });
+        let prepended = match (seen, first) {
+            (Some(seen), Some(first)) if seen > first => {
+                klines.iter().position(|x| x.day == seen).unwrap_or(0)
+            }
+            _ => 0,
+        };
+
+        // x is the candle index so nights, weekends and holidays take no room,
+        // only the bottom plot labels it
+        let mut wants_older = false;
+        let response = plot("kline")
+            .height(height * 0.6)
+            .show_axes([false, true])
+            .show(ui, |plot_ui| {
+                plot_ui.box_plot(candles);
+                lines.into_iter().for_each(|x| plot_ui.line(x));
+                if prepended > 0 {
+                    plot_ui.translate_bounds(Vec2::new(prepended as f32, 0.0));
+                }
+                wants_older = !klines.is_empty() && plot_ui.plot_bounds().min()[0] < 0.0;
+                plot_ui.pointer_coordinate().map(|p| p.x)
+            });
+        track(response.inner);
+
+        let volume = BarChart::new(
+            klines
+                .iter()
+                .enumerate()
+                .map(|(i, x)| {
+                    let color = candle_color(x);
+                    Bar::new(i as f64, x.volume)
+                        .width(0.8)
+                        .stroke(Stroke::new(0.5, color))
+                        .fill(color.linear_multiply(0.3))
+                })
+                .collect(),
+        )
+        .element_formatter(Box::new(|_, _| String::new()));
+        let response = plot("volume")
+            .height(height * 0.15)
+            .show_axes([false, true])
+            .include_y(0.0)
+            .show(ui, |plot_ui| {
+                plot_ui.bar_chart(volume);
+                plot_ui.pointer_coordinate().map(|p| p.x)
+            });
+        track(response.inner);
+
+        let sub = self.sub;
+        let scale = kline.scale;
+        let days: Vec<NaiveDateTime> = klines.iter().map(|x| x.day).collect();
+        let response = plot("sub")
+            .height(ui.available_height())
+            .x_axis_formatter(move |mark, _| time_label(scale, &days, mark.value))
+            .show(ui, |plot_ui| {
+                match sub {
+                    SubPanel::Macd => {
+                        let values = series.macd.values();
+                        let bars = values
+                            .iter()
+                            .enumerate()
+                            .map(|(i, x)| {
+                                Bar::new(i as f64, x.macd)
+                                    .width(0.3)
+                                    .fill(rise_color(x.macd))
+                            })
+                            .collect();
+                        plot_ui.bar_chart(
+                            BarChart::new(bars).element_formatter(Box::new(|_, _| String::new())),
+                        );
+                        plot_ui.line(
+                            line(values.iter().map(|x| Some(x.dif)), Color32::WHITE).name("DIF"),
+                        );
+                        plot_ui.line(
+                            line(values.iter().map(|x| Some(x.dea)), Color32::YELLOW).name("DEA"),
+                        );
+                    }
+                    SubPanel::Rsi => {
+                        for ((period, values), color) in
+                            RSI_PERIODS.iter().zip(&series.rsi).zip(MA_COLORS)
+                        {
+                            plot_ui.line(
+                                line(values.values().iter().copied(), color)
+                                    .name(format!("RSI{period}")),
+                            );
+                        }
+                        for y in [20.0, 80.0] {
+                            plot_ui.hline(HLine::new(y).color(Color32::DARK_GRAY));
+                        }
+                    }
+                    SubPanel::Kdj => {
+                        let values = series.kdj.values();
+                        plot_ui
+                            .line(line(values.iter().map(|x| Some(x.k)), MA_COLORS[0]).name("K"));
+                        plot_ui
+                            .line(line(values.iter().map(|x| Some(x.d)), MA_COLORS[1]).name("D"));
+                        plot_ui
+                            .line(line(values.iter().map(|x| Some(x.j)), MA_COLORS[2]).name("J"));
+                    }
+                }
+                plot_ui.pointer_coordinate().map(|p| p.x)
+            });
+        track(response.inner);
+
+        self.hovered = hovered;
+        wants_older
+    }
+
+    /// OHLCV of the candle under the crosshair, the last one otherwise.
+    fn render_hovered(&self, ui: &mut egui::Ui, kline: &Klines) {
+        let item = self
+            .hovered
+            .and_then(|i| kline.klines.get(i))
+            .or(kline.klines.last());
+        let Some(x) = item else {
+            return;
+        };
+        ui.horizontal_wrapped(|ui| {
+            let color = candle_color(x);
+            let format = if kline.scale.is_intraday() {
+                "%Y-%m-%d %H:%M"
+            } else {
+                "%Y-%m-%d"
+            };
+            ui.label(RichText::new(x.day.format(format).to_string()).small());
+            for (name, value) in [
+                ("开", x.open),
+                ("高", x.high),
+                ("低", x.low),
+                ("收", x.close),
+            ] {
+                ui.label(
+                    RichText::new(format!("{name} {value:.3}"))
+                        .small()
+                        .color(color),
+                );
+            }
+            ui.label(RichText::new(format!("量 {:.0}", x.volume)).small());
+        });
+    }
+}
+
+/// Indicators of one code and scale, fed the candles that changed since the
+/// last frame.
+#[derive(Debug, Clone)]
+struct ChartSeries {
+    ma: Vec<Series<Ma>>,
+    macd: Series<Macd>,
+    rsi: Vec<Series<Rsi>>,
+    kdj: Series<Kdj>,
+    // the candles fed so far
+    len: usize,
+    first: Option<NaiveDateTime>,
+    last: Option<KlineItem>,
+}
+
+impl ChartSeries {
+    fn new() -> Self {
+        Self {
+            ma: MA_PERIODS.map(|x| Series::new(Ma::new(x))).to_vec(),
+            macd: Series::new(Macd::default()),
+            rsi: RSI_PERIODS.map(|x| Series::new(Rsi::new(x))).to_vec(),
+            kdj: Series::new(Kdj::default()),
+            len: 0,
+            first: None,
+            last: None,
+        }
+    }
+
+    /// Only the last candle may have moved and new ones come after it,
+    /// anything else starts over.
+    fn sync(&mut self, klines: &[KlineItem]) {
+        let held = self.len;
+        let same = held > 0
+            && held <= klines.len()
+            && klines.first().map(|x| x.day) == self.first
+            && self.last.as_ref().map(|x| x.day) == Some(klines[held - 1].day);
+        if !same {
+            *self = Self::new();
+        } else if self.last.as_ref() != Some(&klines[held - 1]) {
+            self.update_last(&klines[held - 1]);
+        }
+        for x in &klines[self.len..] {
+            self.push(x);
+        }
+        self.len = klines.len();
+        self.first = klines.first().map(|x| x.day);
+        self.last = klines.last().cloned();
+    }
+
+    fn push(&mut self, x: &KlineItem) {
+        self.ma.iter_mut().for_each(|s| s.push(x));
+        self.macd.push(x);
+        self.rsi.iter_mut().for_each(|s| s.push(x));
+        self.kdj.push(x);
+    }
+
+    fn update_last(&mut self, x: &KlineItem) {
+        self.ma.iter_mut().for_each(|s| s.update_last(x));
+        self.macd.update_last(x);
+        self.rsi.iter_mut().for_each(|s| s.update_last(x));
+        self.kdj.update_last(x);
+    }
+}
+
+fn candle_color(x: &KlineItem) -> Color32 {
+    if x.close < x.open {
+        Color32::GREEN
+    } else {
+        Color32::RED
+    }
+}
+
+fn candle((i, x): (usize, &KlineItem)) -> BoxElem {
+    let color = candle_color(x);
+    BoxElem::new(
+        i as f64,
+        BoxSpread::new(x.low, x.open, (x.open + x.close) / 2.0, x.close, x.high),
+    )
+    .name(x.date.clone())
+    .stroke(Stroke::new(0.2, color))
+    .fill(color.linear_multiply(0.05))
+    .box_width(0.8)
+}
+
+// warm up values are left out
+fn line(values: impl Iterator<Item = Option<f64>>, color: Color32) -> Line {
+    let points: PlotPoints = values
+        .enumerate()
+        .filter_map(|(i, y)| y.map(|y| [i as f64, y]))
+        .collect();
+    Line::new(points).color(color).width(1.0)
+}
+
+/// Label of the candle at `x`, the date is only repeated when it changes.
+fn time_label(scale: KLineScale, days: &[NaiveDateTime], x: f64) -> String {
+    let i = x.round();
+    if (x - i).abs() > 1e-6 || i < 0.0 || i as usize >= days.len() {
+        return String::new();
+    }
+    let i = i as usize;
+    let day = days[i];
+    let prev = i.checked_sub(1).map(|j| days[j]);
+    let format = match scale {
+        KLineScale::Month => "%Y-%m",
+        _ if scale.is_intraday() => {
+            if prev.is_some_and(|p| p.date() == day.date()) {
+                "%H:%M"
+            } else {
+                "%m-%d %H:%M"
+            }
+        }
+        _ if prev.is_some_and(|p| p.year() == day.year()) => "%m-%d",
+        _ => "%Y-%m-%d",
+    };
+    day.format(format).to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn days(xs: &[&str]) -> Vec<NaiveDateTime> {
+        xs.iter()
+            .map(|x| NaiveDateTime::parse_from_str(x, "%Y-%m-%d %H:%M").unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_time_label() {
+        let m30 = days(&["2025-01-24 14:30", "2025-01-24 15:00", "2025-01-27 10:00"]);
+        let label = |x| time_label(KLineScale::Munute30, &m30, x);
+        assert_eq!(label(0.0), "01-24 14:30");
+        assert_eq!(label(1.0), "15:00");
+        // the weekend collapsed into the next candle
+        assert_eq!(label(2.0), "01-27 10:00");
+        assert_eq!(label(1.5), "");
+        assert_eq!(label(3.0), "");
+        assert_eq!(label(-1.0), "");
+
+        let day = days(&["2024-12-31 00:00", "2025-01-02 00:00", "2025-01-03 00:00"]);
+        let label = |x| time_label(KLineScale::Day, &day, x);
+        assert_eq!(label(0.0), "2024-12-31");
+        assert_eq!(label(1.0), "2025-01-02");
+        assert_eq!(label(2.0), "01-03");
+
+        assert_eq!(time_label(KLineScale::Month, &day, 2.0), "2025-01");
+    }
+
+    #[test]
+    fn test_series_follow_candles() {
+        let start = days(&["2025-01-02 00:00"])[0];
+        let klines: Vec<KlineItem> = (0..80)
+            .map(|i| {
+                let close = 10.0 + (i % 7) as f64 - (i % 3) as f64;
+                KlineItem {
+                    day: start + chrono::Days::new(i),
+                    open: close,
+                    high: close + 1.0,
+                    low: close - 1.0,
+                    close,
+                    ..Default::default()
+                }
+            })
+            .collect();
+        let assert_fresh = |series: &ChartSeries, klines: &[KlineItem]| {
+            let mut fresh = ChartSeries::new();
+            fresh.sync(klines);
+            assert_eq!(series.ma[3].values(), fresh.ma[3].values());
+            assert_eq!(series.macd.values(), fresh.macd.values());
+            assert_eq!(series.rsi[0].values(), fresh.rsi[0].values());
+            assert_eq!(series.kdj.values(), fresh.kdj.values());
+        };
+
+        let mut series = ChartSeries::new();
+        series.sync(&klines[10..70]);
+
+        // the last candle moves, then new ones arrive
+        let mut moving = klines[10..70].to_vec();
+        moving.last_mut().unwrap().close += 3.0;
+        series.sync(&moving);
+        assert_fresh(&series, &moving);
+        series.sync(&klines[10..72]);
+        assert_fresh(&series, &klines[10..72]);
+
+        // older candles put in front
+        series.sync(&klines[..72]);
+        assert_eq!(series.macd.values().len(), 72);
+        assert_fresh(&series, &klines[..72]);
+
+        series.sync(&[]);
+        assert!(series.kdj.values().is_empty());
+    }
+}
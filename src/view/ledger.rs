@@ -0,0 +1,423 @@
+use std::collections::HashMap;
+
+use chrono::{Local, NaiveDate};
+use eframe::CreationContext;
+use egui::{
+    Button, CollapsingHeader, Color32, ComboBox, Context, Grid, RichText, TextEdit, TextStyle,
+};
+use rust_decimal::Decimal;
+
+use crate::model::{
+    ledger::{Ledger, Transaction, TxKind},
+    lots::{CostMethod, LotBook},
+    portfolio::Portfolio,
+    price::format_dp,
+    Stock,
+};
+
+use super::widgets::rise_color;
+
+const LEDGER_KEY: &str = "ledger";
+// positions typed in before the ledger existed
+const PORTFOLIO_KEY: &str = "portfolio";
+
+struct TxForm {
+    date: String,
+    kind: TxKind,
+    code: String,
+    account: String,
+    qty: String,
+    price: String,
+    amount: String,
+    to_account: String,
+    lot: String,
+}
+
+impl Default for TxForm {
+    fn default() -> Self {
+        Self {
+            date: Local::now().format("%Y-%m-%d").to_string(),
+            kind: TxKind::Buy,
+            code: String::new(),
+            account: "default".to_string(),
+            qty: String::new(),
+            price: String::new(),
+            amount: String::new(),
+            to_account: String::new(),
+            lot: String::new(),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct LedgerView {
+    ledger: Ledger,
+    // replayed after every change
+    lots: LotBook,
+    portfolio: Portfolio,
+    form: TxForm,
+    error: Option<String>,
+}
+
+impl LedgerView {
+    fn name(&self) -> &'static str {
+        "Ledger"
+    }
+
+    pub fn new(cc: &CreationContext) -> Self {
+        let mut view = Self::default();
+        if let Some(storage) = cc.storage {
+            if let Some(ledger) = eframe::get_value(storage, LEDGER_KEY) {
+                view.ledger = ledger;
+            } else if let Some(portfolio) = eframe::get_value::<Portfolio>(storage, PORTFOLIO_KEY) {
+                view.ledger = migrate(&portfolio);
+            }
+        }
+        if let Err(e) = view.ledger.lots() {
+            tracing::warn!("invalid ledger: {e}");
+        }
+        view.refresh();
+        view
+    }
+
+    pub fn save(&self, storage: &mut dyn eframe::Storage) {
+        eframe::set_value(storage, LEDGER_KEY, &self.ledger);
+    }
+
+    /// Positions derived from the ledger.
+    pub fn portfolio(&self) -> &Portfolio {
+        &self.portfolio
+    }
+
+    /// Returns the code of a newly added transaction, it needs live quotes.
+    pub fn show(
+        &mut self,
+        ctx: &Context,
+        open: &mut bool,
+        quotes: &HashMap<String, Stock>,
+    ) -> Option<String> {
+        let mut added = None;
+        egui::Window::new(self.name())
+            .default_width(560.0)
+            .default_height(360.0)
+            .open(open)
+            .resizable([true, true])
+            .scroll(true)
+            .show(ctx, |ui| {
+                self.render_method(ui);
+                self.render_transactions(ui);
+                ui.separator();
+                added = self.render_form(ui);
+                ui.separator();
+                self.render_lots(ui, quotes);
+                self.render_realized(ui);
+            });
+        added
+    }
+
+    fn render_method(&mut self, ui: &mut egui::Ui) {
+        let mut method = self.ledger.method();
+        ui.horizontal(|ui| {
+            ui.label(RichText::new("成本方法").small().color(Color32::GRAY));
+            ComboBox::from_id_salt("cost_method")
+                .selected_text(method.label())
+                .show_ui(ui, |ui| {
+                    for m in CostMethod::ALL {
+                        ui.selectable_value(&mut method, m, m.label());
+                    }
+                });
+        });
+        if method != self.ledger.method() {
+            match self.ledger.set_method(method) {
+                Ok(_) => self.refresh(),
+                Err(e) => self.error = Some(e.to_string()),
+            }
+        }
+    }
+
+    fn render_lots(&mut self, ui: &mut egui::Ui, quotes: &HashMap<String, Stock>) {
+        CollapsingHeader::new("持仓批次")
+            .id_salt("ledger_lots")
+            .show(ui, |ui| {
+                Grid::new("ledger_lots_grid")
+                    .striped(true)
+                    .num_columns(7)
+                    .show(ui, |ui| {
+                        for title in [
+                            "代码",
+                            "账户",
+                            "买入日",
+                            "数量",
+                            "成本",
+                            "单位成本",
+                            "浮动盈亏",
+                        ] {
+                            ui.label(RichText::new(title).small().color(Color32::GRAY));
+                        }
+                        ui.end_row();
+
+                        for lot in self.lots.lots() {
+                            ui.label(&lot.code);
+                            ui.label(&lot.account);
+                            ui.label(lot.opened.format("%Y-%m-%d").to_string());
+                            ui.label(lot.qty.normalize().to_string());
+                            ui.label(format_dp(lot.cost, 2));
+                            ui.label(format_dp(lot.unit_cost(), 3));
+                            let price = quotes
+                                .get(&lot.code)
+                                .map(|x| x.last_price())
+                                .filter(|x| !x.is_zero());
+                            match price.map(|x| lot.unrealized(x)) {
+                                Some(pnl) => ui
+                                    .label(RichText::new(format_dp(pnl, 2)).color(rise_color(pnl))),
+                                None => ui.label("-"),
+                            };
+                            ui.end_row();
+                        }
+                    });
+            });
+    }
+
+    fn render_realized(&mut self, ui: &mut egui::Ui) {
+        CollapsingHeader::new("已实现盈亏")
+            .id_salt("ledger_realized")
+            .show(ui, |ui| {
+                Grid::new("ledger_realized_grid")
+                    .striped(true)
+                    .num_columns(4)
+                    .show(ui, |ui| {
+                        for title in ["年份", "卖出金额", "成本", "盈亏"] {
+                            ui.label(RichText::new(title).small().color(Color32::GRAY));
+                        }
+                        ui.end_row();
+
+                        for year in self.lots.yearly() {
+                            ui.label(year.year.to_string());
+                            ui.label(format_dp(year.proceeds, 2));
+                            ui.label(format_dp(year.cost, 2));
+                            ui.label(
+                                RichText::new(format_dp(year.gain, 2)).color(rise_color(year.gain)),
+                            );
+                            ui.end_row();
+                        }
+                    });
+            });
+    }
+
+    fn render_transactions(&mut self, ui: &mut egui::Ui) {
+        let mut removed = None;
+        Grid::new("ledger")
+            .striped(true)
+            .num_columns(10)
+            .show(ui, |ui| {
+                for title in [
+                    "日期", "类型", "代码", "账户", "数量", "价格", "金额", "转入", "批次",
+                ] {
+                    ui.label(RichText::new(title).small().color(Color32::GRAY));
+                }
+                ui.end_row();
+
+                for (i, tx) in self.ledger.transactions().iter().enumerate() {
+                    ui.label(tx.date.format("%Y-%m-%d").to_string());
+                    ui.label(tx.kind.label());
+                    ui.label(&tx.code);
+                    ui.label(&tx.account);
+                    ui.label(cell(tx.kind.has_qty(), tx.qty));
+                    ui.label(cell(tx.kind.has_price(), tx.price));
+                    ui.label(cell(tx.kind.has_amount(), tx.amount));
+                    ui.label(&tx.to_account);
+                    ui.label(
+                        tx.lot
+                            .map(|x| x.format("%Y-%m-%d").to_string())
+                            .unwrap_or_default(),
+                    );
+                    let close_btn = ui.add(Button::new(
+                        RichText::new("❌")
+                            .text_style(TextStyle::Body)
+                            .color(Color32::RED),
+                    ));
+                    if close_btn.clicked() {
+                        removed = Some(i);
+                    }
+                    ui.end_row();
+                }
+            });
+
+        if let Some(i) = removed {
+            match self.ledger.remove(i) {
+                Ok(_) => self.refresh(),
+                Err(e) => self.error = Some(e.to_string()),
+            }
+        }
+    }
+
+    fn render_form(&mut self, ui: &mut egui::Ui) -> Option<String> {
+        let mut added = None;
+        let specific = self.ledger.method() == CostMethod::Specific;
+        let form = &mut self.form;
+        ui.horizontal_wrapped(|ui| {
+            ui.label(RichText::new("➕").color(Color32::LIGHT_GRAY));
+            ui.add(
+                TextEdit::singleline(&mut form.date)
+                    .hint_text("yyyy-mm-dd")
+                    .desired_width(80.0),
+            );
+            ComboBox::from_id_salt("tx_kind")
+                .selected_text(form.kind.label())
+                .width(60.0)
+                .show_ui(ui, |ui| {
+                    for kind in TxKind::ALL {
+                        ui.selectable_value(&mut form.kind, kind, kind.label());
+                    }
+                });
+            ui.add(
+                TextEdit::singleline(&mut form.code)
+                    .hint_text("code")
+                    .desired_width(70.0),
+            );
+            ui.add(
+                TextEdit::singleline(&mut form.account)
+                    .hint_text("account")
+                    .desired_width(60.0),
+            );
+            let kind = form.kind;
+            ui.add_enabled(
+                kind.has_qty(),
+                TextEdit::singleline(&mut form.qty)
+                    .hint_text("qty")
+                    .desired_width(50.0),
+            );
+            ui.add_enabled(
+                kind.has_price(),
+                TextEdit::singleline(&mut form.price)
+                    .hint_text("price")
+                    .desired_width(50.0),
+            );
+            ui.add_enabled(
+                kind.has_amount(),
+                TextEdit::singleline(&mut form.amount)
+                    .hint_text(match kind {
+                        TxKind::Buy | TxKind::Sell => "commission",
+                        _ => "amount",
+                    })
+                    .desired_width(60.0),
+            );
+            ui.add_enabled(
+                kind == TxKind::Transfer,
+                TextEdit::singleline(&mut form.to_account)
+                    .hint_text("to account")
+                    .desired_width(60.0),
+            );
+            ui.add_enabled(
+                specific && kind.has_lot(),
+                TextEdit::singleline(&mut form.lot)
+                    .hint_text("lot yyyy-mm-dd")
+                    .desired_width(80.0),
+            );
+
+            if ui.button("add").clicked() {
+                let result = form.parse().and_then(|tx| {
+                    let code = tx.code.clone();
+                    self.ledger.push(tx).map_err(|e| e.to_string())?;
+                    Ok(code)
+                });
+                match result {
+                    Ok(code) => {
+                        added = Some(code);
+                        // keep date and account for the next entry
+                        form.code.clear();
+                        form.qty.clear();
+                        form.price.clear();
+                        form.amount.clear();
+                        form.lot.clear();
+                        self.error = None;
+                    }
+                    Err(e) => self.error = Some(e),
+                }
+            }
+        });
+        if added.is_some() {
+            self.refresh();
+        }
+        if let Some(error) = &self.error {
+            ui.label(RichText::new(error).small().color(Color32::LIGHT_RED));
+        }
+        added
+    }
+
+    fn refresh(&mut self) {
+        // the ledger only accepts changes that replay
+        self.lots = self.ledger.lots().unwrap_or_default();
+        self.portfolio = self.lots.portfolio();
+    }
+}
+
+impl TxForm {
+    fn parse(&self) -> Result<Transaction, String> {
+        let date = NaiveDate::parse_from_str(self.date.trim(), "%Y-%m-%d")
+            .map_err(|_| format!("bad date: {}", self.date))?;
+        let number = |value: &str, used: bool, name: &str| -> Result<Decimal, String> {
+            match (used, value.trim()) {
+                (false, _) => Ok(Decimal::ZERO),
+                // commissions may be left out
+                (true, "")
+                    if name == "amount" && matches!(self.kind, TxKind::Buy | TxKind::Sell) =>
+                {
+                    Ok(Decimal::ZERO)
+                }
+                (true, x) => x.parse::<Decimal>().map_err(|_| format!("bad {name}: {x}")),
+            }
+        };
+
+        Ok(Transaction {
+            date,
+            kind: self.kind,
+            code: self.code.trim().to_string(),
+            account: self.account.trim().to_string(),
+            qty: number(&self.qty, self.kind.has_qty(), "quantity")?,
+            price: number(&self.price, self.kind.has_price(), "price")?,
+            amount: number(&self.amount, self.kind.has_amount(), "amount")?,
+            to_account: match self.kind {
+                TxKind::Transfer => self.to_account.trim().to_string(),
+                _ => String::new(),
+            },
+            lot: match self.lot.trim() {
+                "" => None,
+                x if self.kind.has_lot() => Some(
+                    NaiveDate::parse_from_str(x, "%Y-%m-%d")
+                        .map_err(|_| format!("bad lot: {x}"))?,
+                ),
+                _ => None,
+            },
+        })
+    }
+}
+
+fn cell(used: bool, value: impl ToString) -> String {
+    if used {
+        value.to_string()
+    } else {
+        "-".to_string()
+    }
+}
+
+/// Positions from before the ledger become buys at their average cost.
+fn migrate(portfolio: &Portfolio) -> Ledger {
+    let today = Local::now().date_naive();
+    let mut ledger = Ledger::default();
+    for p in portfolio.positions.iter() {
+        let tx = Transaction {
+            date: today,
+            kind: TxKind::Buy,
+            code: p.code.clone(),
+            account: p.account.clone(),
+            qty: p.qty,
+            price: p.avg_cost,
+            ..Default::default()
+        };
+        if let Err(e) = ledger.push(tx) {
+            tracing::warn!("dropping position {}: {e}", p.code);
+        }
+    }
+    ledger
+}
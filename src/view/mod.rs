@@ -1,21 +1,221 @@
 pub mod bitcoin_view;
+pub mod holdings;
+mod kline_chart;
+pub mod ledger;
 pub mod setting_view;
 pub mod stock_setting;
 pub mod stocks;
+mod timeshare_chart;
+mod widgets;
 
 pub use bitcoin_view::*;
+pub use holdings::*;
+pub use ledger::*;
 pub use stocks::*;
 
+use std::collections::HashMap;
+
 use serde::{Deserialize, Serialize};
 
-use crate::model::stock;
+use crate::model::{
+    stock::{self, KLineScale},
+    Market,
+};
+
+use kline_chart::KlineChart;
+
+/// Bumped whenever the persisted layout of [`Setting`] changes.
+const SETTING_VERSION: u32 = 1;
+
+// 默认三大指数, 港股美股等窗口第一次打开时再加入
+const DEFAULT_ZH_STOCKS: &str = "sh000001,sz399001,sh000300,bj899050,sz399006,sh601127,sz000625";
+const DEFAULT_HK_STOCKS: &str = "hkHSI,hk00700,hk09988";
+const DEFAULT_US_STOCKS: &str = "gb_ixic,gb_aapl,gb_nvda";
+
+fn default_stocks(market: Market) -> Vec<String> {
+    let codes = match market {
+        Market::ZhShare => DEFAULT_ZH_STOCKS,
+        Market::HkShare => DEFAULT_HK_STOCKS,
+        Market::UsShare => DEFAULT_US_STOCKS,
+    };
+    codes.split(',').map(String::from).collect()
+}
 
-#[derive(Default, Serialize, Deserialize)]
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
 struct Setting {
+    version: u32,
+    open: bool,
+    show_name: bool,
+    show_color: bool,
+    interval: u32,
+    /// Watchlist in display order.
+    stocks: Vec<String>,
+    /// Kline scale picked per code.
+    scales: HashMap<String, KLineScale>,
+    /// Markets whose default codes were offered once.
+    seeded: Vec<Market>,
+    chart: KlineChart,
+    #[serde(skip)]
+    adding_code: HashMap<Market, String>,
+}
+
+impl Default for Setting {
+    fn default() -> Self {
+        Self {
+            version: SETTING_VERSION,
+            open: false,
+            show_name: false,
+            show_color: false,
+            interval: 0,
+            stocks: default_stocks(Market::ZhShare),
+            scales: HashMap::new(),
+            seeded: vec![Market::ZhShare],
+            chart: KlineChart::default(),
+            adding_code: HashMap::new(),
+        }
+    }
+}
+
+// before versioning the watchlist was a comma separated string
+#[derive(Deserialize)]
+struct SettingV0 {
     open: bool,
     show_name: bool,
     show_color: bool,
     interval: u32,
     stocks: String,
-    adding_code: String,
+}
+
+impl From<SettingV0> for Setting {
+    fn from(old: SettingV0) -> Self {
+        let stocks: Vec<String> = old
+            .stocks
+            .split(',')
+            .map(str::trim)
+            .filter(|x| !x.is_empty())
+            .map(String::from)
+            .collect();
+        let default = Setting::default();
+        Self {
+            open: old.open,
+            show_name: old.show_name,
+            show_color: old.show_color,
+            interval: old.interval,
+            stocks: if stocks.is_empty() {
+                default.stocks
+            } else {
+                stocks
+            },
+            ..default
+        }
+    }
+}
+
+// just the version of a saved record, it picks how the rest is read
+#[derive(Deserialize)]
+struct Versioned {
+    // records from before versioning have none
+    #[serde(default)]
+    version: u32,
+}
+
+/// Layout version of the record under `key`, `None` if nothing is saved.
+fn stored_version(storage: &dyn eframe::Storage, key: &str) -> Option<u32> {
+    eframe::get_value::<Versioned>(storage, key).map(|x| x.version)
+}
+
+impl Setting {
+    fn load(storage: &dyn eframe::Storage) -> Self {
+        let setting = match stored_version(storage, eframe::APP_KEY) {
+            None => None,
+            Some(0) => eframe::get_value::<SettingV0>(storage, eframe::APP_KEY).map(Into::into),
+            Some(_) => eframe::get_value::<Setting>(storage, eframe::APP_KEY),
+        }
+        .unwrap_or_default();
+        Self {
+            version: SETTING_VERSION,
+            ..setting
+        }
+    }
+
+    fn save(&self, storage: &mut dyn eframe::Storage) {
+        eframe::set_value(storage, eframe::APP_KEY, self);
+    }
+
+    /// Adds `code` to the end of the watchlist, false if already there.
+    fn watch(&mut self, code: &str) -> bool {
+        if self.stocks.iter().any(|x| x == code) {
+            return false;
+        }
+        self.stocks.push(code.to_string());
+        true
+    }
+
+    fn unwatch(&mut self, code: &str) {
+        self.stocks.retain(|x| x != code);
+        self.scales.remove(code);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use eframe::Storage;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct Memory(HashMap<String, String>);
+
+    impl eframe::Storage for Memory {
+        fn get_string(&self, key: &str) -> Option<String> {
+            self.0.get(key).cloned()
+        }
+
+        fn set_string(&mut self, key: &str, value: String) {
+            self.0.insert(key.to_string(), value);
+        }
+
+        fn flush(&mut self) {}
+    }
+
+    #[test]
+    fn test_setting_migration() {
+        let mut storage = Memory::default();
+        storage.set_string(
+            eframe::APP_KEY,
+            r#"(open:true,show_name:false,show_color:true,interval:500,stocks:"sh600000, hk00700,",adding_code:"gb_")"#
+                .to_string(),
+        );
+        assert_eq!(stored_version(&storage, eframe::APP_KEY), Some(0));
+        let setting = Setting::load(&storage);
+        assert_eq!(setting.version, SETTING_VERSION);
+        assert!(setting.open && setting.show_color);
+        assert_eq!(setting.interval, 500);
+        assert_eq!(setting.stocks, ["sh600000", "hk00700"]);
+        assert!(setting.adding_code.is_empty());
+
+        // round trip
+        let mut setting = setting;
+        assert!(setting.watch("gb_aapl"));
+        assert!(!setting.watch("sh600000"));
+        setting.scales.insert("gb_aapl".into(), KLineScale::Day);
+        setting.unwatch("hk00700");
+        setting.save(&mut storage);
+        assert_eq!(
+            stored_version(&storage, eframe::APP_KEY),
+            Some(SETTING_VERSION)
+        );
+
+        let setting = Setting::load(&storage);
+        assert_eq!(setting.stocks, ["sh600000", "gb_aapl"]);
+        assert_eq!(setting.scales["gb_aapl"], KLineScale::Day);
+
+        assert_eq!(setting.seeded, [Market::ZhShare]);
+
+        // nothing saved yet
+        let setting = Setting::load(&Memory::default());
+        assert_eq!(setting.stocks.len(), 7);
+        assert_eq!(setting.seeded, [Market::ZhShare]);
+    }
 }
@@ -1,15 +1,61 @@
-use egui::{global_theme_preference_buttons, Color32, RichText};
+use egui::{global_theme_preference_buttons, Color32, RichText, ThemePreference};
+use serde::{Deserialize, Serialize};
 
-#[derive(Default)]
+use super::stored_version;
+
+const SETTINGS_KEY: &str = "settings";
+const SETTINGS_VERSION: u32 = 1;
+
+/// Window toggles and theme, saved across runs.
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
 pub struct Settings {
+    version: u32,
+    pub theme: ThemePreference,
     pub open: bool,
     pub zh_share: bool,
     pub hk_share: bool,
     pub us_share: bool,
     pub btc: bool,
+    pub holdings: bool,
+    pub ledger: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            version: SETTINGS_VERSION,
+            theme: ThemePreference::System,
+            open: false,
+            zh_share: false,
+            hk_share: false,
+            us_share: false,
+            btc: false,
+            holdings: false,
+            ledger: false,
+        }
+    }
 }
 
 impl Settings {
+    pub fn load(storage: Option<&dyn eframe::Storage>) -> Self {
+        // version 1 is the first saved layout
+        let settings: Self = storage
+            .and_then(|x| match stored_version(x, SETTINGS_KEY)? {
+                0 => None,
+                _ => eframe::get_value(x, SETTINGS_KEY),
+            })
+            .unwrap_or_default();
+        Self {
+            version: SETTINGS_VERSION,
+            ..settings
+        }
+    }
+
+    pub fn save(&self, storage: &mut dyn eframe::Storage) {
+        eframe::set_value(storage, SETTINGS_KEY, self);
+    }
+
     pub fn show(&mut self, ui: &mut egui::Ui, frame: &mut eframe::Frame) {
         ui.label("主题:");
         global_theme_preference_buttons(ui);
@@ -26,6 +72,8 @@ impl Settings {
                     hk_share,
                     us_share,
                     btc,
+                    holdings,
+                    ledger,
                     ..
                 } = self;
 
@@ -55,6 +103,8 @@ impl Settings {
                     us_share,
                     RichText::new("💸 美股").color(Color32::LIGHT_GREEN),
                 );
+                ui.checkbox(holdings, RichText::new("📒 持仓").color(Color32::GOLD));
+                ui.checkbox(ledger, RichText::new("🧾 交易").color(Color32::GOLD));
             });
         });
     }
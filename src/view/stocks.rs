@@ -1,22 +1,46 @@
-use std::{collections::HashMap, thread};
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    thread,
+    time::{Duration, Instant},
+};
 
-use crossbeam::channel::{Receiver, Sender};
+use chrono::{Datelike, NaiveDate};
+use crossbeam::channel::{Receiver, Sender, TryRecvError};
 use eframe::CreationContext;
 use egui::{
-    menu, Align, Align2, Button, CollapsingHeader, Color32, Context, CursorIcon, Frame, Grid,
-    Label, Layout, RichText, Separator, Shadow, Slider, Stroke, TextStyle, TopBottomPanel, Vec2,
-};
-use egui_plot::{
-    AxisHints, Bar, BarChart, BoxElem, BoxPlot, BoxSpread, HLine, Plot, PlotPoint, Text,
+    menu, popup_below_widget, Align, Align2, Button, CollapsingHeader, Color32, Context,
+    CursorIcon, Frame, Grid, Label, Layout, PopupCloseBehavior, RichText, Separator, Shadow,
+    Slider, TextStyle, TopBottomPanel, Vec2,
 };
+use egui_dnd::dnd;
+use egui_plot::{Bar, BarChart, HLine, Plot, PlotPoint, Text};
 
 use crate::{
-    backend::{StockCammnd, StockTask, TxStockData},
-    model::Stock,
-    view::stock::KLineScale,
+    app::WealthTracker,
+    backend::{
+        recorder::{self, Recorder, ReplaySpeed},
+        Endpoint, FetchError, FetchStatus, StockCammnd, StockTask, TxStockData, WatchAck,
+        IDLE_INTERVAL,
+    },
+    model::{
+        bars::Tick,
+        calendar::{Calendar, Session, HOLIDAYS_FILE},
+        kline_store::{CacheEntry, KlineStore},
+        price::{format_amount, format_dp, format_price, to_f64},
+        symbols::SymbolMaster,
+        valid_code, Market, Price, Stock,
+    },
+    view::stock::{KLineScale, MAX_KLINES},
 };
 
-use super::Setting;
+use super::{
+    default_stocks,
+    kline_chart::ChartTab,
+    timeshare_chart::show_timeshare,
+    widgets::{kline_sparkline, market_badge, rise_color},
+    Setting,
+};
 
 #[derive(Default)]
 pub struct StockTrackerView {
@@ -26,6 +50,74 @@ pub struct StockTrackerView {
     tx: Option<Sender<StockCammnd>>,
     rx: Option<Receiver<TxStockData>>,
     time: String,
+    status: HashMap<Endpoint, FetchStatus>,
+    last_error: Option<(FetchError, chrono::DateTime<chrono::Local>)>,
+    // added from the setting panel, waiting for the backend
+    adding: HashSet<String>,
+    add_error: Option<String>,
+    symbols: SymbolMaster,
+    // keywords already sent to the suggest endpoint
+    searched: HashSet<String>,
+    // the keyword typed last, asked once typing pauses
+    search_due: Option<(String, Instant)>,
+    calendar: Calendar,
+    // codes waiting for older klines
+    kline_pending: HashSet<String>,
+    // nothing older than the held klines
+    kline_exhausted: HashSet<(String, KLineScale)>,
+    // codes whose 分时 was asked since the tab opened
+    minutes_asked: HashSet<String>,
+    // klines on disk, listed when asked
+    cache: Option<Vec<CacheEntry>>,
+    data_dir: Option<PathBuf>,
+    recorder: Option<Recorder>,
+    // the backend channel, parked while a recording plays
+    live_rx: Option<Receiver<TxStockData>>,
+    replay_file: Option<PathBuf>,
+    replay_speed: ReplaySpeed,
+    replay_done: bool,
+}
+
+// how long an error stays in the status bar
+const ERROR_DISPLAY_SECS: i64 = 10;
+
+const SYMBOLS_KEY: &str = "symbols";
+const MAX_CANDIDATES: usize = 8;
+const SEARCH_DELAY: Duration = Duration::from_millis(300);
+// the master keeps the answers, forgetting the keywords only costs a request
+const MAX_SEARCHED: usize = 256;
+const KLINE_CACHE_DIR: &str = "klines";
+const RECORDINGS_DIR: &str = "recordings";
+
+impl Endpoint {
+    pub(crate) fn label(&self) -> &'static str {
+        match self {
+            Endpoint::Quote => "quote",
+            Endpoint::Kline => "kline",
+            Endpoint::Minutes => "minutes",
+            Endpoint::Stream => "stream",
+            Endpoint::Search => "search",
+            Endpoint::Cache => "cache",
+            Endpoint::Replay => "replay",
+        }
+    }
+
+    /// No success for this long means the data shown is stale. Out of
+    /// `live` sessions quotes are polled slowly and klines not at all.
+    pub(crate) fn stale_after(&self, live: bool) -> Duration {
+        match self {
+            Endpoint::Quote if live => Duration::from_secs(5),
+            Endpoint::Quote => IDLE_INTERVAL + Duration::from_secs(30),
+            Endpoint::Kline if live => Duration::from_secs(180),
+            Endpoint::Kline => Duration::MAX,
+            // a pong at least every heartbeat
+            Endpoint::Stream => Duration::from_secs(60),
+            // on demand, never stale
+            Endpoint::Minutes | Endpoint::Search | Endpoint::Cache | Endpoint::Replay => {
+                Duration::MAX
+            }
+        }
+    }
 }
 
 impl StockTrackerView {
@@ -33,6 +125,14 @@ impl StockTrackerView {
         "Stock Tracker"
     }
 
+    fn title(&self, market: Market) -> &'static str {
+        match market {
+            Market::ZhShare => self.name(),
+            Market::HkShare => "HK Stock Tracker",
+            Market::UsShare => "US Stock Tracker",
+        }
+    }
+
     fn update_time(&mut self) {
         self.time = format!("{}", chrono::Local::now().format("%Y-%m-%d %H:%M:%S"));
     }
@@ -43,63 +143,232 @@ impl StockTrackerView {
 
         let mut app = StockTrackerView::default();
 
+        let mut cached = vec![];
         if let Some(storage) = cc.storage {
-            if let Some(setting) = eframe::get_value(storage, eframe::APP_KEY) {
-                app.setting = setting
-            }
+            app.setting = Setting::load(storage);
+            cached = eframe::get_value(storage, SYMBOLS_KEY).unwrap_or_default();
+        }
+        app.symbols = SymbolMaster::new(cached);
+        let codes = app.setting.stocks.join(",");
+        app.data_dir = eframe::storage_dir(WealthTracker::name());
+        if let Some(dir) = &app.data_dir {
+            app.calendar = Calendar::load(dir);
+        }
+        let year = chrono::Local::now().year();
+        for market in app.calendar.uncovered(year) {
+            tracing::warn!("no {year} holidays of {market:?}, only weekends are closed");
         }
-        let mut codes = app.setting.stocks.clone();
+        let store = app
+            .data_dir
+            .as_ref()
+            .map(|dir| KlineStore::open(dir.join(KLINE_CACHE_DIR)));
+        let calendar = app.calendar.clone();
+        thread::spawn(|| {
+            let mut task = StockTask::new(rx, tx2, codes).with_calendar(calendar);
+            if let Some(store) = store {
+                task = task.with_store(store);
+            }
+            task.run()
+        });
 
-        // 默认三大指数
-        if codes.is_empty() {
-            codes = String::from("sh000001,sz399001,sh000300,bj899050,sz399006,sh601127,sz000625");
+        if app.setting.interval > 0 {
+            let _ = tx.send(StockCammnd::SetInterval(app.setting.interval));
+        }
+        for (code, scale) in &app.setting.scales {
+            // hk minute scales saved before they were dropped
+            if scale.is_available(Market::from_code(code)) {
+                let _ = tx.send(StockCammnd::StockKLine(code.clone(), *scale));
+            }
         }
-        thread::spawn(|| StockTask::new(rx, tx2, codes).run());
         app.tx = Some(tx);
         app.rx = Some(rx2);
         app
     }
 
+    pub fn save(&self, storage: &mut dyn eframe::Storage) {
+        self.setting.save(storage);
+        eframe::set_value(storage, SYMBOLS_KEY, &self.symbols.symbols());
+    }
+
+    /// Latest quotes by code.
+    pub fn stocks(&self) -> &HashMap<String, Stock> {
+        &self.data
+    }
+
+    /// Asks the backend to quote `code` too.
+    pub fn watch(&self, code: &str) {
+        if self.data.contains_key(code) {
+            return;
+        }
+        if let Some(tx) = &self.tx {
+            let _ = tx.send(StockCammnd::StockAdd(code.to_string()));
+        }
+    }
+
+    /// Watches the default codes of `market` the first time its window
+    /// opens, unless some are watched already.
+    fn seed_market(&mut self, market: Market) {
+        if self.setting.seeded.contains(&market) {
+            return;
+        }
+        self.setting.seeded.push(market);
+        if self
+            .setting
+            .stocks
+            .iter()
+            .any(|x| Market::from_code(x) == market)
+        {
+            return;
+        }
+        for code in default_stocks(market) {
+            self.adding.insert(code.clone());
+            self.send(StockCammnd::StockAdd(code));
+        }
+    }
+
+    fn on_watch_ack(&mut self, ack: WatchAck) {
+        match ack {
+            WatchAck::Added(code) => {
+                // codes watched for the ledger stay out of the saved list
+                if self.adding.remove(&code) {
+                    self.setting.watch(&code);
+                    self.add_error = None;
+                }
+            }
+            WatchAck::Rejected { code, reason } => {
+                self.adding.remove(&code);
+                self.add_error = Some(format!("{code}: {reason}"));
+            }
+            // a list fetched before the delete may have put it back
+            WatchAck::Removed(code) => {
+                self.data.remove(&code);
+            }
+            WatchAck::Order(codes) => {
+                self.setting
+                    .stocks
+                    .sort_by_key(|x| codes.iter().position(|c| c == x));
+            }
+        }
+    }
+
     fn receiver(&mut self) {
-        if let Some(rx) = &self.rx {
-            match rx.try_recv() {
-                Ok(data) => match data {
-                    TxStockData::Stock(stock) => {
-                        if let Some(s) = self.data.get_mut(&stock.code) {
-                            s.data = stock.data.clone();
-                        } else {
-                            self.data.insert(stock.code.clone(), stock);
+        let Some(rx) = self.rx.clone() else {
+            return;
+        };
+        // live market data is dropped while replaying, stopping asks for it
+        // afresh; answers to this session's commands still land
+        if let Some(live) = self.live_rx.clone() {
+            for data in live.try_iter().filter(answers_command) {
+                self.on_data(data);
+            }
+        }
+        loop {
+            let data = match rx.try_recv() {
+                Ok(data) => data,
+                Err(TryRecvError::Disconnected) => {
+                    self.replay_done = self.live_rx.is_some();
+                    break;
+                }
+                Err(TryRecvError::Empty) => break,
+            };
+            // recorded answers were for another session
+            if self.live_rx.is_some() && answers_command(&data) {
+                continue;
+            }
+            if let Some(recorder) = &mut self.recorder {
+                if let Err(e) = recorder.record(&data) {
+                    tracing::warn!("recording stopped: {e}");
+                    self.recorder = None;
+                }
+            }
+            self.on_data(data);
+        }
+    }
+
+    fn on_data(&mut self, data: TxStockData) {
+        match data {
+            TxStockData::Stock(stock) => {
+                if let Some(s) = self.data.get_mut(&stock.code) {
+                    s.data = stock.data.clone();
+                } else {
+                    self.data.insert(stock.code.clone(), *stock);
+                }
+            }
+            TxStockData::StockList(stocks) => {
+                self.update_time();
+                stocks.iter().for_each(|stock| {
+                    if let Some(s) = self.data.get_mut(&stock.code) {
+                        // the last candle moves with every quote in between
+                        // kline refreshes
+                        let market = stock.market();
+                        if let Some(tick) = Tick::between(market, &s.data, &stock.data) {
+                            s.kline.fold_tick(market, &tick);
+                            s.minutes.fold_tick(market, &tick);
                         }
+                        s.data = stock.data.clone();
+                    } else {
+                        self.data.insert(stock.code.to_string(), stock.clone());
                     }
-                    TxStockData::StockList(stocks) => {
-                        self.update_time();
-                        stocks.iter().for_each(|stock| {
-                            if let Some(s) = self.data.get_mut(&stock.code) {
-                                s.data = stock.data.clone();
-                            } else {
-                                self.data.insert(stock.code.to_string(), stock.clone());
-                            }
-                        });
-                    }
-                    TxStockData::Kline((code, kline)) => {
-                        //
-                        if let Some(s) = self.data.get_mut(&code) {
-                            s.kline = kline;
-                        }
+                });
+            }
+            TxStockData::Kline((code, kline)) => {
+                if let Some(s) = self.data.get_mut(&code) {
+                    s.kline.merge_newer(kline);
+                }
+            }
+            TxStockData::TimeShare((code, mut minutes)) => {
+                if let Some(s) = self.data.get_mut(&code) {
+                    minutes.date = NaiveDate::parse_from_str(&s.data.date, "%Y-%m-%d").ok();
+                    s.minutes = minutes;
+                }
+            }
+            TxStockData::KlineHistory((code, older)) => {
+                self.kline_pending.remove(&code);
+                if let Some(s) = self.data.get_mut(&code) {
+                    // the scale may have been switched meanwhile
+                    let scale = older.scale;
+                    if s.kline.scale == scale && s.kline.merge_older(older) == 0 {
+                        self.kline_exhausted.insert((code, scale));
                     }
-                },
-                Err(e) => {
-                    let _ = e;
                 }
             }
+            TxStockData::Error(error) => {
+                if let (Endpoint::Kline, Some(code)) = (error.endpoint, &error.code) {
+                    self.kline_pending.remove(code);
+                }
+                self.last_error = Some((error, chrono::Local::now()));
+            }
+            TxStockData::Status(status) => {
+                self.status.insert(status.endpoint, status);
+            }
+            TxStockData::Watch(ack) => self.on_watch_ack(ack),
+            TxStockData::Symbols(_keyword, symbols) => self.symbols.merge(symbols),
+            TxStockData::Cache(entries) => self.cache = Some(entries),
         }
     }
 }
 
+// watch acks, symbols and cache listings, not market data
+fn answers_command(data: &TxStockData) -> bool {
+    matches!(
+        data,
+        TxStockData::Watch(_) | TxStockData::Symbols(..) | TxStockData::Cache(_)
+    )
+}
+
 impl StockTrackerView {
     pub fn show(&mut self, ctx: &Context, open: &mut bool) {
+        self.show_market(ctx, Market::ZhShare, open);
+    }
+
+    /// Watchlist window of one market, all windows share the same backend task.
+    pub fn show_market(&mut self, ctx: &Context, market: Market, open: &mut bool) {
         let _frame = egui::Frame::none().shadow(Shadow::NONE);
-        egui::Window::new(self.name())
+        self.receiver();
+        if *open {
+            self.seed_market(market);
+        }
+        egui::Window::new(self.title(market))
             .default_width(320.0)
             .default_height(480.0)
             // .frame(frame)
@@ -108,21 +377,38 @@ impl StockTrackerView {
             .scroll(true)
             .show(ctx, |ui| {
                 ctx.request_repaint();
-                self._render_top_panel(ctx, ui);
-                self.receiver();
-                self.render_setting(ctx, ui);
-                self.render_stocks(ctx, ui);
+                self._render_top_panel(ctx, ui, market);
+                // the main window manages the whole watchlist
+                if market == Market::ZhShare {
+                    self.render_setting(ctx, ui);
+                } else {
+                    self.render_add_box(ctx, ui, market);
+                }
+                self.render_status_bar(ui, market);
+                self.render_stocks(ctx, ui, market);
             });
     }
 
-    fn render_stocks(&mut self, ctx: &Context, ui: &mut egui::Ui) {
+    fn render_stocks(&mut self, ctx: &Context, ui: &mut egui::Ui, market: Market) {
         ui.add_space(8.0);
 
-        Grid::new("Stock list")
+        let session = self.calendar.session(market, chrono::Utc::now());
+        Grid::new(("Stock list", market))
             .max_col_width(60.0)
             .striped(true)
             .show(ui, |ui| {
-                self.data.values_mut().for_each(|stock| {
+                let order = &self.setting.stocks;
+                let mut stocks: Vec<&mut Stock> = self.data.values_mut().collect();
+                // watchlist order, codes only quoted for the ledger go last
+                stocks.sort_by_key(|x| {
+                    let rank = order.iter().position(|c| *c == x.code);
+                    (rank.unwrap_or(usize::MAX), x.code.clone())
+                });
+                stocks.into_iter().for_each(|stock| {
+                    if stock.market() != market {
+                        return;
+                    }
+
                     // name
                     ui.centered_and_justified(|ui| {
                         ui.add(
@@ -137,20 +423,17 @@ impl StockTrackerView {
                     // amount
                     ui.centered_and_justified(|ui| {
                         ui.add(Label::new(
-                            RichText::new(stock.data_new().to_string())
+                            RichText::new(format_price(&stock.code, stock.data_new()))
                                 .text_style(egui::TextStyle::Body),
-                        ));
+                        ))
+                        .on_hover_text(price_hover_text(stock, session));
                     });
 
-                    let color = match stock.data_rise_per() {
-                        p if p < 0.0 => Color32::GREEN,
-                        n if n > 0.0 => Color32::RED,
-                        _ => Color32::WHITE,
-                    };
+                    let color = rise_color(stock.data_rise_per());
 
                     ui.centered_and_justified(|ui| {
                         ui.add(Label::new(
-                            RichText::new(stock.data_rise_per().to_string())
+                            RichText::new(format_dp(stock.data_rise_per(), 2))
                                 .text_style(egui::TextStyle::Body)
                                 .color(color),
                         ));
@@ -164,50 +447,8 @@ impl StockTrackerView {
                     // ui.label(text);
 
                     ui.centered_and_justified(|ui| {
-                        let boxs: Vec<BoxElem> = stock
-                            .kline
-                            .klines
-                            .iter()
-                            .enumerate()
-                            .map(|(i, x)| {
-                                let fill_color = if x.close < x.open {
-                                    Color32::GREEN
-                                } else {
-                                    Color32::RED
-                                };
-
-                                BoxElem::new(
-                                    i as f64,
-                                    BoxSpread::new(
-                                        x.low,
-                                        x.open,
-                                        (x.open + x.close) / 2.0,
-                                        x.close,
-                                        x.high,
-                                    ),
-                                )
-                                .stroke(Stroke::new(0.2, fill_color))
-                                .fill(fill_color.linear_multiply(0.1))
-                                .box_width(0.8)
-                            })
-                            .collect();
-
-                        let box1 = BoxPlot::new(boxs);
-
-                        let plot = Plot::new(format!("{}_kline", stock.code))
-                            .allow_zoom(false)
-                            .allow_drag(false)
-                            .allow_scroll(false)
-                            .show_grid([false, false])
-                            .show_axes([false, false])
-                            .sharp_grid_lines(false)
-                            // .show_background(false)
-                            .width(50.0)
-                            .height(16.0)
-                            .show(ui, |plot_ui| {
-                                plot_ui.box_plot(box1);
-                            })
-                            .response;
+                        let plot =
+                            kline_sparkline(ui, format!("{}_kline", stock.code), &stock.kline);
 
                         if plot.clicked() {
                             // request klines
@@ -224,134 +465,87 @@ impl StockTrackerView {
                                     egui::CentralPanel::default().show(ctx, |ui| {
                                         ui.vertical(|ui| {
                                             ui.horizontal_wrapped(|ui| {
-                                                if ui
-                                                    .selectable_value(
-                                                        &mut stock.kline.scale,
-                                                        KLineScale::Munute5,
-                                                        "5",
-                                                    )
-                                                    .clicked()
-                                                {
-                                                    //TODO: request scale kline data
-                                                    if let Some(tx) = &self.tx {
-                                                        let _ = tx.send(StockCammnd::StockKLine(
-                                                            stock.code.to_string(),
-                                                            KLineScale::Munute5,
-                                                        ));
+                                                for tab in ChartTab::ALL {
+                                                    if ui
+                                                        .selectable_value(
+                                                            &mut self.setting.chart.tab,
+                                                            tab,
+                                                            tab.label(),
+                                                        )
+                                                        .clicked()
+                                                    {
+                                                        // asked again on every switch
+                                                        self.minutes_asked.remove(&stock.code);
                                                     }
                                                 }
-                                                if ui
-                                                    .selectable_value(
-                                                        &mut stock.kline.scale,
-                                                        KLineScale::Munute15,
-                                                        "15",
-                                                    )
-                                                    .clicked()
-                                                {
-                                                    //TODO: request scale kline data
-                                                    if let Some(tx) = &self.tx {
-                                                        let _ = tx.send(StockCammnd::StockKLine(
-                                                            stock.code.to_string(),
-                                                            KLineScale::Munute15,
-                                                        ));
-                                                    }
+                                                if self.setting.chart.tab != ChartTab::Kline {
+                                                    return;
                                                 }
-                                                if ui
-                                                    .selectable_value(
-                                                        &mut stock.kline.scale,
-                                                        KLineScale::Munute30,
-                                                        "30",
-                                                    )
-                                                    .clicked()
+                                                ui.separator();
+                                                let market = stock.market();
+                                                for scale in KLineScale::ALL
+                                                    .into_iter()
+                                                    .filter(|x| x.is_available(market))
                                                 {
-                                                    //TODO: request scale kline data
-                                                    if let Some(tx) = &self.tx {
-                                                        let _ = tx.send(StockCammnd::StockKLine(
-                                                            stock.code.to_string(),
-                                                            KLineScale::Munute30,
-                                                        ));
+                                                    if ui
+                                                        .selectable_value(
+                                                            &mut stock.kline.scale,
+                                                            scale,
+                                                            scale.label(),
+                                                        )
+                                                        .clicked()
+                                                    {
+                                                        // candles of another scale don't merge
+                                                        stock.kline.klines.clear();
+                                                        self.setting
+                                                            .scales
+                                                            .insert(stock.code.clone(), scale);
+                                                        if let Some(tx) = &self.tx {
+                                                            let _ =
+                                                                tx.send(StockCammnd::StockKLine(
+                                                                    stock.code.to_string(),
+                                                                    scale,
+                                                                ));
+                                                        }
                                                     }
                                                 }
-                                                if ui
-                                                    .selectable_value(
-                                                        &mut stock.kline.scale,
-                                                        KLineScale::Day,
-                                                        "day",
-                                                    )
-                                                    .clicked()
-                                                {
-                                                    //TODO: request scale kline data
+                                            });
+
+                                            if self.setting.chart.tab == ChartTab::TimeShare {
+                                                if self.minutes_asked.insert(stock.code.clone()) {
                                                     if let Some(tx) = &self.tx {
-                                                        let _ = tx.send(StockCammnd::StockKLine(
+                                                        let _ = tx.send(StockCammnd::TimeShare(
                                                             stock.code.to_string(),
-                                                            KLineScale::Day,
                                                         ));
                                                     }
                                                 }
-                                            });
-
-                                            //
-                                            let mut x_axes = vec![];
-                                            let boxs: Vec<BoxElem> = stock
-                                                .kline
-                                                .klines
-                                                .iter()
-                                                .enumerate()
-                                                .map(|(i, x)| {
-                                                    let x_hints = AxisHints::new_x().label("Time");
-                                                    x_axes.push(x_hints);
-
-                                                    let fill_color = if x.close < x.open {
-                                                        Color32::GREEN
-                                                    } else {
-                                                        Color32::RED
-                                                    };
-
-                                                    BoxElem::new(
-                                                        // x.day.and_utc().timestamp() as f64,
-                                                        i as f64,
-                                                        BoxSpread::new(
-                                                            x.low,
-                                                            x.open,
-                                                            (x.open + x.close) / 2.0,
-                                                            x.close,
-                                                            x.high,
-                                                        ),
-                                                    )
-                                                    .name(x.date.clone())
-                                                    .stroke(Stroke::new(0.2, fill_color))
-                                                    .fill(fill_color.linear_multiply(0.05))
-                                                    .box_width(0.8)
-                                                })
-                                                .collect();
-                                            let box1 = BoxPlot::new(boxs);
-                                            Plot::new(format!("{}_kline", stock.code))
-                                                .show_background(false)
-                                                .show_grid(true)
-                                                .allow_drag([true, false])
-                                                .custom_x_axes(vec![
-                                                    AxisHints::new_x().label("Time (s)")
-                                                ])
-                                                // .custom_x_axes(vec![
-                                                //     AxisHints::new_x().label("Time (s)"),
-                                                //     AxisHints::new_x().label("Time").formatter(
-                                                //         |x, _| {
-                                                //             let date_time =
-                                                //                 DateTime::from_timestamp(
-                                                //                     x.value as i64,
-                                                //                     0,
-                                                //                 )
-                                                //                 .unwrap();
-                                                //             date_time
-                                                //                 .format("%Y-%m-%d %H:%M:%S")
-                                                //                 .to_string()
-                                                //         },
-                                                //     ),
-                                                // ])
-                                                .show(ui, |plot_ui| {
-                                                    plot_ui.box_plot(box1);
-                                                })
-                                                .response;
+                                                show_timeshare(
+                                                    ui,
+                                                    &stock.code,
+                                                    stock.market(),
+                                                    &stock.minutes,
+                                                    to_f64(stock.data.closing),
+                                                );
+                                                return;
+                                            }
+                                            let wants_older = self.setting.chart.show(
+                                                ui,
+                                                &stock.code,
+                                                &stock.kline,
+                                            );
+                                            let held = stock.kline.klines.len();
+                                            let key = (stock.code.clone(), stock.kline.scale);
+                                            if wants_older
+                                                && held < MAX_KLINES
+                                                && !self.kline_exhausted.contains(&key)
+                                                && self.kline_pending.insert(key.0.clone())
+                                            {
+                                                if let Some(tx) = &self.tx {
+                                                    let _ = tx.send(StockCammnd::KLineHistory(
+                                                        key.0, key.1, held,
+                                                    ));
+                                                }
+                                            }
                                         });
                                     });
                                     if ctx.input(|i| i.viewport().close_requested()) {
@@ -368,7 +562,7 @@ impl StockTrackerView {
                             .data_bids()
                             .iter()
                             .map(|(v, p)| {
-                                Bar::new((p - stock.data_new()).into(), *v as f64).width(0.001)
+                                Bar::new(to_f64(p - stock.data_new()), *v as f64).width(0.001)
                             })
                             .collect::<Vec<Bar>>();
 
@@ -380,7 +574,7 @@ impl StockTrackerView {
                             .data_asks()
                             .iter()
                             .map(|(v, p)| {
-                                Bar::new((p - stock.data_new()).into(), *v as f64).width(0.001)
+                                Bar::new(to_f64(p - stock.data_new()), *v as f64).width(0.001)
                             })
                             .collect();
 
@@ -414,17 +608,25 @@ impl StockTrackerView {
                                         .map(|(v, p)| {
                                             bids_text.push(
                                                 Text::new(
-                                                    PlotPoint::new(-10.0, p - stock.data_new()),
+                                                    PlotPoint::new(
+                                                        -10.0,
+                                                        to_f64(p - stock.data_new()),
+                                                    ),
                                                     format!(
-                                                        "{:.2}    {}  -  {}  ",
-                                                        (*v as f32) * (*p) * 0.01,
+                                                        "{}    {}  -  {}  ",
+                                                        // lots of 100 shares, in 万
+                                                        format_dp(
+                                                            Price::from(*v) * p
+                                                                / Price::ONE_HUNDRED,
+                                                            2
+                                                        ),
                                                         v,
-                                                        p
+                                                        format_price(&stock.code, *p)
                                                     ),
                                                 )
                                                 .anchor(Align2::RIGHT_CENTER),
                                             );
-                                            Bar::new((p - stock.data_new()).into(), *v as f64)
+                                            Bar::new(to_f64(p - stock.data_new()), *v as f64)
                                                 .width(0.001)
                                         })
                                         .collect();
@@ -436,17 +638,25 @@ impl StockTrackerView {
                                         .map(|(v, p)| {
                                             asks_text.push(
                                                 Text::new(
-                                                    PlotPoint::new(-10.0, p - stock.data_new()),
+                                                    PlotPoint::new(
+                                                        -10.0,
+                                                        to_f64(p - stock.data_new()),
+                                                    ),
                                                     format!(
-                                                        "{:.2}    {}  -  {}  ",
-                                                        (*v as f32) * (*p) * 0.01,
+                                                        "{}    {}  -  {}  ",
+                                                        // lots of 100 shares, in 万
+                                                        format_dp(
+                                                            Price::from(*v) * p
+                                                                / Price::ONE_HUNDRED,
+                                                            2
+                                                        ),
                                                         v,
-                                                        p
+                                                        format_price(&stock.code, *p)
                                                     ),
                                                 )
                                                 .anchor(Align2::RIGHT_CENTER),
                                             );
-                                            Bar::new((p - stock.data_new()).into(), *v as f64)
+                                            Bar::new(to_f64(p - stock.data_new()), *v as f64)
                                                 .width(0.001)
                                         })
                                         .collect();
@@ -490,9 +700,9 @@ impl StockTrackerView {
             });
     }
 
-    fn _render_top_panel(&mut self, ctx: &Context, ui: &mut egui::Ui) {
+    fn _render_top_panel(&mut self, ctx: &Context, ui: &mut egui::Ui, market: Market) {
         // define a TopBottomPanel widget
-        TopBottomPanel::top("top_panel")
+        TopBottomPanel::top(egui::Id::new(("top_panel", market)))
             // .frame(egui::Frame::none())
             .show_inside(ui, |ui| {
                 ui.add_space(2.0);
@@ -514,6 +724,30 @@ impl StockTrackerView {
                         ui.add(Label::new(
                             RichText::new(self.time.clone()).text_style(egui::TextStyle::Body),
                         ));
+                        ui.label(
+                            RichText::new(market.currency())
+                                .small()
+                                .color(Color32::LIGHT_GRAY),
+                        );
+                        if market == Market::UsShare {
+                            let session = self.calendar.session(market, chrono::Utc::now());
+                            let color = match session {
+                                Session::Regular => Color32::LIGHT_GREEN,
+                                Session::Closed => Color32::GRAY,
+                                _ => Color32::KHAKI,
+                            };
+                            ui.label(RichText::new(session.label()).small().color(color));
+                        }
+                        if self.recorder.is_some() {
+                            ui.label(RichText::new("⏺ REC").small().color(Color32::RED));
+                        }
+                        if self.live_rx.is_some() {
+                            let text = match self.replay_done {
+                                true => "⏹ 回放结束".to_string(),
+                                false => format!("▶ 回放 {}", self.replay_speed.label()),
+                            };
+                            ui.label(RichText::new(text).small().color(Color32::LIGHT_BLUE));
+                        }
                     });
 
                     ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
@@ -549,6 +783,74 @@ impl StockTrackerView {
             });
     }
 
+    fn render_status_bar(&mut self, ui: &mut egui::Ui, market: Market) {
+        TopBottomPanel::bottom(egui::Id::new(("status_bar", market))).show_inside(ui, |ui| {
+            ui.horizontal_wrapped(|ui| {
+                let session = self.calendar.session(market, chrono::Utc::now());
+                let color = match session {
+                    Session::Regular => Color32::GREEN,
+                    s if s.is_live() => Color32::YELLOW,
+                    _ => Color32::GRAY,
+                };
+                ui.label(RichText::new(session.label()).small().color(color));
+
+                let now = chrono::Local::now();
+                if self.calendar.uncovered(now.year()).contains(&market) {
+                    ui.label(RichText::new("⚠ 无假期表").small().color(Color32::YELLOW))
+                        .on_hover_text(format!(
+                            "{} 年的假期未收录，只按周末休市\n可放置 {HOLIDAYS_FILE} 到数据目录",
+                            now.year()
+                        ));
+                }
+                for endpoint in [Endpoint::Quote, Endpoint::Kline] {
+                    let (color, text) = match self.status.get(&endpoint) {
+                        None => (Color32::GRAY, format!("● {}", endpoint.label())),
+                        Some(status) => {
+                            let stale = status.last_success.is_none_or(|t| {
+                                (now - t).to_std().unwrap_or_default()
+                                    > endpoint.stale_after(session.is_live())
+                            });
+                            let (color, state) = match (status.ok, stale) {
+                                (false, _) => (Color32::RED, "down"),
+                                (true, true) => (Color32::YELLOW, "stale"),
+                                (true, false) => (Color32::GREEN, "ok"),
+                            };
+                            let last = status
+                                .last_success
+                                .map(|t| t.format("%H:%M:%S").to_string())
+                                .unwrap_or_else(|| "-".to_string());
+                            (
+                                color,
+                                format!(
+                                    "● {} {} {}ms {}",
+                                    endpoint.label(),
+                                    state,
+                                    status.latency.as_millis(),
+                                    last
+                                ),
+                            )
+                        }
+                    };
+                    ui.label(RichText::new(text).small().color(color));
+                }
+
+                if let Some((error, time)) = &self.last_error {
+                    if (now - *time).num_seconds() < ERROR_DISPLAY_SECS {
+                        let text = match &error.code {
+                            Some(code) => format!("{}: {}", code, error.message),
+                            None => format!("{}: {}", error.endpoint.label(), error.message),
+                        };
+                        ui.add(
+                            Label::new(RichText::new(&text).small().color(Color32::LIGHT_RED))
+                                .truncate(),
+                        )
+                        .on_hover_text(text);
+                    }
+                }
+            });
+        });
+    }
+
     fn render_setting(&mut self, ctx: &Context, ui: &mut egui::Ui) {
         let is_open = self.setting.open;
         egui::SidePanel::right("setting_panel")
@@ -601,13 +903,23 @@ impl StockTrackerView {
             CollapsingHeader::new("stocks")
                 .default_open(false)
                 .show(ui, |ui| {
-                    for (_code, s) in self.data.clone() {
-                        ui.with_layout(Layout::left_to_right(Align::Center), |ui| {
-                            ui.label(
-                                RichText::new(format!("{}({})", s.name, s.code))
-                                    .color(Color32::LIGHT_BLUE),
-                            );
-                            ui.with_layout(Layout::left_to_right(Align::Center), |ui| {
+                    let mut removed = None;
+                    let data = &self.data;
+                    let response = dnd(ui, "watchlist").show_vec(
+                        &mut self.setting.stocks,
+                        |ui, code, handle, _state| {
+                            ui.horizontal(|ui| {
+                                handle.ui(ui, |ui| {
+                                    let name = data.get(code).map_or("", |s| s.name.as_str());
+                                    ui.label(
+                                        RichText::new(format!("{name}({code})"))
+                                            .color(Color32::LIGHT_BLUE),
+                                    );
+                                });
+                                let market = Market::from_code(code);
+                                if market != Market::ZhShare {
+                                    ui.label(RichText::new(market.currency()).small());
+                                }
                                 ui.add_space(3.0);
                                 let close_btn = ui.add(Button::new(
                                     RichText::new("❌")
@@ -615,42 +927,341 @@ impl StockTrackerView {
                                         .color(Color32::RED),
                                 ));
                                 if close_btn.clicked() {
-                                    self.data.remove(&s.code);
+                                    removed = Some(code.clone());
                                 }
                             });
-                        });
+                        },
+                    );
+
+                    if let Some(update) = response.final_update() {
+                        // `to` counts the dragged item still in place
+                        let index = if update.to > update.from {
+                            update.to - 1
+                        } else {
+                            update.to
+                        };
+                        if let (Some(tx), Some(code)) = (&self.tx, self.setting.stocks.get(index)) {
+                            let _ = tx.send(StockCammnd::StockMove(code.clone(), index));
+                        }
+                    }
+                    if let Some(code) = removed {
+                        self.data.remove(&code);
+                        self.setting.unwatch(&code);
+                        if let Some(tx) = &self.tx {
+                            let _ = tx.send(StockCammnd::StockDel(code));
+                        }
                     }
                 });
         });
         ui.add(Separator::default().spacing(0.0));
+        self.render_add_box(ctx, ui, Market::ZhShare);
+        ui.add(Separator::default().spacing(0.0));
+        self.render_kline_cache(ui);
+        self.render_recorder(ui);
+    }
+
+    /// Code, name or pinyin to watch, a pick is validated by the backend.
+    fn render_add_box(&mut self, ctx: &Context, ui: &mut egui::Ui, market: Market) {
         ui.horizontal(|ui| {
             // add stock code
             ui.label(RichText::new("➕").color(Color32::LIGHT_GRAY));
 
-            let Self {
-                setting:
-                    Setting {
-                        open,
-                        adding_code: code,
-                        ..
-                    },
-                ..
-            } = self;
-
             let response = ui.add_sized(
                 ui.available_size() - Vec2 { x: 2.0, y: 0.0 },
-                egui::TextEdit::singleline(code).text_color(Color32::LIGHT_GREEN),
+                egui::TextEdit::singleline(self.setting.adding_code.entry(market).or_default())
+                    .hint_text("代码/名称/拼音")
+                    .text_color(Color32::LIGHT_GREEN),
             );
 
-            if response.lost_focus() || ctx.input(|i| i.key_pressed(egui::Key::Enter)) {
+            let keyword = self
+                .setting
+                .adding_code
+                .get(&market)
+                .map(|x| x.trim().to_string())
+                .unwrap_or_default();
+            let popup_id = ui.make_persistent_id("symbol_search");
+            if response.changed() {
+                if keyword.is_empty() {
+                    ui.memory_mut(|m| m.close_popup());
+                    self.search_due = None;
+                } else {
+                    ui.memory_mut(|m| m.open_popup(popup_id));
+                    self.search_due = Some((keyword.clone(), Instant::now() + SEARCH_DELAY));
+                }
+            }
+            self.send_search(ctx);
+
+            let candidates = self.symbols.search(&keyword, MAX_CANDIDATES);
+            let mut picked = None;
+            popup_below_widget(
+                ui,
+                popup_id,
+                &response,
+                PopupCloseBehavior::CloseOnClickOutside,
+                |ui| {
+                    ui.set_min_width(180.0);
+                    if candidates.is_empty() {
+                        ui.weak("无匹配");
+                    }
+                    for symbol in &candidates {
+                        ui.horizontal(|ui| {
+                            market_badge(ui, symbol.market);
+                            let text = format!("{} {}", symbol.name, symbol.code);
+                            if ui.selectable_label(false, text).clicked() {
+                                picked = Some(symbol.code.clone());
+                            }
+                        });
+                    }
+                },
+            );
+
+            if response.lost_focus() && ctx.input(|i| i.key_pressed(egui::Key::Enter)) {
+                // a typed code as is, anything else picks the best candidate
+                picked = match candidates.first() {
+                    Some(symbol) if !valid_code(&keyword) => Some(symbol.code.clone()),
+                    _ => Some(keyword).filter(|x| !x.is_empty()),
+                };
+            }
+            if let Some(code) = picked {
+                self.setting.adding_code.remove(&market);
+                ui.memory_mut(|m| m.close_popup());
                 if let Some(tx) = &self.tx {
-                    tx.send(StockCammnd::StockAdd(code.to_string()))
-                        .expect("Failed sending  add stock event .");
-                    *code = String::new();
-                    *open = false;
+                    self.adding.insert(code.clone());
+                    if tx.send(StockCammnd::StockAdd(code.clone())).is_err() {
+                        self.adding.remove(&code);
+                        self.add_error = Some(format!("{code}: backend stopped"));
+                    }
                 }
             }
         });
+        if let Some(error) = &self.add_error {
+            ui.label(RichText::new(error).small().color(Color32::LIGHT_RED));
+        }
+    }
+
+    // each keyword asks the source once, the master keeps the answer
+    fn send_search(&mut self, ctx: &Context) {
+        let Some((keyword, due)) = self.search_due.take() else {
+            return;
+        };
+        let now = Instant::now();
+        if now < due {
+            ctx.request_repaint_after(due - now);
+            self.search_due = Some((keyword, due));
+            return;
+        }
+        if self.searched.len() >= MAX_SEARCHED {
+            self.searched.clear();
+        }
+        if self.searched.insert(keyword.clone()) {
+            if let Some(tx) = &self.tx {
+                let _ = tx.send(StockCammnd::Search(keyword));
+            }
+        }
+    }
+
+    fn render_recorder(&mut self, ui: &mut egui::Ui) {
+        let Some(dir) = self.data_dir.as_ref().map(|x| x.join(RECORDINGS_DIR)) else {
+            return;
+        };
+        ui.horizontal(|ui| {
+            ui.label(RichText::new("🎞").color(Color32::LIGHT_BLUE));
+            CollapsingHeader::new("录制/回放")
+                .default_open(false)
+                .show(ui, |ui| {
+                    let replaying = self.live_rx.is_some();
+                    ui.add_enabled_ui(!replaying, |ui| match self.recorder.take() {
+                        Some(recorder) => {
+                            let name = recorder.path().file_name().unwrap_or_default();
+                            let text = format!("⏹ 停止 {}", name.to_string_lossy());
+                            if ui.button(text).clicked() {
+                                if let Err(e) = recorder.finish() {
+                                    tracing::warn!("recording not finished: {e}");
+                                }
+                            } else {
+                                self.recorder = Some(recorder);
+                            }
+                        }
+                        None => {
+                            if ui.button("⏺ 录制").clicked() {
+                                match Recorder::create(&dir) {
+                                    Ok(recorder) => self.recorder = Some(recorder),
+                                    Err(e) => tracing::warn!("recording not started: {e}"),
+                                }
+                            }
+                        }
+                    });
+
+                    ui.add_enabled_ui(self.recorder.is_none(), |ui| {
+                        let files = recorder::recordings(&dir);
+                        let name = |x: &PathBuf| {
+                            x.file_name()
+                                .unwrap_or_default()
+                                .to_string_lossy()
+                                .to_string()
+                        };
+                        let selected = self.replay_file.as_ref().map(name).unwrap_or_default();
+                        egui::ComboBox::from_id_salt("recordings")
+                            .selected_text(selected)
+                            .show_ui(ui, |ui| {
+                                for file in files {
+                                    let text = name(&file);
+                                    ui.selectable_value(&mut self.replay_file, Some(file), text);
+                                }
+                            });
+                        ui.horizontal(|ui| {
+                            for speed in ReplaySpeed::ALL {
+                                ui.selectable_value(&mut self.replay_speed, speed, speed.label());
+                            }
+                        });
+                        ui.horizontal(|ui| {
+                            if let Some(file) = self.replay_file.clone() {
+                                if ui.button("▶ 回放").clicked() {
+                                    self.start_replay(file);
+                                }
+                            }
+                            if replaying && ui.button("⏹ 实时").clicked() {
+                                self.stop_replay();
+                            }
+                        });
+                    });
+                });
+        });
+        ui.add(Separator::default().spacing(0.0));
+    }
+
+    /// Feeds a recording through the channel the backend uses.
+    fn start_replay(&mut self, file: PathBuf) {
+        let (tx, rx) = crossbeam::channel::unbounded();
+        let speed = self.replay_speed;
+        thread::spawn(move || {
+            if let Err(e) = recorder::replay(&file, &tx, speed) {
+                let error = FetchError {
+                    endpoint: Endpoint::Replay,
+                    code: None,
+                    message: e.to_string(),
+                };
+                tx.send(TxStockData::Error(error)).ok();
+            }
+        });
+        if self.live_rx.is_none() {
+            self.live_rx = self.rx.take();
+        }
+        self.rx = Some(rx);
+        self.replay_done = false;
+        self.data.clear();
+    }
+
+    fn stop_replay(&mut self) {
+        let Some(live) = self.live_rx.take() else {
+            return;
+        };
+        self.rx = Some(live);
+        self.replay_done = false;
+        // replayed quotes and klines give way to live ones, the quotes come
+        // first so the klines have somewhere to go
+        self.data.clear();
+        self.send(StockCammnd::Refresh);
+        for code in &self.setting.stocks {
+            let scale = self
+                .setting
+                .scales
+                .get(code)
+                .copied()
+                .filter(|x| x.is_available(Market::from_code(code)))
+                .unwrap_or_else(|| KLineScale::default_for(Market::from_code(code)));
+            self.send(StockCammnd::StockKLine(code.clone(), scale));
+        }
+    }
+
+    fn render_kline_cache(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label(RichText::new("💾").color(Color32::LIGHT_BLUE));
+            let response = CollapsingHeader::new("K线缓存")
+                .default_open(false)
+                .show(ui, |ui| {
+                    let mut clear = None;
+                    match &self.cache {
+                        None => {
+                            ui.spinner();
+                        }
+                        Some(entries) if entries.is_empty() => {
+                            ui.weak("无缓存");
+                        }
+                        Some(entries) => {
+                            Grid::new("kline_cache").striped(true).show(ui, |ui| {
+                                for entry in entries {
+                                    let range = match (entry.first, entry.last) {
+                                        (Some(first), Some(last)) => format!(
+                                            "{} ~ {}",
+                                            first.format("%Y-%m-%d"),
+                                            last.format("%Y-%m-%d")
+                                        ),
+                                        _ => "-".to_string(),
+                                    };
+                                    ui.label(RichText::new(&entry.code).small());
+                                    ui.label(RichText::new(entry.scale.label()).small());
+                                    ui.label(RichText::new(entry.candles.to_string()).small())
+                                        .on_hover_text(format!(
+                                            "{range}\n{:.1} KB",
+                                            entry.bytes as f64 / 1024.0
+                                        ));
+                                    if ui.small_button("🗑").clicked() {
+                                        clear = Some(Some(entry.code.clone()));
+                                    }
+                                    ui.end_row();
+                                }
+                            });
+                        }
+                    }
+                    ui.horizontal(|ui| {
+                        if ui.small_button("🔄").clicked() {
+                            self.send(StockCammnd::CacheInspect);
+                        }
+                        if ui.small_button("清空").clicked() {
+                            clear = Some(None);
+                        }
+                    });
+                    if let Some(code) = clear {
+                        self.send(StockCammnd::CacheClear(code));
+                    }
+                });
+            // listed when opened, refreshed on demand
+            if response.header_response.clicked() {
+                self.cache = None;
+                self.send(StockCammnd::CacheInspect);
+            }
+        });
         ui.add(Separator::default().spacing(0.0));
     }
+
+    fn send(&self, command: StockCammnd) {
+        if let Some(tx) = &self.tx {
+            let _ = tx.send(command);
+        }
+    }
+}
+
+fn price_hover_text(stock: &Stock, session: Session) -> String {
+    let mut text = format!(
+        "{} {}\n成交额 {}",
+        format_price(&stock.code, stock.data_new()),
+        stock.market().currency(),
+        format_amount(stock.data.amount)
+    );
+    if let Some(ext) = &stock.data.ext {
+        // outside the pre-market the last extended quote is the post-market one
+        let label = match session {
+            Session::PreMarket => Session::PreMarket.label(),
+            _ => Session::PostMarket.label(),
+        };
+        text.push_str(&format!(
+            "\n{} {} ({}%) {}",
+            label,
+            format_price(&stock.code, ext.price),
+            ext.rise_per,
+            ext.time
+        ));
+    }
+    text
 }
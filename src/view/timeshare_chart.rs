@@ -0,0 +1,180 @@
+use egui::{Color32, RichText, Stroke};
+use egui_plot::{uniform_grid_spacer, Bar, BarChart, HLine, Line, LineStyle, Plot, PlotPoints};
+
+use crate::model::{
+    bars::{clock_minute, session_length},
+    timeshare::{MinutePoint, TimeShare},
+    Market,
+};
+
+use super::widgets::rise_color;
+
+const Y_AXIS_WIDTH: f32 = 56.0;
+
+/// 分时: price and average over the whole session, previous close in the
+/// middle, volume below.
+pub(crate) fn show_timeshare(
+    ui: &mut egui::Ui,
+    id: &str,
+    market: Market,
+    minutes: &TimeShare,
+    prev_close: f64,
+) {
+    let points = &minutes.points;
+    let hovered_id = ui.id().with((id, "timeshare_hovered"));
+    let hovered = ui.data(|d| d.get_temp::<u32>(hovered_id));
+    let point = hovered
+        .and_then(|m| points.iter().rev().find(|x| x.minute <= m))
+        .or(points.last());
+    render_point(ui, market, point, prev_close);
+
+    let total = session_length(market);
+    let group = ui.id().with((id, "timeshare_group"));
+    let height = ui.available_height();
+    let plot = |name: &str| {
+        Plot::new(format!("{id}_{name}"))
+            .show_background(false)
+            .allow_drag(false)
+            .allow_zoom(false)
+            .allow_scroll(false)
+            .allow_boxed_zoom(false)
+            .include_x(0.0)
+            .include_x(total as f64)
+            .link_cursor(group, [true, false].into())
+            .x_grid_spacer(uniform_grid_spacer(|_| [30.0, 60.0, 120.0]))
+            .y_axis_min_width(Y_AXIS_WIDTH)
+    };
+
+    // the previous close stays in the middle
+    let spread = points
+        .iter()
+        .map(|x| (x.price - prev_close).abs())
+        .fold(prev_close * 0.01, f64::max);
+    let line = |value: fn(&MinutePoint) -> f64, color: Color32| {
+        let points: PlotPoints = points.iter().map(|x| [x.minute as f64, value(x)]).collect();
+        Line::new(points).color(color).width(1.0)
+    };
+    let price = line(|x| x.price, Color32::WHITE).name("价格");
+    let avg = line(|x| x.avg, Color32::YELLOW).name("均价");
+    let response = plot("price")
+        .height(height * 0.7)
+        .show_axes([false, true])
+        .include_y(prev_close - spread)
+        .include_y(prev_close + spread)
+        .show(ui, |plot_ui| {
+            plot_ui.hline(
+                HLine::new(prev_close)
+                    .color(Color32::DARK_GRAY)
+                    .style(LineStyle::dashed_loose()),
+            );
+            plot_ui.line(price);
+            plot_ui.line(avg);
+            plot_ui.pointer_coordinate().map(|p| p.x)
+        });
+    let mut pointer = response.inner;
+
+    let mut last = prev_close;
+    let bars = points
+        .iter()
+        .map(|x| {
+            let color = rise_color(x.price - last);
+            last = x.price;
+            Bar::new(x.minute as f64, x.volume)
+                .width(0.6)
+                .stroke(Stroke::new(0.5, color))
+                .fill(color)
+        })
+        .collect();
+    let response = plot("volume")
+        .height(ui.available_height())
+        .include_y(0.0)
+        .x_axis_formatter(move |mark, _| minute_label(market, mark.value))
+        .show(ui, |plot_ui| {
+            plot_ui
+                .bar_chart(BarChart::new(bars).element_formatter(Box::new(|_, _| String::new())));
+            plot_ui.pointer_coordinate().map(|p| p.x)
+        });
+    pointer = pointer.or(response.inner);
+
+    let hovered = pointer
+        .map(|x| x.round())
+        .filter(|x| *x >= 0.0 && *x <= total as f64)
+        .map(|x| x as u32);
+    ui.data_mut(|d| match hovered {
+        Some(minute) => d.insert_temp(hovered_id, minute),
+        None => d.remove::<u32>(hovered_id),
+    });
+}
+
+fn render_point(ui: &mut egui::Ui, market: Market, point: Option<&MinutePoint>, prev_close: f64) {
+    let Some(x) = point else {
+        ui.horizontal_wrapped(|ui| {
+            ui.weak("暂无分时");
+            // only a-share minute lines are served, the rest is drawn from
+            // the quotes seen since opening
+            if market != Market::ZhShare {
+                ui.weak(RichText::new("港美股无分时数据，从开盘后的行情画起").small());
+            }
+        });
+        return;
+    };
+    ui.horizontal_wrapped(|ui| {
+        let color = rise_color(x.price - prev_close);
+        let clock = clock_minute(market, x.minute);
+        ui.label(RichText::new(format!("{:02}:{:02}", clock / 60, clock % 60)).small());
+        ui.label(
+            RichText::new(format!("价 {:.3}", x.price))
+                .small()
+                .color(color),
+        );
+        if prev_close > 0.0 {
+            let percent = (x.price / prev_close - 1.0) * 100.0;
+            ui.label(
+                RichText::new(format!("{percent:+.2}%"))
+                    .small()
+                    .color(color),
+            );
+        }
+        ui.label(
+            RichText::new(format!("均 {:.3}", x.avg))
+                .small()
+                .color(Color32::YELLOW),
+        );
+        ui.label(RichText::new(format!("量 {:.0}", x.volume)).small());
+    });
+}
+
+/// Clock time of a trading minute on the half hours, a break shows both ends.
+fn minute_label(market: Market, x: f64) -> String {
+    let total = session_length(market);
+    if x.fract() != 0.0 || x < 0.0 || x > total as f64 || !(x as u32).is_multiple_of(30) {
+        return String::new();
+    }
+    let minute = x as u32;
+    let hm = |m: u32| format!("{:02}:{:02}", m / 60, m % 60);
+    let end = clock_minute(market, minute);
+    match clock_minute(market, minute + 1) - 1 {
+        start if minute < total && start != end => format!("{}/{}", hm(end), hm(start)),
+        _ => hm(end),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_minute_label() {
+        let zh = |x| minute_label(Market::ZhShare, x);
+        assert_eq!(zh(0.0), "09:30");
+        assert_eq!(zh(60.0), "10:30");
+        assert_eq!(zh(120.0), "11:30/13:00");
+        assert_eq!(zh(240.0), "15:00");
+        assert_eq!(zh(45.0), "");
+        assert_eq!(zh(30.5), "");
+        assert_eq!(zh(270.0), "");
+
+        assert_eq!(minute_label(Market::HkShare, 150.0), "12:00/13:00");
+        assert_eq!(minute_label(Market::UsShare, 390.0), "16:00");
+    }
+}
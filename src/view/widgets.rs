@@ -0,0 +1,72 @@
+use egui::{Color32, Response, Stroke};
+use egui_plot::{BoxElem, BoxPlot, BoxSpread, Plot};
+
+use crate::model::{stock::Klines, Market};
+
+/// Red up, green down.
+pub(crate) fn rise_color<T: PartialOrd + Default>(rise: T) -> Color32 {
+    match rise {
+        p if p < T::default() => Color32::GREEN,
+        n if n > T::default() => Color32::RED,
+        _ => Color32::WHITE,
+    }
+}
+
+/// Small colored tag naming the market.
+pub(crate) fn market_badge(ui: &mut egui::Ui, market: Market) -> Response {
+    let (text, color) = match market {
+        Market::ZhShare => ("A", Color32::LIGHT_RED),
+        Market::HkShare => ("HK", Color32::LIGHT_BLUE),
+        Market::UsShare => ("US", Color32::LIGHT_GREEN),
+    };
+    ui.label(
+        egui::RichText::new(text)
+            .small()
+            .color(Color32::BLACK)
+            .background_color(color),
+    )
+}
+
+/// Tiny candlestick chart shown in the list rows.
+pub(crate) fn kline_sparkline(
+    ui: &mut egui::Ui,
+    id: impl std::hash::Hash,
+    kline: &Klines,
+) -> Response {
+    let boxs: Vec<BoxElem> = kline
+        .klines
+        .iter()
+        .enumerate()
+        .map(|(i, x)| {
+            let fill_color = if x.close < x.open {
+                Color32::GREEN
+            } else {
+                Color32::RED
+            };
+
+            BoxElem::new(
+                i as f64,
+                BoxSpread::new(x.low, x.open, (x.open + x.close) / 2.0, x.close, x.high),
+            )
+            .stroke(Stroke::new(0.2, fill_color))
+            .fill(fill_color.linear_multiply(0.1))
+            .box_width(0.8)
+        })
+        .collect();
+
+    let box1 = BoxPlot::new(boxs);
+
+    Plot::new(id)
+        .allow_zoom(false)
+        .allow_drag(false)
+        .allow_scroll(false)
+        .show_grid([false, false])
+        .show_axes([false, false])
+        .sharp_grid_lines(false)
+        .width(50.0)
+        .height(16.0)
+        .show(ui, |plot_ui| {
+            plot_ui.box_plot(box1);
+        })
+        .response
+}
@@ -0,0 +1,754 @@
+mod common;
+
+use std::{
+    sync::Arc,
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Utc};
+use common::{temp_dir, MockSina};
+use crossbeam::channel::{unbounded, Receiver, Sender};
+use wealth_tracker::{
+    backend::{Endpoint, FetchError, FetchStatus, StockCammnd, StockTask, TxStockData, WatchAck},
+    model::{kline_store::KlineStore, stock::KLineScale, Market, Price, Stock, Symbol},
+};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+fn spawn_task(
+    mock: &MockSina,
+    codes: &str,
+) -> (Sender<StockCammnd>, Receiver<TxStockData>, JoinHandle<()>) {
+    let (tx, rx) = unbounded();
+    let (tx2, rx2) = unbounded();
+    let mut task = StockTask::with_provider(rx, tx2, codes.to_string(), Arc::new(mock.provider()))
+        .with_clock(trading);
+    let handle = thread::spawn(move || task.run());
+    (tx, rx2, handle)
+}
+
+// monday 2025-01-27 10:00 in beijing
+fn trading() -> DateTime<Utc> {
+    "2025-01-27T02:00:00Z".parse().unwrap()
+}
+
+fn price(x: &str) -> Price {
+    x.parse().unwrap()
+}
+
+/// Next `StockList` prices of `code`, other messages are skipped.
+fn next_price(rx: &Receiver<TxStockData>, code: &str) -> Price {
+    let deadline = Instant::now() + TIMEOUT;
+    loop {
+        let msg = rx.recv_deadline(deadline).expect("no stock list received");
+        if let TxStockData::StockList(stocks) = msg {
+            if let Some(s) = stocks.iter().find(|s| s.code == code) {
+                return s.data_new();
+            }
+        }
+    }
+}
+
+/// Messages received until `done` holds for them.
+fn collect_until(
+    rx: &Receiver<TxStockData>,
+    done: impl Fn(&[TxStockData]) -> bool,
+) -> Vec<TxStockData> {
+    let deadline = Instant::now() + TIMEOUT;
+    let mut msgs = vec![];
+    while !done(&msgs) {
+        msgs.push(
+            rx.recv_deadline(deadline)
+                .expect("condition not met in time"),
+        );
+    }
+    msgs
+}
+
+fn stock_lists(msgs: &[TxStockData]) -> Vec<&Vec<Stock>> {
+    msgs.iter()
+        .filter_map(|x| match x {
+            TxStockData::StockList(stocks) => Some(stocks),
+            _ => None,
+        })
+        .collect()
+}
+
+fn errors(msgs: &[TxStockData]) -> Vec<&FetchError> {
+    msgs.iter()
+        .filter_map(|x| match x {
+            TxStockData::Error(e) => Some(e),
+            _ => None,
+        })
+        .collect()
+}
+
+fn acks(msgs: &[TxStockData]) -> Vec<&WatchAck> {
+    msgs.iter()
+        .filter_map(|x| match x {
+            TxStockData::Watch(ack) => Some(ack),
+            _ => None,
+        })
+        .collect()
+}
+
+fn statuses(msgs: &[TxStockData], endpoint: Endpoint) -> Vec<&FetchStatus> {
+    msgs.iter()
+        .filter_map(|x| match x {
+            TxStockData::Status(s) if s.endpoint == endpoint => Some(s),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn test_refresh_follows_price_path() {
+    let mock = MockSina::start();
+    mock.stock("sh601127", "赛力斯", 10.0, &[10.0, 10.5, 11.0]);
+    let (tx, rx, handle) = spawn_task(&mock, "sh601127");
+
+    assert_eq!(next_price(&rx, "sh601127"), price("10.0"));
+    assert_eq!(next_price(&rx, "sh601127"), price("10.5"));
+    assert_eq!(next_price(&rx, "sh601127"), price("11.0"));
+    assert_eq!(next_price(&rx, "sh601127"), price("11.0"));
+
+    drop(tx);
+    handle.join().unwrap();
+}
+
+#[test]
+fn test_initial_klines_and_scale_command() {
+    let mock = MockSina::start();
+    mock.stock("sh601127", "赛力斯", 10.0, &[10.0]);
+    let (tx, rx, handle) = spawn_task(&mock, "sh601127");
+
+    let mut scales = vec![];
+    let deadline = Instant::now() + TIMEOUT;
+    tx.send(StockCammnd::StockKLine(
+        "sh601127".into(),
+        KLineScale::Munute5,
+    ))
+    .unwrap();
+    while scales.len() < 2 {
+        if let TxStockData::Kline((code, klines)) = rx.recv_deadline(deadline).unwrap() {
+            assert_eq!(code, "sh601127");
+            assert_eq!(klines.klines.len(), 100);
+            scales.push(klines.scale);
+        }
+    }
+    assert_eq!(scales, vec![KLineScale::Munute15, KLineScale::Munute5]);
+    assert!(mock
+        .requests()
+        .iter()
+        .any(|x| x.contains("symbol=sh601127&scale=5&")));
+
+    drop(tx);
+    handle.join().unwrap();
+}
+
+#[test]
+fn test_older_klines_are_paged() {
+    let mock = MockSina::start();
+    mock.stock("sh601127", "赛力斯", 10.0, &[10.0]);
+    let (tx, rx, handle) = spawn_task(&mock, "sh601127");
+
+    tx.send(StockCammnd::KLineHistory(
+        "sh601127".into(),
+        KLineScale::Day,
+        100,
+    ))
+    .unwrap();
+    let msgs = collect_until(&rx, |msgs| {
+        msgs.iter()
+            .any(|x| matches!(x, TxStockData::KlineHistory(_)))
+    });
+    let Some(TxStockData::KlineHistory((code, klines))) = msgs.last() else {
+        unreachable!()
+    };
+    assert_eq!(code, "sh601127");
+    assert_eq!(klines.scale, KLineScale::Day);
+    assert_eq!(klines.klines.len(), 200);
+    assert!(mock
+        .requests()
+        .iter()
+        .any(|x| x.contains("symbol=sh601127&scale=240&") && x.contains("datalen=200")));
+
+    // a page at least doubles what is held
+    tx.send(StockCammnd::KLineHistory(
+        "sh601127".into(),
+        KLineScale::Day,
+        300,
+    ))
+    .unwrap();
+    collect_until(&rx, |msgs| {
+        msgs.iter()
+            .any(|x| matches!(x, TxStockData::KlineHistory((_, k)) if k.klines.len() == 600))
+    });
+    assert!(mock.requests().iter().any(|x| x.contains("datalen=600")));
+
+    // never more than the memory bound
+    tx.send(StockCammnd::KLineHistory(
+        "sh601127".into(),
+        KLineScale::Day,
+        990,
+    ))
+    .unwrap();
+    collect_until(&rx, |msgs| {
+        msgs.iter()
+            .any(|x| matches!(x, TxStockData::KlineHistory((_, k)) if k.klines.len() == 1000))
+    });
+
+    drop(tx);
+    handle.join().unwrap();
+}
+
+#[test]
+fn test_klines_are_cached_on_disk() {
+    let dir = temp_dir("backend-klines");
+    let spawn_cached = |mock: &MockSina| {
+        let (tx, rx) = unbounded();
+        let (tx2, rx2) = unbounded();
+        let mut task =
+            StockTask::with_provider(rx, tx2, "sh601127".into(), Arc::new(mock.provider()))
+                .with_clock(trading)
+                .with_store(KlineStore::open(&dir));
+        (tx, rx2, thread::spawn(move || task.run()))
+    };
+    let klines = |rx: &Receiver<TxStockData>| {
+        let msgs = collect_until(rx, |msgs| {
+            msgs.iter().any(|x| matches!(x, TxStockData::Kline(_)))
+        });
+        match msgs.last() {
+            Some(TxStockData::Kline((_, klines))) => klines.clone(),
+            _ => unreachable!(),
+        }
+    };
+
+    // a cold cache asks for a full page
+    let mock = MockSina::start();
+    mock.stock("sh601127", "赛力斯", 10.0, &[10.0]);
+    let (tx, rx, handle) = spawn_cached(&mock);
+    assert_eq!(klines(&rx).klines.len(), 100);
+    assert!(mock.requests().iter().any(|x| x.contains("datalen=100")));
+    drop(tx);
+    handle.join().unwrap();
+
+    // a warm one only the tail, the history comes from disk
+    let mock = MockSina::start();
+    mock.stock("sh601127", "赛力斯", 10.0, &[10.0]);
+    let (tx, rx, handle) = spawn_cached(&mock);
+    assert_eq!(klines(&rx).klines.len(), 100);
+    let requests = mock.requests();
+    assert!(requests.iter().any(|x| x.contains("datalen=10")));
+    assert!(!requests.iter().any(|x| x.contains("datalen=100")));
+
+    tx.send(StockCammnd::CacheInspect).unwrap();
+    let msgs = collect_until(&rx, |msgs| {
+        msgs.iter().any(|x| matches!(x, TxStockData::Cache(_)))
+    });
+    let Some(TxStockData::Cache(entries)) = msgs.last() else {
+        unreachable!()
+    };
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].code, "sh601127");
+    assert_eq!(entries[0].scale, KLineScale::Munute15);
+    assert_eq!(entries[0].candles, 100);
+
+    tx.send(StockCammnd::CacheClear(None)).unwrap();
+    let msgs = collect_until(&rx, |msgs| {
+        msgs.iter().any(|x| matches!(x, TxStockData::Cache(_)))
+    });
+    assert!(matches!(msgs.last(), Some(TxStockData::Cache(entries)) if entries.is_empty()));
+
+    drop(tx);
+    handle.join().unwrap();
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_time_share() {
+    let mock = MockSina::start();
+    mock.stock("sh601127", "赛力斯", 10.0, &[10.5]);
+    mock.stock("hk00700", "腾讯控股", 370.0, &[372.0]);
+    let (tx, rx, handle) = spawn_task(&mock, "sh601127,hk00700");
+
+    tx.send(StockCammnd::TimeShare("sh601127".into())).unwrap();
+    tx.send(StockCammnd::TimeShare("hk00700".into())).unwrap();
+    let msgs = collect_until(&rx, |msgs| {
+        msgs.iter()
+            .filter(|x| matches!(x, TxStockData::TimeShare(_)))
+            .count()
+            == 2
+    });
+    let minutes: Vec<_> = msgs
+        .iter()
+        .filter_map(|x| match x {
+            TxStockData::TimeShare((code, minutes)) => Some((code.as_str(), minutes)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(minutes[0].0, "sh601127");
+    let points = &minutes[0].1.points;
+    assert_eq!(points.len(), 61);
+    assert_eq!(points[60].minute, 60);
+    assert_eq!(points[60].price, 10.5);
+    // only a-shares are asked, the rest is built from quotes
+    assert_eq!(minutes[1].0, "hk00700");
+    assert!(minutes[1].1.points.is_empty());
+    let asked = mock.requests();
+    assert_eq!(
+        asked
+            .iter()
+            .filter(|x| x.contains("getMinlineData"))
+            .count(),
+        1
+    );
+    assert!(statuses(&msgs, Endpoint::Minutes).iter().all(|s| s.ok));
+
+    // a failed minute line is told apart from the klines
+    mock.set_minline_error(Some(500));
+    tx.send(StockCammnd::TimeShare("sh601127".into())).unwrap();
+    let msgs = collect_until(&rx, |msgs| {
+        statuses(msgs, Endpoint::Minutes).iter().any(|s| !s.ok)
+    });
+    let error = errors(&msgs)[0];
+    assert_eq!(error.endpoint, Endpoint::Minutes);
+    assert_eq!(error.code.as_deref(), Some("sh601127"));
+    assert!(statuses(&msgs, Endpoint::Kline).is_empty());
+
+    drop(tx);
+    handle.join().unwrap();
+}
+
+#[test]
+fn test_deleted_code_is_not_fetched() {
+    let mock = MockSina::start();
+    mock.stock("sh601127", "赛力斯", 10.0, &[10.0])
+        .stock("sz000625", "长安汽车", 12.0, &[12.0]);
+    let (tx, rx, handle) = spawn_task(&mock, "sh601127,sz000625");
+
+    next_price(&rx, "sz000625");
+    tx.send(StockCammnd::StockDel("sz000625".into())).unwrap();
+
+    // every list after the ack only holds sh601127
+    collect_until(&rx, |msgs| {
+        acks(msgs).contains(&&WatchAck::Removed("sz000625".into()))
+    });
+    for _ in 0..3 {
+        next_price(&rx, "sh601127");
+    }
+    assert!(mock
+        .requests()
+        .last()
+        .is_some_and(|x| x == "/list=sh601127"));
+
+    drop(tx);
+    handle.join().unwrap();
+}
+
+#[test]
+fn test_source_errors_and_recovery() {
+    let mock = MockSina::start();
+    mock.stock("sh601127", "赛力斯", 10.0, &[10.0, 10.5]);
+    mock.set_quote_error(Some(500));
+    let (tx, rx, handle) = spawn_task(&mock, "sh601127");
+
+    let msgs = collect_until(&rx, |msgs| statuses(msgs, Endpoint::Quote).len() >= 3);
+    assert!(mock.quote_requests() >= 3);
+    assert!(stock_lists(&msgs).is_empty());
+
+    let quote_errors: Vec<_> = errors(&msgs)
+        .into_iter()
+        .filter(|e| e.endpoint == Endpoint::Quote)
+        .collect();
+    assert!(!quote_errors.is_empty());
+    assert!(quote_errors.iter().all(|e| e.code.is_none()));
+    let failed = statuses(&msgs, Endpoint::Quote);
+    assert!(!failed.is_empty());
+    assert!(failed.iter().all(|s| !s.ok && s.last_success.is_none()));
+
+    mock.set_quote_error(None);
+    assert_eq!(next_price(&rx, "sh601127"), price("10.0"));
+    let recovered = collect_until(&rx, |msgs| !statuses(msgs, Endpoint::Quote).is_empty());
+    let status = statuses(&recovered, Endpoint::Quote)[0];
+    assert!(status.ok);
+    assert!(status.last_success.is_some());
+
+    drop(tx);
+    handle.join().unwrap();
+}
+
+#[test]
+fn test_set_interval_and_latency() {
+    let mock = MockSina::start();
+    mock.stock("sh601127", "赛力斯", 10.0, &[10.0]);
+    mock.set_latency(Duration::from_millis(50));
+    let (tx, rx, handle) = spawn_task(&mock, "sh601127");
+
+    next_price(&rx, "sh601127");
+    tx.send(StockCammnd::SetInterval(1000)).unwrap();
+    thread::sleep(Duration::from_millis(100));
+    let before = mock.quote_requests();
+    thread::sleep(Duration::from_millis(1500));
+    let ticks = mock.quote_requests() - before;
+    assert!((1..=2).contains(&ticks), "{ticks} refreshes in 1.5s");
+
+    tx.send(StockCammnd::Refresh).unwrap();
+    next_price(&rx, "sh601127");
+
+    drop(tx);
+    handle.join().unwrap();
+}
+
+#[test]
+fn test_bad_rows_are_skipped() {
+    let mock = MockSina::start();
+    mock.stock("sh601127", "赛力斯", 10.0, &[10.0, 10.5]);
+    mock.set_kline_error(Some(502));
+    // sh000000 is unknown to the mock and comes back as an empty quote
+    let (tx, rx, handle) = spawn_task(&mock, "sh000000,sh601127");
+
+    let msgs = collect_until(&rx, |msgs| stock_lists(msgs).len() >= 2);
+    let errors = errors(&msgs);
+    assert!(errors
+        .iter()
+        .any(|e| e.endpoint == Endpoint::Quote && e.code.as_deref() == Some("sh000000")));
+    assert!(errors
+        .iter()
+        .any(|e| e.endpoint == Endpoint::Kline && e.code.as_deref() == Some("sh601127")));
+    assert!(statuses(&msgs, Endpoint::Kline).iter().all(|s| !s.ok));
+    assert!(statuses(&msgs, Endpoint::Quote).iter().all(|s| s.ok));
+
+    let prices: Vec<Price> = stock_lists(&msgs)
+        .iter()
+        .map(|stocks| stocks[0].data_new())
+        .collect();
+    assert_eq!(prices, [price("10.0"), price("10.5")]);
+
+    drop(tx);
+    handle.join().unwrap();
+}
+
+#[test]
+fn test_bad_kline_rows_are_skipped() {
+    let mock = MockSina::start();
+    mock.stock("sh601127", "赛力斯", 10.0, &[10.0]);
+    mock.set_bad_kline_row(true);
+    let (tx, rx, handle) = spawn_task(&mock, "sh601127");
+
+    let msgs = collect_until(&rx, |msgs| {
+        msgs.iter().any(|x| matches!(x, TxStockData::Kline(_)))
+    });
+    let klines = msgs
+        .iter()
+        .find_map(|x| match x {
+            TxStockData::Kline((_, klines)) => Some(klines),
+            _ => None,
+        })
+        .unwrap();
+    assert_eq!(klines.klines.len(), 99);
+    assert_eq!(
+        klines.klines.last().map(|x| x.date.as_str()),
+        Some("2025-01-28 01:39:00")
+    );
+    let errors = errors(&msgs);
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].endpoint, Endpoint::Kline);
+    assert_eq!(errors[0].code.as_deref(), Some("sh601127"));
+    assert!(errors[0].message.contains("`close`"));
+    assert!(statuses(&msgs, Endpoint::Kline).iter().all(|s| s.ok));
+
+    drop(tx);
+    handle.join().unwrap();
+}
+
+#[test]
+fn test_hk_klines_are_daily() {
+    let mock = MockSina::start();
+    mock.stock("hk00700", "腾讯控股", 370.0, &[372.0]);
+    let (tx, rx, handle) = spawn_task(&mock, "hk00700");
+
+    // hk charts open on days
+    let msgs = collect_until(&rx, |msgs| {
+        msgs.iter().any(|x| matches!(x, TxStockData::Kline(_)))
+    });
+    let klines = msgs
+        .iter()
+        .find_map(|x| match x {
+            TxStockData::Kline((_, klines)) => Some(klines),
+            _ => None,
+        })
+        .unwrap();
+    assert_eq!(klines.scale, KLineScale::Day);
+    assert_eq!(klines.klines.len(), 30);
+    assert!(errors(&msgs).is_empty());
+
+    // minute scales are refused before asking the source
+    let requests = mock.requests().len();
+    tx.send(StockCammnd::StockKLine(
+        "hk00700".into(),
+        KLineScale::Munute5,
+    ))
+    .unwrap();
+    let msgs = collect_until(&rx, |msgs| !errors(msgs).is_empty());
+    let error = errors(&msgs)[0];
+    assert_eq!(error.endpoint, Endpoint::Kline);
+    assert_eq!(error.code.as_deref(), Some("hk00700"));
+    assert!(!msgs.iter().any(|x| matches!(x, TxStockData::Kline(_))));
+    assert!(!mock.requests()[requests..]
+        .iter()
+        .any(|x| x.contains("getHKDayKLine")));
+
+    drop(tx);
+    handle.join().unwrap();
+}
+
+#[test]
+fn test_hk_klines_are_cached_per_scale() {
+    let dir = temp_dir("backend-hk");
+    let mock = MockSina::start();
+    mock.stock("hk00700", "腾讯控股", 370.0, &[372.0]);
+    let (tx, rx) = unbounded();
+    let (tx2, rx2) = unbounded();
+    let mut task = StockTask::with_provider(rx, tx2, "hk00700".into(), Arc::new(mock.provider()))
+        .with_clock(trading)
+        .with_store(KlineStore::open(&dir));
+    let handle = thread::spawn(move || task.run());
+    let kline = |scale: KLineScale| {
+        let msgs = collect_until(&rx2, |msgs| {
+            msgs.iter()
+                .any(|x| matches!(x, TxStockData::Kline((_, k)) if k.scale == scale))
+        });
+        assert!(errors(&msgs).is_empty());
+    };
+    kline(KLineScale::Day);
+
+    // weeks of the same daily source go to their own file
+    tx.send(StockCammnd::StockKLine("hk00700".into(), KLineScale::Week))
+        .unwrap();
+    kline(KLineScale::Week);
+    // a refused scale leaves no file behind
+    tx.send(StockCammnd::StockKLine(
+        "hk00700".into(),
+        KLineScale::Munute5,
+    ))
+    .unwrap();
+    collect_until(&rx2, |msgs| !errors(msgs).is_empty());
+
+    tx.send(StockCammnd::CacheInspect).unwrap();
+    let msgs = collect_until(&rx2, |msgs| {
+        msgs.iter().any(|x| matches!(x, TxStockData::Cache(_)))
+    });
+    let Some(TxStockData::Cache(entries)) = msgs.last() else {
+        unreachable!()
+    };
+    let held: Vec<(KLineScale, usize)> = entries.iter().map(|x| (x.scale, x.candles)).collect();
+    // 2024-12-29 to 2025-01-27 spans six iso weeks
+    assert_eq!(held, [(KLineScale::Day, 30), (KLineScale::Week, 6)]);
+
+    drop(tx);
+    handle.join().unwrap();
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_add_code_is_validated() {
+    let mock = MockSina::start();
+    mock.stock("sh601127", "赛力斯", 10.0, &[10.0])
+        .stock("hk00700", "腾讯控股", 370.0, &[373.6]);
+    let (tx, rx, handle) = spawn_task(&mock, "sh601127");
+    next_price(&rx, "sh601127");
+
+    for code in [
+        "600000", "sh60000x", "gb_AAPL", "sh000000", "hk00700", "sh601127",
+    ] {
+        tx.send(StockCammnd::StockAdd(code.into())).unwrap();
+    }
+    let probed = |msgs: &[TxStockData]| {
+        msgs.iter()
+            .any(|x| matches!(x, TxStockData::Stock(s) if s.code == "hk00700"))
+    };
+    let msgs = collect_until(&rx, |msgs| acks(msgs).len() >= 6 && probed(msgs));
+    let acks = acks(&msgs);
+    let mut rejected: Vec<_> = acks
+        .iter()
+        .filter_map(|x| match x {
+            WatchAck::Rejected { code, .. } => Some(code.as_str()),
+            _ => None,
+        })
+        .collect();
+    rejected.sort();
+    // sh000000 has the right shape but the source knows nothing of it
+    assert_eq!(rejected, ["600000", "gb_AAPL", "sh000000", "sh60000x"]);
+    // already watched, answered before the probes are back
+    assert!(acks.contains(&&WatchAck::Added("sh601127".into())));
+    assert!(acks.contains(&&WatchAck::Added("hk00700".into())));
+
+    // the probe went alone, later batches hold the new code only once
+    assert!(mock.requests().iter().any(|x| x == "/list=sh000000"));
+    assert_eq!(next_price(&rx, "hk00700"), price("373.6"));
+    assert!(mock
+        .requests()
+        .last()
+        .is_some_and(|x| x == "/list=sh601127,hk00700"));
+
+    drop(tx);
+    handle.join().unwrap();
+}
+
+#[test]
+fn test_slow_probe_keeps_refreshing() {
+    let mock = MockSina::start();
+    mock.stock("sh601127", "赛力斯", 10.0, &[10.0, 10.5, 11.0, 11.5])
+        .stock("hk00700", "腾讯控股", 370.0, &[373.6]);
+    mock.set_code_latency("hk00700", Duration::from_secs(2));
+    let (tx, rx, handle) = spawn_task(&mock, "sh601127");
+    next_price(&rx, "sh601127");
+
+    tx.send(StockCammnd::StockAdd("hk00700".into())).unwrap();
+    // quotes keep coming while the new code is probed
+    let msgs = collect_until(&rx, |msgs| stock_lists(msgs).len() >= 3);
+    assert!(acks(&msgs).is_empty());
+
+    let msgs = collect_until(&rx, |msgs| !acks(msgs).is_empty());
+    assert_eq!(acks(&msgs), [&WatchAck::Added("hk00700".into())]);
+
+    drop(tx);
+    handle.join().unwrap();
+}
+
+#[test]
+fn test_move_code() {
+    let mock = MockSina::start();
+    mock.stock("sh601127", "赛力斯", 10.0, &[10.0])
+        .stock("sz000625", "长安汽车", 12.0, &[12.0])
+        .stock("hk00700", "腾讯控股", 370.0, &[373.6]);
+    let (tx, rx, handle) = spawn_task(&mock, "sh601127,sz000625,hk00700");
+
+    tx.send(StockCammnd::StockMove("hk00700".into(), 0))
+        .unwrap();
+    tx.send(StockCammnd::StockMove("sh601127".into(), 9))
+        .unwrap();
+    let msgs = collect_until(&rx, |msgs| acks(msgs).len() >= 2);
+    assert_eq!(
+        acks(&msgs)[1],
+        &WatchAck::Order(vec!["hk00700".into(), "sz000625".into(), "sh601127".into()])
+    );
+
+    next_price(&rx, "hk00700");
+    assert!(mock
+        .requests()
+        .last()
+        .is_some_and(|x| x == "/list=hk00700,sz000625,sh601127"));
+
+    drop(tx);
+    handle.join().unwrap();
+}
+
+#[test]
+fn test_search_symbols() {
+    let mock = MockSina::start();
+    mock.stock("sh601127", "赛力斯", 10.0, &[10.0])
+        .stock("hk00700", "腾讯控股", 370.0, &[373.6]);
+    let (tx, rx, handle) = spawn_task(&mock, "sh601127");
+
+    tx.send(StockCammnd::Search("700".into())).unwrap();
+    let deadline = Instant::now() + TIMEOUT;
+    let (keyword, symbols) = loop {
+        if let TxStockData::Symbols(keyword, symbols) = rx.recv_deadline(deadline).unwrap() {
+            break (keyword, symbols);
+        }
+    };
+    assert_eq!(keyword, "700");
+    assert_eq!(
+        symbols,
+        [Symbol {
+            code: "hk00700".into(),
+            name: "腾讯控股".into(),
+            market: Market::HkShare,
+            pinyin: String::new(),
+        }]
+    );
+
+    // names and url delimiters reach the source as typed
+    tx.send(StockCammnd::Search("腾讯".into())).unwrap();
+    tx.send(StockCammnd::Search("a&key=601".into())).unwrap();
+    let msgs = collect_until(&rx, |msgs| {
+        msgs.iter()
+            .filter(|x| matches!(x, TxStockData::Symbols(..)))
+            .count()
+            == 2
+    });
+    let answers: Vec<_> = msgs
+        .iter()
+        .filter_map(|x| match x {
+            TxStockData::Symbols(keyword, symbols) => Some((keyword.as_str(), symbols.len())),
+            _ => None,
+        })
+        .collect();
+    assert!(answers.contains(&("腾讯", 1)));
+    assert!(answers.contains(&("a&key=601", 0)));
+    assert!(mock
+        .requests()
+        .iter()
+        .any(|x| x.contains("key=a%26key%3D601")));
+
+    drop(tx);
+    handle.join().unwrap();
+}
+
+#[test]
+fn test_slow_search_keeps_refreshing() {
+    let mock = MockSina::start();
+    mock.stock("sh601127", "赛力斯", 10.0, &[10.0])
+        .stock("hk00700", "腾讯控股", 370.0, &[373.6]);
+    mock.set_code_latency("00700", Duration::from_secs(2));
+    let (tx, rx, handle) = spawn_task(&mock, "sh601127");
+    next_price(&rx, "sh601127");
+
+    tx.send(StockCammnd::Search("00700".into())).unwrap();
+    let msgs = collect_until(&rx, |msgs| stock_lists(msgs).len() >= 3);
+    assert!(!msgs.iter().any(|x| matches!(x, TxStockData::Symbols(..))));
+    collect_until(&rx, |msgs| {
+        msgs.iter()
+            .any(|x| matches!(x, TxStockData::Symbols(keyword, _) if keyword == "00700"))
+    });
+
+    drop(tx);
+    handle.join().unwrap();
+}
+
+#[test]
+fn test_closed_markets_slow_polling() {
+    let mock = MockSina::start();
+    mock.stock("sh601127", "赛力斯", 10.0, &[10.0, 10.5]).stock(
+        "hk00700",
+        "腾讯控股",
+        370.0,
+        &[373.6],
+    );
+    let (tx, rx) = unbounded();
+    let (tx2, rx2) = unbounded();
+    // saturday noon in beijing, hong kong is closed too
+    let mut task = StockTask::with_provider(
+        rx,
+        tx2,
+        "sh601127,hk00700".to_string(),
+        Arc::new(mock.provider()),
+    )
+    .with_clock(|| "2025-02-08T04:00:00Z".parse().unwrap());
+    let handle = thread::spawn(move || task.run());
+
+    // the closing prices once, then nothing until asked
+    assert_eq!(next_price(&rx2, "sh601127"), price("10.0"));
+    thread::sleep(Duration::from_millis(800));
+    assert_eq!(mock.quote_requests(), 1);
+
+    tx.send(StockCammnd::Refresh).unwrap();
+    assert_eq!(next_price(&rx2, "sh601127"), price("10.5"));
+    assert_eq!(mock.quote_requests(), 2);
+
+    drop(tx);
+    handle.join().unwrap();
+}
@@ -0,0 +1,356 @@
+// mock sina quote server for backend tests
+// each test crate only uses part of the mocks
+#![allow(dead_code)]
+
+pub mod ws;
+
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use wealth_tracker::model::stock::SinaProvider;
+
+const MOCK_KLINES: usize = 1000;
+const KLINE_PATH: &str = "/cn/api/json_v2.php/CN_MarketDataService.getKLineData";
+const MINLINE_PATH: &str = "/cn/api/openapi.php/CN_MinlineService.getMinlineData";
+const HK_KLINE_PATH: &str = "/hk/api/openapi.php/HK_StockService.getHKDayKLine";
+// days of hk history served
+const MOCK_HK_DAYS: u64 = 30;
+
+/// An empty directory of its own for the test `name`.
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("wt-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+#[derive(Default)]
+struct MockState {
+    latency: Duration,
+    // extra latency of requests naming a code
+    code_latency: HashMap<String, Duration>,
+    quote_error: Option<u16>,
+    kline_error: Option<u16>,
+    minline_error: Option<u16>,
+    // the row before the last kline can't be parsed
+    bad_kline_row: bool,
+    // code -> (name, closing, price path)
+    stocks: HashMap<String, (String, f32, Vec<f32>)>,
+    steps: HashMap<String, usize>,
+    requests: Vec<String>,
+}
+
+pub struct MockSina {
+    addr: SocketAddr,
+    state: Arc<Mutex<MockState>>,
+    stop: Arc<AtomicBool>,
+}
+
+impl MockSina {
+    pub fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let state = Arc::new(Mutex::new(MockState::default()));
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let server_state = state.clone();
+        let server_stop = stop.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                if server_stop.load(Ordering::SeqCst) {
+                    break;
+                }
+                if let Ok(stream) = stream {
+                    let state = server_state.clone();
+                    thread::spawn(move || handle(stream, state));
+                }
+            }
+        });
+
+        Self { addr, state, stop }
+    }
+
+    /// Provider pointing at this server.
+    pub fn provider(&self) -> SinaProvider {
+        SinaProvider::default()
+            .with_base_url(format!("http://{}", self.addr))
+            .with_kline_url(format!("http://{}{}", self.addr, KLINE_PATH))
+            .with_suggest_url(format!("http://{}/suggest/", self.addr))
+            .with_minline_url(format!("http://{}{}", self.addr, MINLINE_PATH))
+            .with_hk_kline_url(format!("http://{}{}", self.addr, HK_KLINE_PATH))
+    }
+
+    /// Every `list=` request advances `code` one step along `path`, the last
+    /// price is repeated once the path is exhausted.
+    pub fn stock(&self, code: &str, name: &str, closing: f32, path: &[f32]) -> &Self {
+        let mut state = self.state.lock().unwrap();
+        state
+            .stocks
+            .insert(code.to_string(), (name.to_string(), closing, path.to_vec()));
+        state.steps.remove(code);
+        self
+    }
+
+    pub fn set_latency(&self, latency: Duration) {
+        self.state.lock().unwrap().latency = latency;
+    }
+
+    /// Requests naming `code` answer `latency` later than the rest.
+    pub fn set_code_latency(&self, code: &str, latency: Duration) {
+        self.state
+            .lock()
+            .unwrap()
+            .code_latency
+            .insert(code.to_string(), latency);
+    }
+
+    pub fn set_quote_error(&self, status: Option<u16>) {
+        self.state.lock().unwrap().quote_error = status;
+    }
+
+    pub fn set_kline_error(&self, status: Option<u16>) {
+        self.state.lock().unwrap().kline_error = status;
+    }
+
+    pub fn set_minline_error(&self, status: Option<u16>) {
+        self.state.lock().unwrap().minline_error = status;
+    }
+
+    pub fn set_bad_kline_row(&self, bad: bool) {
+        self.state.lock().unwrap().bad_kline_row = bad;
+    }
+
+    /// Request paths seen so far.
+    pub fn requests(&self) -> Vec<String> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    pub fn quote_requests(&self) -> usize {
+        self.requests()
+            .iter()
+            .filter(|x| x.starts_with("/list="))
+            .count()
+    }
+}
+
+impl Drop for MockSina {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        // wake up the accept loop
+        let _ = TcpStream::connect(self.addr);
+    }
+}
+
+fn handle(mut stream: TcpStream, state: Arc<Mutex<MockState>>) {
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut request_line = String::new();
+    if reader.read_line(&mut request_line).is_err() {
+        return;
+    }
+    // skip headers
+    let mut line = String::new();
+    while reader.read_line(&mut line).is_ok() && line.trim() != "" {
+        line.clear();
+    }
+
+    let path = request_line
+        .split_whitespace()
+        .nth(1)
+        .unwrap_or_default()
+        .to_string();
+
+    let latency = {
+        let state = state.lock().unwrap();
+        state.latency
+            + state
+                .code_latency
+                .iter()
+                .filter(|(code, _)| path.contains(code.as_str()))
+                .map(|(_, x)| *x)
+                .sum::<Duration>()
+    };
+    thread::sleep(latency);
+
+    let (status, body) = route(&path, &mut state.lock().unwrap());
+    let response = format!(
+        "HTTP/1.1 {status} MOCK\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    let _ = stream.write_all(response.as_bytes());
+}
+
+fn route(path: &str, state: &mut MockState) -> (u16, String) {
+    state.requests.push(path.to_string());
+
+    if let Some(codes) = path.strip_prefix("/list=") {
+        if let Some(status) = state.quote_error {
+            return (status, "error".into());
+        }
+        let body = codes
+            .split(',')
+            .map(|code| quote_line(code, state))
+            .collect::<Vec<String>>()
+            .join("\n");
+        (200, body)
+    } else if path.starts_with("/suggest/?") {
+        let url = reqwest::Url::parse(&format!("http://mock{path}")).unwrap();
+        let key = url
+            .query_pairs()
+            .find(|(name, _)| name == "key")
+            .map(|(_, key)| key.into_owned())
+            .unwrap_or_default();
+        (200, suggest(&key, state))
+    } else if path.starts_with(KLINE_PATH) {
+        if let Some(status) = state.kline_error {
+            return (status, "error".into());
+        }
+        (200, klines_json(path, state))
+    } else if let Some(query) = path.strip_prefix(HK_KLINE_PATH) {
+        if let Some(status) = state.kline_error {
+            return (status, "error".into());
+        }
+        let symbol = query.strip_prefix("?symbol=").unwrap_or_default();
+        (200, hk_klines_json(&format!("hk{symbol}"), state))
+    } else if let Some(query) = path.strip_prefix(MINLINE_PATH) {
+        if let Some(status) = state.minline_error {
+            return (status, "error".into());
+        }
+        let code = query.strip_prefix("?symbol=").unwrap_or_default();
+        (200, minline_json(code, state))
+    } else {
+        (404, "not found".into())
+    }
+}
+
+// codes or names containing `key`, in the suggest format
+fn suggest(key: &str, state: &MockState) -> String {
+    let mut codes: Vec<&String> = state
+        .stocks
+        .iter()
+        .filter(|(code, (name, ..))| code.contains(key) || name.contains(key))
+        .map(|(code, _)| code)
+        .collect();
+    codes.sort();
+    let items: Vec<String> = codes
+        .into_iter()
+        .map(|code| {
+            let name = &state.stocks[code].0;
+            let (kind, bare) = match code.strip_prefix("hk") {
+                Some(bare) => ("31", bare),
+                None => ("11", &code[2..]),
+            };
+            format!("{key},{kind},{bare},{code},{name},,{name},99,1,,,")
+        })
+        .collect();
+    format!("var suggestdata=\"{}\";", items.join(";"))
+}
+
+fn current_price(code: &str, state: &MockState) -> Option<(String, f32, f32)> {
+    let (name, closing, path) = state.stocks.get(code)?;
+    let step = state.steps.get(code).copied().unwrap_or(0);
+    let price = path.get(step).or(path.last()).copied().unwrap_or(*closing);
+    Some((name.clone(), *closing, price))
+}
+
+fn quote_line(code: &str, state: &mut MockState) -> String {
+    let Some((name, closing, price)) = current_price(code, state) else {
+        return format!("var hq_str_{code}=\"\";");
+    };
+    *state.steps.entry(code.to_string()).or_default() += 1;
+
+    if code.starts_with("hk") {
+        return format!(
+            "var hq_str_{code}=\"{name},{name},{closing:.3},{closing:.3},{price:.3},{price:.3},{price:.3},0.000,0.000,{price:.3},{price:.3},{:.3},1000,0.000,0.000,{price:.3},{price:.3},2025/01/27,10:00\";",
+            price * 1000.0
+        );
+    }
+
+    let book = (1..=5)
+        .map(|i| format!("{},{:.3}", i * 100, price - 0.01 * i as f32))
+        .chain((1..=5).map(|i| format!("{},{:.3}", i * 100, price + 0.01 * i as f32)))
+        .collect::<Vec<String>>()
+        .join(",");
+
+    format!(
+        "var hq_str_{code}=\"{name},{closing:.3},{closing:.3},{price:.3},{price:.3},{price:.3},{price:.3},{price:.3},1000,{:.3},{book},2025-01-27,10:00:00,00,\";",
+        price * 1000.0
+    )
+}
+
+// the first hour of the day at the current price
+fn minline_json(code: &str, state: &MockState) -> String {
+    let price = current_price(code, state).map(|x| x.2).unwrap_or(1.0);
+    let items = (0..=60)
+        .map(|i| {
+            format!(
+                "{{\"m\":\"{:02}:{:02}:00\",\"v\":\"100\",\"p\":\"{price:.3}\",\"avg_p\":\"{price:.3}\"}}",
+                9 + (30 + i) / 60,
+                (30 + i) % 60
+            )
+        })
+        .collect::<Vec<String>>()
+        .join(",");
+    format!("{{\"result\":{{\"status\":{{\"code\":0}},\"data\":[{items}]}}}}")
+}
+
+// daily candles up to 2025-01-27 at the current price
+fn hk_klines_json(code: &str, state: &MockState) -> String {
+    let price = current_price(code, state).map(|x| x.2).unwrap_or(1.0);
+    let last = chrono::NaiveDate::from_ymd_opt(2025, 1, 27).unwrap();
+    let items = (0..MOCK_HK_DAYS)
+        .rev()
+        .map(|i| {
+            let day = last - chrono::Days::new(i);
+            format!(
+                "{{\"date\":\"{day}\",\"open\":\"{price:.3}\",\"high\":\"{price:.3}\",\"low\":\"{price:.3}\",\"close\":\"{price:.3}\",\"volume\":\"100\"}}"
+            )
+        })
+        .collect::<Vec<String>>()
+        .join(",");
+    format!("{{\"result\":{{\"status\":{{\"code\":0}},\"data\":[{items}]}}}}")
+}
+
+fn klines_json(path: &str, state: &MockState) -> String {
+    let query: HashMap<&str, &str> = path
+        .split_once('?')
+        .map(|(_, q)| q)
+        .unwrap_or_default()
+        .split('&')
+        .filter_map(|kv| kv.split_once('='))
+        .collect();
+
+    let code = query.get("symbol").copied().unwrap_or_default();
+    let datalen = query
+        .get("datalen")
+        .and_then(|x| x.parse::<usize>().ok())
+        .unwrap_or(0);
+    let price = current_price(code, state).map(|x| x.2).unwrap_or(1.0);
+
+    // the latest `datalen` of a fixed minute series from 2025-01-27 09:00
+    let items = (MOCK_KLINES - datalen.min(MOCK_KLINES)..MOCK_KLINES)
+        .map(|i| {
+            let minute = 9 * 60 + i;
+            let close = match state.bad_kline_row && i == MOCK_KLINES - 2 {
+                true => "n/a".to_string(),
+                false => format!("{price:.3}"),
+            };
+            format!(
+                "{{\"day\":\"2025-01-{:02} {:02}:{:02}:00\",\"open\":\"{price:.3}\",\"high\":\"{price:.3}\",\"low\":\"{price:.3}\",\"close\":\"{close}\",\"volume\":\"100\"}}",
+                27 + minute / 1440,
+                minute % 1440 / 60,
+                minute % 60
+            )
+        })
+        .collect::<Vec<String>>()
+        .join(",");
+    format!("[{items}]")
+}
@@ -0,0 +1,207 @@
+// local websocket stand-in for crypto stream tests
+
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use tungstenite::handshake::derive_accept_key;
+
+#[derive(Default)]
+struct WsState {
+    // messages waiting for the connected client
+    outbox: Vec<String>,
+    subscriptions: Vec<String>,
+    connections: usize,
+    pings: usize,
+    ignore_pings: bool,
+    // bumped to drop every open connection
+    generation: usize,
+}
+
+pub struct MockWs {
+    addr: SocketAddr,
+    state: Arc<Mutex<WsState>>,
+    stop: Arc<AtomicBool>,
+}
+
+impl MockWs {
+    pub fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let state = Arc::new(Mutex::new(WsState::default()));
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let server_state = state.clone();
+        let server_stop = stop.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                if server_stop.load(Ordering::SeqCst) {
+                    break;
+                }
+                if let Ok(stream) = stream {
+                    let state = server_state.clone();
+                    let stop = server_stop.clone();
+                    thread::spawn(move || serve(stream, state, stop));
+                }
+            }
+        });
+
+        Self { addr, state, stop }
+    }
+
+    pub fn url(&self) -> String {
+        format!("ws://{}/ws", self.addr)
+    }
+
+    /// Sends `text` to the connected client.
+    pub fn push(&self, text: &str) {
+        self.state.lock().unwrap().outbox.push(text.to_string());
+    }
+
+    /// Drops the open connections, new ones are still accepted.
+    pub fn disconnect(&self) {
+        self.state.lock().unwrap().generation += 1;
+    }
+
+    /// Stop answering pings, as if the connection went silent.
+    pub fn set_ignore_pings(&self, ignore: bool) {
+        self.state.lock().unwrap().ignore_pings = ignore;
+    }
+
+    /// Text frames received from clients.
+    pub fn subscriptions(&self) -> Vec<String> {
+        self.state.lock().unwrap().subscriptions.clone()
+    }
+
+    pub fn connections(&self) -> usize {
+        self.state.lock().unwrap().connections
+    }
+
+    pub fn pings(&self) -> usize {
+        self.state.lock().unwrap().pings
+    }
+}
+
+impl Drop for MockWs {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        let _ = TcpStream::connect(self.addr);
+    }
+}
+
+fn serve(mut stream: TcpStream, state: Arc<Mutex<WsState>>, stop: Arc<AtomicBool>) {
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut key = None;
+    let mut line = String::new();
+    while reader.read_line(&mut line).is_ok() && line.trim() != "" {
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("sec-websocket-key") {
+                key = Some(value.trim().to_string());
+            }
+        }
+        line.clear();
+    }
+    let Some(key) = key else {
+        return;
+    };
+    let response = format!(
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+        derive_accept_key(key.as_bytes())
+    );
+    if stream.write_all(response.as_bytes()).is_err() {
+        return;
+    }
+
+    let generation = {
+        let mut state = state.lock().unwrap();
+        state.connections += 1;
+        state.generation
+    };
+    stream
+        .set_read_timeout(Some(Duration::from_millis(10)))
+        .unwrap();
+
+    loop {
+        if stop.load(Ordering::SeqCst) || state.lock().unwrap().generation != generation {
+            let _ = stream.shutdown(std::net::Shutdown::Both);
+            return;
+        }
+
+        let outbox = std::mem::take(&mut state.lock().unwrap().outbox);
+        for text in outbox {
+            if write_frame(&mut stream, 0x1, text.as_bytes()).is_err() {
+                return;
+            }
+        }
+
+        let mut peek = [0u8; 1];
+        match stream.peek(&mut peek) {
+            Ok(0) => return,
+            Ok(_) => {}
+            Err(_) => continue,
+        }
+        let Some((opcode, payload)) = read_frame(&mut stream) else {
+            return;
+        };
+        let mut state = state.lock().unwrap();
+        match opcode {
+            0x1 => state
+                .subscriptions
+                .push(String::from_utf8_lossy(&payload).to_string()),
+            0x9 => {
+                state.pings += 1;
+                if !state.ignore_pings && write_frame(&mut stream, 0xA, &payload).is_err() {
+                    return;
+                }
+            }
+            0x8 => return,
+            _ => {}
+        }
+    }
+}
+
+// client frames are small and masked
+fn read_frame(stream: &mut TcpStream) -> Option<(u8, Vec<u8>)> {
+    stream.set_read_timeout(Some(Duration::from_secs(1))).ok()?;
+    let mut head = [0u8; 2];
+    stream.read_exact(&mut head).ok()?;
+    let len = match head[1] & 0x7F {
+        126 => {
+            let mut buf = [0u8; 2];
+            stream.read_exact(&mut buf).ok()?;
+            u16::from_be_bytes(buf) as usize
+        }
+        n => n as usize,
+    };
+    let mut mask = [0u8; 4];
+    stream.read_exact(&mut mask).ok()?;
+    let mut payload = vec![0u8; len];
+    stream.read_exact(&mut payload).ok()?;
+    payload
+        .iter_mut()
+        .enumerate()
+        .for_each(|(i, b)| *b ^= mask[i % 4]);
+    stream
+        .set_read_timeout(Some(Duration::from_millis(10)))
+        .ok()?;
+    Some((head[0] & 0x0F, payload))
+}
+
+fn write_frame(stream: &mut TcpStream, opcode: u8, payload: &[u8]) -> std::io::Result<()> {
+    let mut frame = vec![0x80 | opcode];
+    if payload.len() < 126 {
+        frame.push(payload.len() as u8);
+    } else {
+        frame.push(126);
+        frame.extend((payload.len() as u16).to_be_bytes());
+    }
+    frame.extend(payload);
+    stream.write_all(&frame)
+}
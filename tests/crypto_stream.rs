@@ -0,0 +1,143 @@
+mod common;
+
+use std::{
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use common::ws::MockWs;
+use crossbeam::channel::{unbounded, Receiver};
+use wealth_tracker::{
+    backend::{Endpoint, TickerStream, TxCryptoData},
+    model::{
+        crypto::{BinanceProvider, Coin},
+        Price,
+    },
+};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+fn spawn_stream(mock: &MockWs, heartbeat: Duration) -> Receiver<TxCryptoData> {
+    let (tx, rx) = unbounded();
+    let provider = BinanceProvider::default().with_stream_url(mock.url());
+    let mut stream = TickerStream::new(
+        mock.url(),
+        vec!["BTCUSDT".to_string(), "ETHUSDT".to_string()],
+        Arc::new(provider),
+        tx,
+    )
+    .with_heartbeat(heartbeat);
+    // exits on the first send after the receiver is dropped
+    thread::spawn(move || stream.run());
+    rx
+}
+
+fn price(x: &str) -> Price {
+    x.parse().unwrap()
+}
+
+fn ticker_event(symbol: &str, price: &str) -> String {
+    format!(
+        "{{\"e\":\"24hrTicker\",\"E\":1737936001234,\"s\":\"{symbol}\",\"P\":\"1.000\",\"c\":\"{price}\",\"h\":\"{price}\",\"l\":\"{price}\",\"v\":\"10.0\"}}"
+    )
+}
+
+fn wait_for(what: &str, cond: impl Fn() -> bool) {
+    let deadline = Instant::now() + TIMEOUT;
+    while !cond() {
+        assert!(Instant::now() < deadline, "timed out waiting for {what}");
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+/// Next pushed coin, other messages are skipped.
+fn next_coin(rx: &Receiver<TxCryptoData>) -> Coin {
+    let deadline = Instant::now() + TIMEOUT;
+    loop {
+        if let TxCryptoData::CoinList(mut coins) = rx.recv_deadline(deadline).unwrap() {
+            return coins.remove(0);
+        }
+    }
+}
+
+/// Messages until an error of the stream endpoint, inclusive.
+fn until_stream_error(rx: &Receiver<TxCryptoData>) -> String {
+    let deadline = Instant::now() + TIMEOUT;
+    loop {
+        if let TxCryptoData::Error(e) = rx.recv_deadline(deadline).unwrap() {
+            assert_eq!(e.endpoint, Endpoint::Stream);
+            return e.message;
+        }
+    }
+}
+
+#[test]
+fn test_stream_subscribes_and_forwards_tickers() {
+    let mock = MockWs::start();
+    let rx = spawn_stream(&mock, Duration::from_secs(20));
+
+    wait_for("subscription", || mock.subscriptions().len() == 1);
+    let sub: serde_json::Value = serde_json::from_str(&mock.subscriptions()[0]).unwrap();
+    assert_eq!(
+        sub["params"],
+        serde_json::json!(["btcusdt@ticker", "ethusdt@ticker"])
+    );
+
+    // the subscription ack is not a ticker
+    mock.push("{\"result\":null,\"id\":1}");
+    mock.push(&ticker_event("BTCUSDT", "97000.5"));
+    mock.push(&ticker_event("ETHUSDT", "3200.25"));
+
+    let btc = next_coin(&rx);
+    assert_eq!(btc.symbol, "BTCUSDT");
+    assert_eq!(btc.price, price("97000.5"));
+    assert_eq!(next_coin(&rx).price, price("3200.25"));
+    assert_eq!(mock.connections(), 1);
+}
+
+#[test]
+fn test_stream_reconnects_and_resubscribes() {
+    let mock = MockWs::start();
+    let rx = spawn_stream(&mock, Duration::from_secs(20));
+
+    wait_for("subscription", || mock.subscriptions().len() == 1);
+    mock.disconnect();
+    until_stream_error(&rx);
+
+    wait_for("resubscription", || mock.subscriptions().len() == 2);
+    assert_eq!(mock.connections(), 2);
+    assert_eq!(mock.subscriptions()[0], mock.subscriptions()[1]);
+
+    mock.push(&ticker_event("BTCUSDT", "98000"));
+    assert_eq!(next_coin(&rx).price, price("98000"));
+}
+
+#[test]
+fn test_stream_heartbeat() {
+    let mock = MockWs::start();
+    let rx = spawn_stream(&mock, Duration::from_millis(200));
+
+    // a quiet but healthy connection is pinged and kept
+    wait_for("ping", || mock.pings() >= 2);
+    assert_eq!(mock.connections(), 1);
+    let pong = rx
+        .try_iter()
+        .filter_map(|x| match x {
+            TxCryptoData::Status(s) if s.endpoint == Endpoint::Stream => Some(s),
+            _ => None,
+        })
+        .last()
+        .unwrap();
+    assert!(pong.ok);
+
+    // no pong, the stream gives up on the connection and dials again
+    mock.set_ignore_pings(true);
+    assert_eq!(
+        until_stream_error(&rx),
+        "websocket error: heartbeat timeout"
+    );
+    mock.set_ignore_pings(false);
+    wait_for("reconnect", || mock.connections() == 2);
+    wait_for("resubscription", || mock.subscriptions().len() == 2);
+}